@@ -1,4 +1,4 @@
-use fortunes_algorithm::DelauneyVertex;
+use fortunes_algorithm::{DelauneyVertex, SweepEvent, VoronoiSweep};
 use piston_window::*;
 use rand::Rng;
 
@@ -10,6 +10,7 @@ const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
 const GREEN: [f32; 4] = [0.0, 1.0, 0.0, 1.0];
 const BLUE: [f32; 4] = [0.3, 0.3, 1.0, 1.0];
 const YELLOW: [f32; 4] = [1.0, 1.0, 0.0, 1.0];
+const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
 
 const POINT_SIZE: f64 = 2.0;
 const LINE_WIDTH: f64 = 0.5;
@@ -22,6 +23,10 @@ const DRAW_VORONOI_VERTICES: bool = true;
 
 const NUM_POINTS: usize = 5_000;
 
+const ANIMATE_SWEEP: bool = true;
+const SWEEP_EVENTS_PER_UPDATE: usize = 20;
+const BEACHLINE_SAMPLES: usize = 500;
+
 pub struct BasicDelauneyVertex {
     pub position: cgmath::Point2<f64>,
     pub is_edge: bool,
@@ -103,6 +108,76 @@ fn draw<G: Graphics>(
     }
 }
 
+/// Returns the y coordinate of the parabola for a site at x with the sweep line at sweep_y.
+fn parabola_y(site: &cgmath::Point2<f64>, x: f64, sweep_y: f64) -> f64 {
+    ((x - site.x).powi(2) + site.y.powi(2) - sweep_y.powi(2)) / (2.0 * (site.y - sweep_y))
+}
+
+fn draw_sweep<G: Graphics>(sweep: &VoronoiSweep, c: Context, g: &mut G) {
+    let sweep_y = sweep.sweep_y();
+    let voronoi = sweep.diagram();
+    draw(
+        &voronoi.get_vertex_points(),
+        &voronoi.get_edge_endpoints(),
+        DRAW_VORONOI_VERTICES,
+        DRAW_VORONOI_EDGES,
+        BLUE,
+        GREEN,
+        c,
+        g,
+    );
+
+    // The sweep line
+    let pen = Line::new(WHITE, LINE_WIDTH);
+    draw_edge(
+        &cgmath::Point2::new(0.0, sweep_y),
+        &cgmath::Point2::new(1.0, sweep_y),
+        pen,
+        c,
+        g,
+    );
+
+    // The beachline, sampled across the diagram for each arc between its breakpoints
+    let pen = Line::new(YELLOW, LINE_WIDTH);
+    for arc in sweep.beachline() {
+        if (arc.site.y - sweep_y).abs() < f64::EPSILON {
+            continue;
+        }
+        let left = arc.left.max(0.0);
+        let right = arc.right.min(1.0);
+        if left >= right {
+            continue;
+        }
+        let samples = ((right - left) * BEACHLINE_SAMPLES as f64).ceil() as usize;
+        let step = (right - left) / samples as f64;
+        for i in 0..samples {
+            let x0 = left + step * i as f64;
+            let x1 = x0 + step;
+            draw_edge(
+                &cgmath::Point2::new(x0, parabola_y(&arc.site, x0, sweep_y)),
+                &cgmath::Point2::new(x1, parabola_y(&arc.site, x1, sweep_y)),
+                pen,
+                c,
+                g,
+            );
+        }
+    }
+
+    // Sites still to be reached by the sweep line and the centers of pending circle events
+    for event in sweep.pending_events() {
+        match event {
+            SweepEvent::Site { face, .. } => {
+                draw_point(&voronoi.get_face_point(face), Rectangle::new(RED), c, g)
+            }
+            SweepEvent::Circle { center, .. } => {
+                if in_diagram(&center) {
+                    draw_point(&center, Rectangle::new(WHITE), c, g)
+                }
+            }
+        }
+    }
+}
+
 fn main() {
     let mut points: Vec<cgmath::Point2<f64>> = vec![];
     let mut rng = rand::thread_rng();
@@ -140,10 +215,36 @@ fn main() {
             .build()
             .unwrap_or_else(|e| panic!("Failed to build PistonWindow: {}", e));
 
-    window.set_lazy(true);
+    let mut sweep = if ANIMATE_SWEEP {
+        Some(VoronoiSweep::new(&points))
+    } else {
+        None
+    };
+
+    window.set_lazy(!ANIMATE_SWEEP);
     while let Some(e) = window.next() {
+        if e.update_args().is_some() {
+            if let Some(current) = sweep.as_mut() {
+                let mut finished = false;
+                for _ in 0..SWEEP_EVENTS_PER_UPDATE {
+                    if !current.step() {
+                        finished = true;
+                        break;
+                    }
+                }
+                if finished {
+                    sweep = None;
+                    window.set_lazy(true);
+                }
+            }
+        }
+
         window.draw_2d(&e, |c, g, _device| {
             clear(BLACK, g);
+            if let Some(current) = sweep.as_ref() {
+                draw_sweep(current, c, g);
+                return;
+            }
             draw(
                 &delauney_vertices,
                 &delauney_edges,
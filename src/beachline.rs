@@ -1,8 +1,8 @@
 use super::*;
+use crate::priority_queue::QueueIndex;
+use crate::tree::{NodeKey, Tree};
 use crate::vector2::get_orthogonal;
-use binary_search_tree::{NodeKey, Tree};
 use cgmath::EuclideanSpace;
-use priority_queue::QueueIndex;
 use std::f64;
 
 #[derive(Debug, Clone)]
@@ -26,13 +26,25 @@ impl Arc {
     }
 }
 
+#[derive(Default)]
 pub struct Beachline {
     pub tree: Tree<Arc>,
+    // The edges between sites on the same line as the first site, which run up from the beachline
+    // with no vertex at their upper end
+    top_edges: Vec<(HalfEdgeKey, HalfEdgeKey)>,
 }
 
 impl Beachline {
     pub fn new() -> Self {
-        Beachline { tree: Tree::new() }
+        Beachline {
+            tree: Tree::new(),
+            top_edges: vec![],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.tree.clear();
+        self.top_edges.clear();
     }
 
     pub fn create_root(&mut self, face: FaceKey) -> NodeKey {
@@ -52,12 +64,19 @@ impl Beachline {
             let face = self.get_arc_face(current_arc).unwrap();
             let current_arc_focus = voronoi.get_face_point(face);
             if (current_arc_focus.y - y).abs() < f64::EPSILON {
-                if point.x < current_arc_focus.x {
-                    current_arc = self.tree.get_left(current_arc).unwrap();
+                // While every site swept so far is on the line there are only vertical edges
+                // between their arcs, so the arc beside the point is returned if there are none
+                // further across
+                let child = if point.x < current_arc_focus.x {
+                    self.tree.get_left(current_arc)
                 } else if point.x > current_arc_focus.x {
-                    current_arc = self.tree.get_right(current_arc).unwrap();
+                    self.tree.get_right(current_arc)
                 } else {
                     panic!("Two sites located at the same point");
+                };
+                match child {
+                    Some(child) => current_arc = child,
+                    None => found = true,
                 }
             } else {
                 let prev = self.tree.get_prev(current_arc);
@@ -112,9 +131,47 @@ impl Beachline {
         self.set_right_half_edge(right_arc, right_half_edge);
     }
 
+    /// Adds an arc for a face between an arc and the next one, for a site directly below the
+    /// breakpoint between them.
+    pub fn insert_arc_after(&mut self, node: NodeKey, face: FaceKey) -> NodeKey {
+        self.tree.insert_after(node, Arc::new(face))
+    }
+
+    /// Adds an arc for a face beside an existing arc, before it if `before` is true and after it
+    /// otherwise. Both arcs must be for sites on the same line as the first site.
+    ///
+    /// The edge between them is added to the diagram and runs vertically up from the beachline.
+    pub fn insert_arc_beside(
+        &mut self,
+        node: NodeKey,
+        face: FaceKey,
+        before: bool,
+        voronoi: &mut Diagram,
+    ) -> NodeKey {
+        let new_node = if before {
+            self.tree.insert_before(node, Arc::new(face))
+        } else {
+            self.tree.insert_after(node, Arc::new(face))
+        };
+        let (left_node, right_node) = if before {
+            (new_node, node)
+        } else {
+            (node, new_node)
+        };
+
+        let (half_edge_1, half_edge_2) = voronoi.add_edge(
+            self.get_arc_face(left_node).unwrap(),
+            self.get_arc_face(right_node).unwrap(),
+        );
+        self.set_right_half_edge(left_node, Some(half_edge_1));
+        self.set_left_half_edge(right_node, Some(half_edge_2));
+        self.top_edges.push((half_edge_1, half_edge_2));
+        new_node
+    }
+
     pub fn complete_edges(&self, bbox: &BoundingBox, voronoi: &mut Diagram) {
         let mut departing_edges = vec![];
-        let mut arriving_edges = vec![];
+        let mut arriving_edges = HashMap::new();
         if self.tree.has_root() {
             let mut left_node = self.tree.get_leftmost_node();
             let mut right_node = self.tree.get_next(left_node.unwrap());
@@ -138,28 +195,60 @@ impl Beachline {
 
                 // Store the vertex on the boundary
                 departing_edges.push((departing_edge, intersection.1));
-                arriving_edges.push((arriving_edge, intersection.1));
+                arriving_edges.insert(arriving_edge, intersection.1);
 
                 left_node = right_node;
                 right_node = self.tree.get_next(left_node.unwrap());
             }
+            // Complete the upper ends of the edges between sites on the same line as the first
+            for &(half_edge_1, half_edge_2) in &self.top_edges {
+                let left_point = voronoi
+                    .get_face_point(voronoi.get_half_edge_incident_face(half_edge_1).unwrap());
+                let right_point = voronoi
+                    .get_face_point(voronoi.get_half_edge_incident_face(half_edge_2).unwrap());
+
+                // The opposite direction to the breakpoints, so the intersection is above them
+                let direction = get_orthogonal(right_point - left_point);
+                let origin = (left_point + right_point.to_vec()) * 0.5;
+                let intersection = bbox.get_intersection(&origin, &direction);
+
+                let vertex = voronoi.add_vertex(intersection.0);
+                voronoi.set_half_edge_destination(half_edge_1, Some(vertex));
+                voronoi.set_half_edge_origin(half_edge_2, Some(vertex));
+                departing_edges.push((half_edge_1, intersection.1));
+                arriving_edges.insert(half_edge_2, intersection.1);
+            }
+
+            // Each departing edge continues around the border to the start of its chain of edges.
+            // The region of a face that crosses the whole diagram has two chains, and each
+            // continues to the start of the other instead.
+            let mut links = vec![];
+            let mut unlinked_faces = HashMap::new();
             for (departing_edge, departing_side) in departing_edges {
-                // Find the corresponding arriving edge
                 let mut current_edge = departing_edge;
                 while voronoi.get_half_edge_prev(current_edge).is_some() {
                     current_edge = voronoi.get_half_edge_prev(current_edge).unwrap();
                 }
-                let &(arriving_edge, arriving_side) = arriving_edges
-                    .iter()
-                    .find(|&&(edge, _)| edge == current_edge)
-                    .unwrap();
-
-                // The arriving and departing vertices should have the same incident face
-                debug_assert_eq!(
-                    voronoi.get_half_edge_incident_face(departing_edge),
-                    voronoi.get_half_edge_incident_face(arriving_edge)
-                );
+                let chain_start = current_edge;
+
+                let face = voronoi.get_half_edge_incident_face(departing_edge).unwrap();
+                match unlinked_faces.remove(&face) {
+                    Some(other_link) => {
+                        let (other_departing_edge, other_departing_side, other_chain_start) =
+                            links[other_link];
+                        links[other_link] =
+                            (other_departing_edge, other_departing_side, chain_start);
+                        links.push((departing_edge, departing_side, other_chain_start));
+                    }
+                    None => {
+                        unlinked_faces.insert(face, links.len());
+                        links.push((departing_edge, departing_side, chain_start));
+                    }
+                }
+            }
 
+            for (departing_edge, departing_side, arriving_edge) in links {
+                let arriving_side = arriving_edges[&arriving_edge];
                 bbox.link_vertices(
                     voronoi,
                     departing_edge,
@@ -203,11 +292,11 @@ impl Beachline {
 
     pub fn get_arc_event(&self, node: NodeKey) -> QueueIndex {
         let arc = self.tree.get_contents(node);
-        arc.event_index.clone()
+        arc.event_index
     }
 }
 
-fn compute_breakpoint(point1: cgmath::Point2<f64>, point2: cgmath::Point2<f64>, y: f64) -> f64 {
+pub fn compute_breakpoint(point1: cgmath::Point2<f64>, point2: cgmath::Point2<f64>, y: f64) -> f64 {
     let d1 = 1.0 / (2.0 * (point1.y - y));
     let d2 = 1.0 / (2.0 * (point2.y - y));
     let a = d1 - d2;
@@ -215,7 +304,10 @@ fn compute_breakpoint(point1: cgmath::Point2<f64>, point2: cgmath::Point2<f64>,
     let b = 2.0 * (point2.x * d2 - point1.x * d1);
     let c = (point1.y.powi(2) + point1.x.powi(2) - y.powi(2)) * d1
         - (point2.y.powi(2) + point2.x.powi(2) - y.powi(2)) * d2;
-    if a == 0.0 {
+    if (point1.y - y).abs() < f64::EPSILON && (point2.y - y).abs() < f64::EPSILON {
+        // Special case where both points are on the beachline y, the edge between them is vertical
+        (point1.x + point2.x) * 0.5
+    } else if a == 0.0 {
         // Special case where we have a linear equation
         -c / b
     } else if (point1.y - y).abs() < f64::EPSILON {
@@ -223,7 +315,7 @@ fn compute_breakpoint(point1: cgmath::Point2<f64>, point2: cgmath::Point2<f64>,
         point1.x
     } else if (point2.y - y).abs() < f64::EPSILON {
         // Special case where point 2 is on the beachline y
-        point2.y
+        point2.x
     } else {
         let delta = b.powi(2) - 4.0 * a * c;
         (-b - f64::sqrt(delta)) / (2.0 * a)
@@ -261,7 +353,7 @@ mod tests {
                 cgmath::Point2::new(0.5, 0.2),
                 cgmath::Point2::new(0.6, 0.5),
                 0.5
-            ) - 0.5)
+            ) - 0.6)
                 .abs()
                 < f64::EPSILON
         );
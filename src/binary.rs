@@ -0,0 +1,570 @@
+use super::*;
+use slotmap::SecondaryMap;
+use std::io::{self, Read, Write};
+
+/// The bytes every diagram written by `write_binary` starts with.
+const MAGIC: [u8; 4] = *b"VDCL";
+
+/// The version of the format written. This only changes when older readers could not read the
+/// diagram at all, additions that can be skipped go at the end of the header or after the body.
+const FORMAT_VERSION: u16 = 1;
+
+/// The size of the part of the header after its size, which older readers skip over if it grows.
+const HEADER_SIZE: u16 = 16;
+
+/// The coordinates are stored as single precision floats.
+const FLAG_F32: u32 = 1;
+
+/// Writes a diagram in a compact binary format that `read_binary` rebuilds it from.
+///
+/// Faces, vertices and half edges are written as arrays and refer to each other by their positions
+/// in them. The vertices and half edges are ordered by walking around each face in turn, so most
+/// references are close to the last of their kind and are written as small differences. The
+/// coordinates are written as the differences between the bits of successive floats, which saves
+/// a little when neighbouring points are close.
+/// # Arguments
+/// * `voronoi` - the diagram to write.
+/// * `writer` - where to write the diagram.
+pub fn write_binary<W: Write>(voronoi: &Diagram, writer: W) -> io::Result<()> {
+    write_diagram(voronoi, false, writer)
+}
+
+/// Writes a diagram in the binary format of `write_binary`, with the coordinates rounded to single
+/// precision floats.
+///
+/// This roughly halves the size of the coordinates. The topology is kept exactly, but the points
+/// move by up to about 6e-8 within the unit square.
+/// # Arguments
+/// * `voronoi` - the diagram to write.
+/// * `writer` - where to write the diagram.
+pub fn write_binary_f32<W: Write>(voronoi: &Diagram, writer: W) -> io::Result<()> {
+    write_diagram(voronoi, true, writer)
+}
+
+/// Reads a diagram written by `write_binary` or `write_binary_f32`.
+///
+/// The faces are added in the order they were written, so their positions in `get_face_indices`
+/// match those in the original diagram although the keys differ. The diagram is checked as it is
+/// rebuilt, failing if references are out of range or the half edges are not linked consistently.
+/// Anything after the diagram is left unread.
+/// # Arguments
+/// * `reader` - where to read the diagram from.
+pub fn read_binary<R: Read>(reader: R) -> io::Result<Diagram> {
+    let mut decoder = Decoder { reader };
+    let mut magic = [0; 4];
+    decoder.reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(invalid_binary("Not a diagram"));
+    }
+    let version = decoder.read_u16()?;
+    if version != FORMAT_VERSION {
+        return Err(invalid_binary(&format!("Unsupported version {}", version)));
+    }
+    let header_size = decoder.read_u16()?;
+    if header_size < HEADER_SIZE {
+        return Err(invalid_binary("Header too small"));
+    }
+    let flags = decoder.read_u32()?;
+    if flags & !FLAG_F32 != 0 {
+        return Err(invalid_binary(&format!("Unsupported flags {:#x}", flags)));
+    }
+    let face_count = decoder.read_u32()? as usize;
+    let vertex_count = decoder.read_u32()? as usize;
+    let half_edge_count = decoder.read_u32()? as usize;
+    io::copy(
+        &mut decoder
+            .reader
+            .by_ref()
+            .take(u64::from(header_size - HEADER_SIZE)),
+        &mut io::sink(),
+    )?;
+    let is_f32 = flags & FLAG_F32 != 0;
+
+    // Everything is read before building the diagram as the counts can not be trusted to size it
+    let mut face_records = vec![];
+    let mut previous = (0, 0);
+    let mut last_half_edge = 0;
+    for _ in 0..face_count {
+        let point = decoder.read_point(&mut previous, is_f32)?;
+        let outer_component =
+            decoder.read_running_reference(&mut last_half_edge, half_edge_count)?;
+        face_records.push((point, outer_component));
+    }
+    let mut vertex_points = vec![];
+    let mut previous = (0, 0);
+    for _ in 0..vertex_count {
+        vertex_points.push(decoder.read_point(&mut previous, is_f32)?);
+    }
+    let mut half_edge_records = vec![];
+    let (mut last_vertex, mut last_face) = (0, 0);
+    for i in 0..half_edge_count {
+        half_edge_records.push(HalfEdgeRecord {
+            origin: decoder.read_running_reference(&mut last_vertex, vertex_count)?,
+            destination: decoder.read_running_reference(&mut last_vertex, vertex_count)?,
+            incident_face: decoder
+                .read_running_reference(&mut last_face, face_count)?
+                .ok_or_else(|| invalid_binary("Half edge without a face"))?,
+            twin: decoder.read_reference(i, half_edge_count)?,
+            next: decoder.read_reference(i, half_edge_count)?,
+        });
+    }
+
+    let mut voronoi = Diagram::new();
+    for &(point, _) in &face_records {
+        voronoi.add_face(point);
+    }
+    let faces = voronoi.get_face_indices();
+    let vertices: Vec<VertexKey> = vertex_points
+        .into_iter()
+        .map(|point| voronoi.add_vertex(point))
+        .collect();
+    let half_edges: Vec<HalfEdgeKey> = half_edge_records
+        .iter()
+        .map(|record| voronoi.add_half_edge(faces[record.incident_face]))
+        .collect();
+    for (&half_edge, record) in half_edges.iter().zip(&half_edge_records) {
+        voronoi.set_half_edge_origin(half_edge, record.origin.map(|i| vertices[i]));
+        voronoi.set_half_edge_destination(half_edge, record.destination.map(|i| vertices[i]));
+        voronoi.set_half_edge_twin(half_edge, record.twin.map(|i| half_edges[i]));
+        if let Some(next) = record.next {
+            if voronoi.get_half_edge_prev(half_edges[next]).is_some() {
+                return Err(invalid_binary("Half edge follows two others"));
+            }
+            voronoi.link_half_edges(half_edge, half_edges[next]);
+        }
+    }
+    for (&face, &(_, outer_component)) in faces.iter().zip(&face_records) {
+        voronoi.set_face_outer_component(face, outer_component.map(|i| half_edges[i]));
+    }
+    voronoi.validate().map_err(|error| invalid_binary(&error))?;
+    Ok(voronoi)
+}
+
+/// The references of a half edge as positions in the arrays of the format.
+struct HalfEdgeRecord {
+    origin: Option<usize>,
+    destination: Option<usize>,
+    incident_face: usize,
+    twin: Option<usize>,
+    next: Option<usize>,
+}
+
+fn write_diagram<W: Write>(voronoi: &Diagram, is_f32: bool, writer: W) -> io::Result<()> {
+    // Number the half edges and vertices in the order they are met walking around each face, then
+    // any that were not met
+    let faces = voronoi.get_face_indices();
+    let mut face_indices = SecondaryMap::new();
+    let mut half_edges = vec![];
+    let mut half_edge_indices = SecondaryMap::new();
+    let mut vertices = vec![];
+    let mut vertex_indices = SecondaryMap::new();
+    let mut number_vertex = |vertex: VertexKey| {
+        if !vertex_indices.contains_key(vertex) {
+            vertex_indices.insert(vertex, vertices.len());
+            vertices.push(vertex);
+        }
+    };
+    for (i, &face) in faces.iter().enumerate() {
+        face_indices.insert(face, i);
+        if voronoi.get_face_outer_component(face).is_some() {
+            for half_edge in voronoi.outer_edge_iter(face) {
+                if half_edge_indices.contains_key(half_edge) {
+                    break;
+                }
+                half_edge_indices.insert(half_edge, half_edges.len());
+                half_edges.push(half_edge);
+                if let Some(origin) = voronoi.get_half_edge_origin(half_edge) {
+                    number_vertex(origin);
+                }
+            }
+        }
+    }
+    for half_edge in voronoi.get_half_edge_indices() {
+        if !half_edge_indices.contains_key(half_edge) {
+            half_edge_indices.insert(half_edge, half_edges.len());
+            half_edges.push(half_edge);
+        }
+    }
+    for &half_edge in &half_edges {
+        if let Some(destination) = voronoi.get_half_edge_destination(half_edge) {
+            number_vertex(destination);
+        }
+    }
+    for vertex in voronoi.get_vertex_indices() {
+        number_vertex(vertex);
+    }
+
+    let mut encoder = Encoder { writer };
+    encoder.writer.write_all(&MAGIC)?;
+    encoder.writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+    encoder.writer.write_all(&HEADER_SIZE.to_le_bytes())?;
+    let flags = if is_f32 { FLAG_F32 } else { 0 };
+    encoder.writer.write_all(&flags.to_le_bytes())?;
+    for &count in &[faces.len(), vertices.len(), half_edges.len()] {
+        encoder.writer.write_all(&(count as u32).to_le_bytes())?;
+    }
+
+    let mut previous = (0, 0);
+    let mut last_half_edge = 0;
+    for &face in &faces {
+        encoder.write_point(voronoi.get_face_point(face), &mut previous, is_f32)?;
+        let outer_component = voronoi
+            .get_face_outer_component(face)
+            .map(|half_edge| half_edge_indices[half_edge]);
+        encoder.write_running_reference(outer_component, &mut last_half_edge)?;
+    }
+    let mut previous = (0, 0);
+    for &vertex in &vertices {
+        encoder.write_point(voronoi.get_vertex_point(vertex), &mut previous, is_f32)?;
+    }
+    let (mut last_vertex, mut last_face) = (0, 0);
+    for (i, &half_edge) in half_edges.iter().enumerate() {
+        let vertex_index = |vertex: Option<VertexKey>| vertex.map(|vertex| vertex_indices[vertex]);
+        let half_edge_index = |half_edge: Option<HalfEdgeKey>| {
+            half_edge.map(|half_edge| half_edge_indices[half_edge])
+        };
+        let incident_face = voronoi
+            .get_half_edge_incident_face(half_edge)
+            .map(|face| face_indices[face]);
+        encoder.write_running_reference(
+            vertex_index(voronoi.get_half_edge_origin(half_edge)),
+            &mut last_vertex,
+        )?;
+        encoder.write_running_reference(
+            vertex_index(voronoi.get_half_edge_destination(half_edge)),
+            &mut last_vertex,
+        )?;
+        encoder.write_running_reference(incident_face, &mut last_face)?;
+        encoder.write_reference(half_edge_index(voronoi.get_half_edge_twin(half_edge)), i)?;
+        encoder.write_reference(half_edge_index(voronoi.get_half_edge_next(half_edge)), i)?;
+    }
+    Ok(())
+}
+
+struct Encoder<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> Encoder<W> {
+    fn write_varint(&mut self, mut value: u64) -> io::Result<()> {
+        while value >= 0x80 {
+            self.writer.write_all(&[value as u8 | 0x80])?;
+            value >>= 7;
+        }
+        self.writer.write_all(&[value as u8])
+    }
+
+    /// Writes a position in an array as the difference from another, with zero meaning there is
+    /// no reference.
+    fn write_reference(&mut self, index: Option<usize>, base: usize) -> io::Result<()> {
+        match index {
+            Some(index) => self.write_varint(zigzag(index as i64 - base as i64) + 1),
+            None => self.write_varint(0),
+        }
+    }
+
+    /// Writes a position in an array as the difference from the last position written with the
+    /// same `last`.
+    fn write_running_reference(
+        &mut self,
+        index: Option<usize>,
+        last: &mut usize,
+    ) -> io::Result<()> {
+        self.write_reference(index, *last)?;
+        *last = index.unwrap_or(*last);
+        Ok(())
+    }
+
+    /// Writes the coordinates of a point as the differences between their bits and those of the
+    /// previous point.
+    fn write_point(
+        &mut self,
+        point: cgmath::Point2<f64>,
+        previous: &mut (u64, u64),
+        is_f32: bool,
+    ) -> io::Result<()> {
+        let bits = if is_f32 {
+            (
+                u64::from((point.x as f32).to_bits()),
+                u64::from((point.y as f32).to_bits()),
+            )
+        } else {
+            (point.x.to_bits(), point.y.to_bits())
+        };
+        for &(bits, previous) in &[(bits.0, previous.0), (bits.1, previous.1)] {
+            let difference = if is_f32 {
+                i64::from((bits as u32).wrapping_sub(previous as u32) as i32)
+            } else {
+                bits.wrapping_sub(previous) as i64
+            };
+            self.write_varint(zigzag(difference))?;
+        }
+        *previous = bits;
+        Ok(())
+    }
+}
+
+struct Decoder<R: Read> {
+    reader: R,
+}
+
+impl<R: Read> Decoder<R> {
+    fn read_u16(&mut self) -> io::Result<u16> {
+        let mut bytes = [0; 2];
+        self.reader.read_exact(&mut bytes)?;
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    fn read_u32(&mut self) -> io::Result<u32> {
+        let mut bytes = [0; 4];
+        self.reader.read_exact(&mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn read_varint(&mut self) -> io::Result<u64> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let mut byte = [0];
+            self.reader.read_exact(&mut byte)?;
+            value |= u64::from(byte[0] & 0x7f) << shift;
+            if byte[0] & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(invalid_binary("Integer too long"))
+    }
+
+    fn read_reference(&mut self, base: usize, count: usize) -> io::Result<Option<usize>> {
+        match self.read_varint()? {
+            0 => Ok(None),
+            value => match unzigzag(value - 1).checked_add(base as i64) {
+                Some(index) if index >= 0 && index < count as i64 => Ok(Some(index as usize)),
+                _ => Err(invalid_binary("Reference out of range")),
+            },
+        }
+    }
+
+    fn read_running_reference(
+        &mut self,
+        last: &mut usize,
+        count: usize,
+    ) -> io::Result<Option<usize>> {
+        let index = self.read_reference(*last, count)?;
+        *last = index.unwrap_or(*last);
+        Ok(index)
+    }
+
+    fn read_point(
+        &mut self,
+        previous: &mut (u64, u64),
+        is_f32: bool,
+    ) -> io::Result<cgmath::Point2<f64>> {
+        let mut read_coordinate = |previous: &mut u64| -> io::Result<f64> {
+            let difference = unzigzag(self.read_varint()?);
+            if is_f32 {
+                let bits = (*previous as u32).wrapping_add(difference as u32);
+                *previous = u64::from(bits);
+                Ok(f64::from(f32::from_bits(bits)))
+            } else {
+                *previous = previous.wrapping_add(difference as u64);
+                Ok(f64::from_bits(*previous))
+            }
+        };
+        let x = read_coordinate(&mut previous.0)?;
+        let y = read_coordinate(&mut previous.1)?;
+        Ok(cgmath::Point2::new(x, y))
+    }
+}
+
+/// Maps signed integers to unsigned ones so that those close to zero are small.
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+fn invalid_binary(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid diagram: {}", message),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::random_points;
+
+    type Outline = Vec<(cgmath::Point2<f64>, Option<usize>)>;
+
+    /// Returns the point of each face with the corners of its region, along with the position of
+    /// the face across each edge.
+    fn describe(voronoi: &Diagram) -> Vec<(cgmath::Point2<f64>, Outline)> {
+        let faces = voronoi.get_face_indices();
+        faces
+            .iter()
+            .map(|&face| {
+                let mut outline = vec![];
+                if voronoi.get_face_outer_component(face).is_some() {
+                    for edge in voronoi.outer_edge_iter(face) {
+                        let neighbour = voronoi.get_half_edge_twin(edge).map(|twin| {
+                            let neighbour = voronoi.get_half_edge_incident_face(twin).unwrap();
+                            faces.iter().position(|&f| f == neighbour).unwrap()
+                        });
+                        outline.push((voronoi.get_half_edge_origin_point(edge), neighbour));
+                    }
+                }
+                (voronoi.get_face_point(face), outline)
+            })
+            .collect()
+    }
+
+    fn grid_points() -> Vec<cgmath::Point2<f64>> {
+        let mut points = vec![];
+        for row in 0..6 {
+            for column in 0..6 {
+                points.push(cgmath::Point2::new(
+                    (column as f64 + 0.5) / 6.0,
+                    (row as f64 + 0.5) / 6.0,
+                ));
+            }
+        }
+        points
+    }
+
+    #[test]
+    fn binary_round_trip_test() {
+        for points in &[
+            random_points(90, 500),
+            grid_points(),
+            vec![cgmath::Point2::new(0.5, 0.5)],
+            vec![],
+        ] {
+            let voronoi = build_voronoi(points);
+            let mut binary = vec![];
+            write_binary(&voronoi, &mut binary).unwrap();
+            let read = read_binary(&binary[..]).unwrap();
+            assert_eq!(describe(&read), describe(&voronoi));
+            assert_eq!(
+                read.get_vertex_points().len(),
+                voronoi.get_vertex_points().len()
+            );
+            assert_eq!(
+                read.get_half_edge_indices().len(),
+                voronoi.get_half_edge_indices().len()
+            );
+
+            let mut quantized = vec![];
+            write_binary_f32(&voronoi, &mut quantized).unwrap();
+            let read = read_binary(&quantized[..]).unwrap();
+            for ((point, outline), (expected_point, expected_outline)) in
+                describe(&read).into_iter().zip(describe(&voronoi))
+            {
+                assert!(point.distance(expected_point) < 1e-7);
+                assert_eq!(outline.len(), expected_outline.len());
+                for (&(corner, neighbour), &(expected_corner, expected_neighbour)) in
+                    outline.iter().zip(&expected_outline)
+                {
+                    assert!(corner.distance(expected_corner) < 1e-7);
+                    assert_eq!(neighbour, expected_neighbour);
+                }
+            }
+            if !points.is_empty() {
+                assert!(quantized.len() < binary.len());
+            }
+        }
+    }
+
+    #[test]
+    fn binary_size_test() {
+        // Each half edge takes a byte or two for each reference, and each point less than its two
+        // floats
+        let voronoi = build_voronoi(&random_points(91, 1000));
+        let mut binary = vec![];
+        write_binary(&voronoi, &mut binary).unwrap();
+        let half_edges = voronoi.get_half_edge_indices().len();
+        let points = voronoi.get_face_indices().len() + voronoi.get_vertex_points().len();
+        assert!(binary.len() < 8 * half_edges + 16 * points);
+        let mut quantized = vec![];
+        write_binary_f32(&voronoi, &mut quantized).unwrap();
+        assert!(quantized.len() < 8 * half_edges + 8 * points);
+    }
+
+    #[test]
+    fn binary_header_test() {
+        let voronoi = build_voronoi(&random_points(92, 20));
+        let mut binary = vec![];
+        write_binary(&voronoi, &mut binary).unwrap();
+        let header_end = 8 + HEADER_SIZE as usize;
+
+        // A larger header and anything after the diagram are skipped
+        let mut extended = binary[..header_end].to_vec();
+        extended[6..8].copy_from_slice(&(HEADER_SIZE + 3).to_le_bytes());
+        extended.extend_from_slice(&[7, 8, 9]);
+        extended.extend_from_slice(&binary[header_end..]);
+        extended.extend_from_slice(b"more");
+        let read = read_binary(&extended[..]).unwrap();
+        assert_eq!(describe(&read), describe(&voronoi));
+
+        let mut invalid = vec![];
+        let mut bad_magic = binary.clone();
+        bad_magic[0] = b'X';
+        invalid.push(bad_magic);
+        let mut bad_version = binary.clone();
+        bad_version[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        invalid.push(bad_version);
+        let mut bad_header_size = binary.clone();
+        bad_header_size[6..8].copy_from_slice(&(HEADER_SIZE - 1).to_le_bytes());
+        invalid.push(bad_header_size);
+        let mut bad_flags = binary.clone();
+        bad_flags[8..12].copy_from_slice(&2u32.to_le_bytes());
+        invalid.push(bad_flags);
+        for data in invalid {
+            match read_binary(&data[..]) {
+                Err(error) => assert_eq!(error.kind(), io::ErrorKind::InvalidData),
+                Ok(_) => panic!("Read an invalid diagram"),
+            }
+        }
+        for length in 0..binary.len() {
+            assert!(read_binary(&binary[..length]).is_err());
+        }
+    }
+
+    #[test]
+    fn binary_validation_test() {
+        // One face with a single half edge from and to one vertex, whose twin and next are given
+        // by offsets
+        let write = |twin: Option<usize>, next: Option<usize>| {
+            let mut encoder = Encoder { writer: vec![] };
+            encoder.writer.extend_from_slice(&MAGIC);
+            encoder
+                .writer
+                .extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+            encoder.writer.extend_from_slice(&HEADER_SIZE.to_le_bytes());
+            for &value in &[0u32, 1, 1, 1] {
+                encoder.writer.extend_from_slice(&value.to_le_bytes());
+            }
+            let mut previous = (0, 0);
+            encoder
+                .write_point(cgmath::Point2::new(0.5, 0.5), &mut previous, false)
+                .unwrap();
+            encoder.write_reference(Some(0), 0).unwrap();
+            let mut previous = (0, 0);
+            encoder
+                .write_point(cgmath::Point2::new(0.25, 0.5), &mut previous, false)
+                .unwrap();
+            encoder.write_reference(Some(0), 0).unwrap();
+            encoder.write_reference(Some(0), 0).unwrap();
+            encoder.write_reference(Some(0), 0).unwrap();
+            encoder.write_reference(twin, 0).unwrap();
+            encoder.write_reference(next, 0).unwrap();
+            encoder.writer
+        };
+        assert!(read_binary(&write(None, Some(0))[..]).is_ok());
+        // A half edge can not be its own twin
+        assert!(read_binary(&write(Some(0), Some(0))[..]).is_err());
+        assert!(read_binary(&write(None, Some(1))[..]).is_err());
+    }
+}
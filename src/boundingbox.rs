@@ -1,4 +1,5 @@
 use super::*;
+use slotmap::SecondaryMap;
 use std::f64;
 
 #[derive(Clone, Copy, PartialEq, Debug)]
@@ -23,6 +24,29 @@ impl Side {
     }
 }
 
+/// How an edge of the diagram is clipped by the box.
+///
+/// An edge that is at least partly inside records the sides through which it comes into and goes
+/// out of the box, `Side::None` if that end was already inside.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Clip {
+    Outside,
+    Inside { incoming: Side, outgoing: Side },
+}
+
+impl Clip {
+    /// Returns the clip of the twin half edge running in the opposite direction.
+    fn reversed(self) -> Clip {
+        match self {
+            Clip::Outside => Clip::Outside,
+            Clip::Inside { incoming, outgoing } => Clip::Inside {
+                incoming: outgoing,
+                outgoing: incoming,
+            },
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct BoundingBox {
     left: f64,
@@ -101,212 +125,219 @@ impl BoundingBox {
         cgmath::Point2::new(self.right, self.bottom)
     }
 
+    /// Returns the points at which the segment from origin to destination crosses the border of
+    /// the box, in order from the origin, along with the side they lie on.
+    ///
+    /// A segment with one end inside the box crosses it exactly once and a segment with both ends
+    /// outside either misses the box or crosses it twice. Points on the border count as inside.
     pub fn get_intersections(
         &self,
         origin: &cgmath::Point2<f64>,
         destination: &cgmath::Point2<f64>,
     ) -> Vec<(cgmath::Point2<f64>, Side)> {
-        let mut intersections = vec![];
-        let direction = *destination - *origin;
-        // Left
-        if origin.x < self.left || destination.x < self.left {
-            let t = (self.left - origin.x) / direction.x;
-            if t > 0.0 && t < 1.0 {
-                let intersection_pt = *origin + (direction * t);
-                if intersection_pt.y >= self.top && intersection_pt.y <= self.bottom {
-                    intersections.push((intersection_pt, Side::Left));
-                }
-            }
+        match (self.contains(origin), self.contains(destination)) {
+            (true, true) => vec![],
+            (true, false) => vec![self.get_crossing(origin, destination)],
+            (false, true) => vec![self.get_crossing(destination, origin)],
+            (false, false) => self.clip_segment(origin, destination),
         }
-        // Right
-        if origin.x > self.right || destination.x > self.right {
-            let t = (self.right - origin.x) / direction.x;
-            if t > 0.0 && t < 1.0 {
-                let intersection_pt = *origin + (direction * t);
-                if intersection_pt.y >= self.top && intersection_pt.y <= self.bottom {
-                    intersections.push((intersection_pt, Side::Right));
+    }
+
+    /// Returns the point at which the segment from a point inside the box to one outside it
+    /// leaves the box.
+    fn get_crossing(
+        &self,
+        inside: &cgmath::Point2<f64>,
+        outside: &cgmath::Point2<f64>,
+    ) -> (cgmath::Point2<f64>, Side) {
+        let direction = *outside - *inside;
+        let mut t = f64::MAX;
+        let mut side = Side::None;
+        // The outside point is beyond at least one side, the first side reached is the crossing
+        for &(beyond, bound, from, delta, candidate) in &[
+            (
+                outside.x < self.left,
+                self.left,
+                inside.x,
+                direction.x,
+                Side::Left,
+            ),
+            (
+                outside.x > self.right,
+                self.right,
+                inside.x,
+                direction.x,
+                Side::Right,
+            ),
+            (
+                outside.y < self.top,
+                self.top,
+                inside.y,
+                direction.y,
+                Side::Top,
+            ),
+            (
+                outside.y > self.bottom,
+                self.bottom,
+                inside.y,
+                direction.y,
+                Side::Bottom,
+            ),
+        ] {
+            if beyond {
+                let candidate_t = (bound - from) / delta;
+                if candidate_t < t {
+                    t = candidate_t;
+                    side = candidate;
                 }
             }
         }
-        // Top
-        if origin.y < self.top || destination.y < self.top {
-            let t = (self.top - origin.y) / direction.y;
-            if t > 0.0 && t < 1.0 {
-                let intersection_pt = *origin + (direction * t);
-                if intersection_pt.x <= self.right && intersection_pt.x >= self.left {
-                    intersections.push((intersection_pt, Side::Top));
+        (self.snap(*inside + direction * t, side), side)
+    }
+
+    /// Clips a segment with both ends outside the box using the Liang-Barsky algorithm, returning
+    /// the points where it enters and leaves the box or nothing if it misses the box.
+    fn clip_segment(
+        &self,
+        origin: &cgmath::Point2<f64>,
+        destination: &cgmath::Point2<f64>,
+    ) -> Vec<(cgmath::Point2<f64>, Side)> {
+        let direction = *destination - *origin;
+        let (mut t0, mut side0) = (0.0, Side::None);
+        let (mut t1, mut side1) = (1.0, Side::None);
+        for &(p, q, side) in &[
+            (-direction.x, origin.x - self.left, Side::Left),
+            (direction.x, self.right - origin.x, Side::Right),
+            (-direction.y, origin.y - self.top, Side::Top),
+            (direction.y, self.bottom - origin.y, Side::Bottom),
+        ] {
+            if p == 0.0 {
+                if q < 0.0 {
+                    return vec![];
                 }
-            }
-        }
-        // Bottom
-        if origin.y > self.bottom || destination.y > self.bottom {
-            let t = (self.bottom - origin.y) / direction.y;
-            if t > 0.0 && t < 1.0 {
-                let intersection_pt = *origin + (direction * t);
-                if intersection_pt.x <= self.right && intersection_pt.x >= self.left {
-                    intersections.push((intersection_pt, Side::Bottom));
+            } else {
+                let t = q / p;
+                if p < 0.0 && t > t0 {
+                    t0 = t;
+                    side0 = side;
+                } else if p > 0.0 && t < t1 {
+                    t1 = t;
+                    side1 = side;
                 }
             }
         }
 
-        intersections
+        // An end very close to the border can be rounded inside above, use the side it is beyond
+        if side0 == Side::None {
+            side0 = self.get_side_beyond(origin);
+        }
+        if side1 == Side::None {
+            side1 = self.get_side_beyond(destination);
+        }
+
+        // A segment that only touches the border is treated as missing the box
+        if t0 >= t1 {
+            return vec![];
+        }
+        vec![
+            (self.snap(*origin + direction * t0, side0), side0),
+            (self.snap(*origin + direction * t1, side1), side1),
+        ]
     }
 
+    /// Returns a side of the box that a point outside it lies beyond.
+    fn get_side_beyond(&self, point: &cgmath::Point2<f64>) -> Side {
+        if point.x < self.left {
+            Side::Left
+        } else if point.x > self.right {
+            Side::Right
+        } else if point.y < self.top {
+            Side::Top
+        } else if point.y > self.bottom {
+            Side::Bottom
+        } else {
+            Side::None
+        }
+    }
+
+    /// Moves a point that should be on a side of the box exactly onto it.
+    fn snap(&self, point: cgmath::Point2<f64>, side: Side) -> cgmath::Point2<f64> {
+        let x = point.x.max(self.left).min(self.right);
+        let y = point.y.max(self.top).min(self.bottom);
+        match side {
+            Side::Left => cgmath::Point2::new(self.left, y),
+            Side::Right => cgmath::Point2::new(self.right, y),
+            Side::Top => cgmath::Point2::new(x, self.top),
+            Side::Bottom => cgmath::Point2::new(x, self.bottom),
+            Side::None => cgmath::Point2::new(x, y),
+        }
+    }
+
+    /// Clips every region of the diagram to the box.
+    ///
+    /// Each edge is clipped once, when the first of its two half edges is reached, and the result
+    /// is recorded against both half edges so that neighbouring regions share the new vertices and
+    /// agree on which edges are removed. The parts of a region's border that run along the box
+    /// are then added between the half edge leaving the box and the next one entering it.
     pub fn intersect_diagram(&self, voronoi: &mut Diagram) {
+        let mut clips: SecondaryMap<HalfEdgeKey, Clip> = SecondaryMap::new();
         let mut vertices_to_remove = vec![];
         let mut half_edges_to_remove = vec![];
-        let mut processed_half_edges = vec![];
         for face in voronoi.get_face_indices() {
-            let start_half_edge = voronoi.get_face_outer_component(face).unwrap();
-            let mut outgoing_half_edge: Option<HalfEdgeKey> = None;
-            let mut outgoing_side = Side::None;
-            let mut incoming_half_edge: Option<HalfEdgeKey> = None;
-            let mut incoming_side = Side::None;
-            let mut half_edge = start_half_edge;
-            loop {
-                let origin = voronoi.get_half_edge_origin(half_edge).unwrap();
-                let destination = voronoi.get_half_edge_destination(half_edge).unwrap();
-                let inside = self.contains(&voronoi.get_vertex_point(origin));
-                let next_inside = self.contains(&voronoi.get_vertex_point(destination));
-
-                let next_half_edge = voronoi.get_half_edge_next(half_edge).unwrap();
-
-                if !inside || !next_inside {
-                    let intersections = self.get_intersections(
-                        &voronoi.get_vertex_point(origin),
-                        &voronoi.get_vertex_point(destination),
-                    );
-                    if !inside && !next_inside {
-                        // Both points are outside the box
-                        if intersections.is_empty() {
-                            // The edge is outside the box
-                            vertices_to_remove.push(origin);
-                            if Some(half_edge) == voronoi.get_face_outer_component(face) {
-                                // Update the outer component before we delete the half_edge
-                                voronoi.set_face_outer_component(
-                                    face,
-                                    voronoi.get_half_edge_next(half_edge),
-                                );
-                            }
-
-                            half_edges_to_remove.push(half_edge);
-                        } else if intersections.len() == 2 {
-                            // The edge crosses the bounds of the box twice
-                            vertices_to_remove.push(origin);
-                            let half_edge_twin = voronoi.get_half_edge_twin(half_edge);
-                            if half_edge_twin.is_some()
-                                && processed_half_edges.contains(&half_edge_twin.unwrap())
-                            {
-                                voronoi.set_half_edge_origin(
-                                    half_edge,
-                                    voronoi.get_half_edge_destination(half_edge_twin.unwrap()),
-                                );
-                                voronoi.set_half_edge_destination(
-                                    half_edge,
-                                    voronoi.get_half_edge_origin(half_edge_twin.unwrap()),
-                                );
-                            } else {
-                                let origin = voronoi.add_vertex(intersections[0].0);
-                                let destination = voronoi.add_vertex(intersections[1].0);
-                                voronoi.set_half_edge_origin(half_edge, Some(origin));
-                                voronoi.set_half_edge_destination(half_edge, Some(destination));
-                            }
-                            if outgoing_half_edge.is_some() {
-                                self.link_vertices(
-                                    voronoi,
-                                    outgoing_half_edge.unwrap(),
-                                    outgoing_side,
-                                    half_edge,
-                                    intersections[0].1,
-                                )
-                            }
-                            outgoing_half_edge = Some(half_edge);
-                            outgoing_side = intersections[1].1;
-                            processed_half_edges.push(half_edge);
-                        } else {
-                            panic!(
-                                "An edge that begins inside the box but ends outside can only have a single intersection, origin {:?}, destination {:?}",
-                                 &voronoi.get_vertex_point(origin),
-                                  &voronoi.get_vertex_point(destination)
-                                  );
+            let half_edges: Vec<HalfEdgeKey> = match voronoi.get_face_outer_component(face) {
+                Some(_) => voronoi.outer_edge_iter(face).collect(),
+                None => continue,
+            };
+
+            let mut first_incoming: Option<(HalfEdgeKey, Side)> = None;
+            let mut outgoing: Option<(HalfEdgeKey, Side)> = None;
+            let mut outer_component = None;
+            for half_edge in half_edges {
+                let clip = match clips.get(half_edge) {
+                    Some(&clip) => clip,
+                    None => self.clip_edge(voronoi, half_edge, &mut clips, &mut vertices_to_remove),
+                };
+                match clip {
+                    Clip::Outside => half_edges_to_remove.push(half_edge),
+                    Clip::Inside {
+                        incoming,
+                        outgoing: outgoing_side,
+                    } => {
+                        if outer_component.is_none() {
+                            outer_component = Some(half_edge);
                         }
-                    } else if inside && !next_inside {
-                        // Edge is going outside the box
-                        if intersections.len() == 1 {
-                            let half_edge_twin = voronoi.get_half_edge_twin(half_edge);
-                            if half_edge_twin.is_some()
-                                && processed_half_edges.contains(&half_edge_twin.unwrap())
-                            {
-                                voronoi.set_half_edge_destination(
-                                    half_edge,
-                                    voronoi.get_half_edge_origin(half_edge_twin.unwrap()),
-                                );
-                            } else {
-                                let destination = voronoi.add_vertex(intersections[0].0);
-                                voronoi.set_half_edge_destination(half_edge, Some(destination));
-                            }
-                            if incoming_half_edge.is_some() {
-                                self.link_vertices(
+                        if incoming != Side::None {
+                            match outgoing.take() {
+                                Some((outgoing_half_edge, side)) => self.link_vertices(
                                     voronoi,
+                                    outgoing_half_edge,
+                                    side,
                                     half_edge,
-                                    intersections[0].1,
-                                    incoming_half_edge.unwrap(),
-                                    incoming_side,
-                                )
+                                    incoming,
+                                ),
+                                None => first_incoming = Some((half_edge, incoming)),
                             }
-                            outgoing_half_edge = Some(half_edge);
-                            outgoing_side = intersections[0].1;
-                            processed_half_edges.push(half_edge);
-                        } else {
-                            panic!(
-                                "An edge that begins inside the box but ends outside can only have a single intersection, origin {:?}, destination {:?}", 
-                                &voronoi.get_vertex_point(origin), 
-                                &voronoi.get_vertex_point(destination)
-                                );
                         }
-                    } else if !inside && next_inside {
-                        // Edge is coming into the box
-                        if intersections.len() == 1 {
-                            vertices_to_remove.push(origin);
-                            let half_edge_twin = voronoi.get_half_edge_twin(half_edge);
-                            if half_edge_twin.is_some()
-                                && processed_half_edges.contains(&half_edge_twin.unwrap())
-                            {
-                                voronoi.set_half_edge_origin(
-                                    half_edge,
-                                    voronoi.get_half_edge_destination(half_edge_twin.unwrap()),
-                                );
-                            } else {
-                                let origin = voronoi.add_vertex(intersections[0].0);
-                                voronoi.set_half_edge_origin(half_edge, Some(origin));
-                            }
-                            if outgoing_half_edge.is_some() {
-                                self.link_vertices(
-                                    voronoi,
-                                    outgoing_half_edge.unwrap(),
-                                    outgoing_side,
-                                    half_edge,
-                                    intersections[0].1,
-                                )
-                            }
-                            incoming_half_edge = Some(half_edge);
-                            incoming_side = intersections[0].1;
-                            processed_half_edges.push(half_edge);
-                        } else {
-                            panic!(
-                                "An edge that begins inside the box but ends outside can only have a single intersection, origin {:?}, destination {:?}", 
-                                &voronoi.get_vertex_point(origin), 
-                                &voronoi.get_vertex_point(destination)
-                            );
+                        if outgoing_side != Side::None {
+                            outgoing = Some((half_edge, outgoing_side));
                         }
                     }
                 }
-                if next_half_edge == start_half_edge {
-                    // Back where we started so break out of the loop
-                    break;
-                }
-                half_edge = next_half_edge;
             }
+            if let (
+                Some((outgoing_half_edge, outgoing_side)),
+                Some((incoming_half_edge, incoming_side)),
+            ) = (outgoing, first_incoming)
+            {
+                self.link_vertices(
+                    voronoi,
+                    outgoing_half_edge,
+                    outgoing_side,
+                    incoming_half_edge,
+                    incoming_side,
+                );
+            }
+            voronoi.set_face_outer_component(face, outer_component);
         }
         for half_edge in half_edges_to_remove {
             voronoi.remove_half_edge(half_edge);
@@ -316,6 +347,83 @@ impl BoundingBox {
         }
     }
 
+    /// Clips a half edge and its twin to the box, recording the result for both of them.
+    fn clip_edge(
+        &self,
+        voronoi: &mut Diagram,
+        half_edge: HalfEdgeKey,
+        clips: &mut SecondaryMap<HalfEdgeKey, Clip>,
+        vertices_to_remove: &mut Vec<VertexKey>,
+    ) -> Clip {
+        let origin = voronoi.get_half_edge_origin(half_edge).unwrap();
+        let destination = voronoi.get_half_edge_destination(half_edge).unwrap();
+        let origin_point = voronoi.get_vertex_point(origin);
+        let destination_point = voronoi.get_vertex_point(destination);
+        let origin_inside = self.contains(&origin_point);
+        let destination_inside = self.contains(&destination_point);
+        if !origin_inside {
+            vertices_to_remove.push(origin);
+        }
+        if !destination_inside {
+            vertices_to_remove.push(destination);
+        }
+
+        let intersections = self.get_intersections(&origin_point, &destination_point);
+        let clip = match (origin_inside, destination_inside, &intersections[..]) {
+            (true, true, _) => Clip::Inside {
+                incoming: Side::None,
+                outgoing: Side::None,
+            },
+            (true, false, &[(point, side)]) => {
+                let vertex = voronoi.add_vertex(point);
+                self.set_edge_vertices(voronoi, half_edge, origin, vertex);
+                Clip::Inside {
+                    incoming: Side::None,
+                    outgoing: side,
+                }
+            }
+            (false, true, &[(point, side)]) => {
+                let vertex = voronoi.add_vertex(point);
+                self.set_edge_vertices(voronoi, half_edge, vertex, destination);
+                Clip::Inside {
+                    incoming: side,
+                    outgoing: Side::None,
+                }
+            }
+            (false, false, &[(point_1, side_1), (point_2, side_2)]) => {
+                let vertex_1 = voronoi.add_vertex(point_1);
+                let vertex_2 = voronoi.add_vertex(point_2);
+                self.set_edge_vertices(voronoi, half_edge, vertex_1, vertex_2);
+                Clip::Inside {
+                    incoming: side_1,
+                    outgoing: side_2,
+                }
+            }
+            _ => Clip::Outside,
+        };
+
+        clips.insert(half_edge, clip);
+        if let Some(twin) = voronoi.get_half_edge_twin(half_edge) {
+            clips.insert(twin, clip.reversed());
+        }
+        clip
+    }
+
+    fn set_edge_vertices(
+        &self,
+        voronoi: &mut Diagram,
+        half_edge: HalfEdgeKey,
+        origin: VertexKey,
+        destination: VertexKey,
+    ) {
+        voronoi.set_half_edge_origin(half_edge, Some(origin));
+        voronoi.set_half_edge_destination(half_edge, Some(destination));
+        if let Some(twin) = voronoi.get_half_edge_twin(half_edge) {
+            voronoi.set_half_edge_origin(twin, Some(destination));
+            voronoi.set_half_edge_destination(twin, Some(origin));
+        }
+    }
+
     pub fn link_vertices(
         &self,
         voronoi: &mut Diagram,
@@ -398,4 +506,26 @@ mod tests {
         let intersections = bbox.get_intersections(&origin, &destination);
         assert_eq!(intersections.len(), 2);
     }
+
+    #[test]
+    fn clip_segment_test() {
+        let bbox = BoundingBox::new(0.0, 1.0, 0.0, 1.0);
+
+        // Passing through a corner only touches the box
+        let origin = cgmath::Point2::new(-0.5, 0.5);
+        let destination = cgmath::Point2::new(0.5, -0.5);
+        assert!(bbox.get_intersections(&origin, &destination).is_empty());
+
+        // Leaving through the top just outside the box, which rounding alone would miss
+        let origin = cgmath::Point2::new(-8.148102283752184, 37.990901671347714);
+        let destination = cgmath::Point2::new(0.6446793359866143, -1.1102230246251565e-16);
+        let intersections = bbox.get_intersections(&origin, &destination);
+        assert_eq!(intersections.len(), 2);
+        assert_eq!(intersections[0].1, Side::Bottom);
+        assert_eq!(intersections[1].1, Side::Top);
+        assert_eq!(intersections[1].0.y, 0.0);
+        for (point, _) in intersections {
+            assert!(bbox.contains(&point));
+        }
+    }
 }
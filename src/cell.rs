@@ -0,0 +1,417 @@
+use super::*;
+use cgmath::{EuclideanSpace, InnerSpace, MetricSpace};
+use std::collections::HashSet;
+
+/// Points closer together than this are treated as the same vertex when clipping a cell.
+const VERTEX_TOLERANCE: f64 = 1e-12;
+
+/// A convex region of the plane that is computed directly rather than by sweeping, used to update
+/// part of a diagram without rebuilding all of it.
+///
+/// The vertices are stored in the same order as the half edges around a face of the diagram. Each
+/// vertex is paired with the face on the other side of the edge that leaves it, or `None` if that
+/// edge is on the border of the diagram.
+#[derive(Clone, Debug)]
+pub struct Cell {
+    vertices: Vec<(cgmath::Point2<f64>, Option<FaceKey>)>,
+}
+
+impl Cell {
+    /// Constructs a cell covering the whole of a bounding box.
+    pub fn new(bbox: &BoundingBox) -> Self {
+        Cell {
+            vertices: vec![
+                (bbox.get_top_left(), None),
+                (bbox.get_bottom_left(), None),
+                (bbox.get_bottom_right(), None),
+                (bbox.get_top_right(), None),
+            ],
+        }
+    }
+
+    /// Constructs a cell from its vertices, each paired with the face across the edge leaving it.
+    #[cfg(feature = "parallel")]
+    pub fn from_vertices(vertices: Vec<(cgmath::Point2<f64>, Option<FaceKey>)>) -> Self {
+        Cell { vertices }
+    }
+
+    /// Returns the vertices of the cell, each paired with the face across the edge leaving it.
+    pub fn vertices(&self) -> &[(cgmath::Point2<f64>, Option<FaceKey>)] {
+        &self.vertices
+    }
+
+    /// Returns the faces that share an edge with the cell.
+    pub fn neighbours(&self) -> impl Iterator<Item = FaceKey> + '_ {
+        self.vertices.iter().filter_map(|&(_, face)| face)
+    }
+
+    /// Calculates the area of the cell.
+    pub fn area(&self) -> f64 {
+        let points: Vec<cgmath::Point2<f64>> =
+            self.vertices.iter().map(|&(point, _)| point).collect();
+        vector2::get_polygon_signed_area(&points).abs()
+    }
+
+    /// Returns the distance from a point to the furthest vertex of the cell.
+    pub fn radius(&self, point: cgmath::Point2<f64>) -> f64 {
+        self.vertices
+            .iter()
+            .map(|&(vertex, _)| vertex.distance(point))
+            .fold(0.0, f64::max)
+    }
+
+    /// Clips the cell to the part of the plane that is closer to `site` than to the point of
+    /// another face.
+    ///
+    /// # Arguments
+    /// * `site` - the point the cell belongs to.
+    /// * `other_face` - the face that any new edge will border.
+    /// * `other_site` - the point of the other face.
+    pub fn clip(
+        &mut self,
+        site: cgmath::Point2<f64>,
+        other_face: FaceKey,
+        other_site: cgmath::Point2<f64>,
+    ) {
+        let normal = other_site - site;
+        let midpoint = site.midpoint(other_site);
+        let distance = |point: cgmath::Point2<f64>| (point - midpoint).dot(normal);
+
+        if self
+            .vertices
+            .iter()
+            .all(|&(point, _)| distance(point) <= 0.0)
+        {
+            return;
+        }
+
+        let n = self.vertices.len();
+        let mut clipped = Vec::with_capacity(n + 1);
+        for i in 0..n {
+            let (point, face) = self.vertices[i];
+            let next_point = self.vertices[(i + 1) % n].0;
+            let point_distance = distance(point);
+            let next_distance = distance(next_point);
+            if point_distance <= 0.0 {
+                clipped.push((point, face));
+                if next_distance > 0.0 {
+                    // The edge leaves the cell, the new edge along the bisector starts here
+                    let t = point_distance / (point_distance - next_distance);
+                    clipped.push((point + (next_point - point) * t, Some(other_face)));
+                }
+            } else if next_distance <= 0.0 {
+                // The edge enters the cell
+                let t = point_distance / (point_distance - next_distance);
+                clipped.push((point + (next_point - point) * t, face));
+            }
+        }
+
+        // Remove any edges that have collapsed to a point
+        let mut i = 0;
+        while clipped.len() > 1 && i < clipped.len() {
+            let next_point = clipped[(i + 1) % clipped.len()].0;
+            if clipped[i].0.distance2(next_point) < VERTEX_TOLERANCE * VERTEX_TOLERANCE {
+                clipped.remove(i);
+            } else {
+                i += 1;
+            }
+        }
+
+        self.vertices = clipped;
+    }
+}
+
+/// Computes the region of a site within the unit square given the faces that may border it.
+///
+/// # Arguments
+/// * `voronoi` - the diagram containing the candidate faces.
+/// * `face` - the face the region is being computed for, this is skipped if it is a candidate.
+/// * `site` - the point of the face.
+/// * `candidates` - the faces that may share an edge with the region.
+pub fn compute_cell<I>(
+    voronoi: &Diagram,
+    face: FaceKey,
+    site: cgmath::Point2<f64>,
+    candidates: I,
+) -> Cell
+where
+    I: IntoIterator<Item = FaceKey>,
+{
+    // Clipping twice against the same face could leave behind slivers due to rounding
+    let mut candidates: Vec<FaceKey> = candidates
+        .into_iter()
+        .filter(|&candidate| candidate != face)
+        .collect();
+    candidates.sort();
+    candidates.dedup();
+
+    let mut cell = Cell::new(&BoundingBox::new(0.0, 1.0, 0.0, 1.0));
+    for candidate in candidates {
+        cell.clip(site, candidate, voronoi.get_face_point(candidate));
+    }
+    cell
+}
+
+/// Returns the faces that share an edge with a face, or nothing if the face has no edges.
+pub fn get_neighbours(voronoi: &Diagram, face: FaceKey) -> Vec<FaceKey> {
+    if voronoi.get_face_outer_component(face).is_none() {
+        return vec![];
+    }
+    delauney::get_adjacent_face_iterator(voronoi, face).collect()
+}
+
+/// Returns the corners of the region of a face as a closed ring, which repeats the first corner at
+/// the end, or an empty ring if the face has no edges.
+///
+/// The ring winds counter-clockwise when y increases up, which appears clockwise when drawn with y
+/// increasing down like the diagram.
+pub fn get_counter_clockwise_ring(voronoi: &Diagram, face: FaceKey) -> Vec<cgmath::Point2<f64>> {
+    if voronoi.get_face_outer_component(face).is_none() {
+        return vec![];
+    }
+    let mut ring: Vec<cgmath::Point2<f64>> = voronoi
+        .outer_edge_iter(face)
+        .map(|edge| voronoi.get_half_edge_origin_point(edge))
+        .collect();
+    if vector2::get_polygon_signed_area(&ring) < 0.0 {
+        ring.reverse();
+    }
+    ring.push(ring[0]);
+    ring
+}
+
+/// Finds the face whose point is closest to a point by walking across neighbouring faces.
+///
+/// Each step moves to the neighbour closest to the point, which always reaches the closest face as
+/// every face that is not the closest has a neighbour that is closer.
+///
+/// # Arguments
+/// * `voronoi` - the diagram to search.
+/// * `start` - the face to start walking from.
+/// * `point` - the point to find the closest face to.
+pub fn find_nearest_face(voronoi: &Diagram, start: FaceKey, point: cgmath::Point2<f64>) -> FaceKey {
+    let mut current = start;
+    let mut current_distance = point.distance2(voronoi.get_face_point(current));
+    loop {
+        let mut closer = None;
+        for neighbour in get_neighbours(voronoi, current) {
+            let distance = point.distance2(voronoi.get_face_point(neighbour));
+            if distance < current_distance {
+                current_distance = distance;
+                closer = Some(neighbour);
+            }
+        }
+        match closer {
+            Some(neighbour) => current = neighbour,
+            None => return current,
+        }
+    }
+}
+
+/// Returns the key identifying a vertex shared by several cells.
+///
+/// The faces around a vertex, with `None` standing for the outside of the diagram, appear in the
+/// same cyclic order from each of them, so rotating them to start at the smallest gives the same
+/// key whichever cell the vertex is seen from.
+fn get_vertex_key(
+    face: FaceKey,
+    next: Option<FaceKey>,
+    prev: Option<FaceKey>,
+) -> [Option<FaceKey>; 3] {
+    let key = [Some(face), next, prev];
+    let start = (0..3).min_by_key(|&i| key[i]).unwrap();
+    [key[start], key[(start + 1) % 3], key[(start + 2) % 3]]
+}
+
+/// Finds the half edge of an unchanged face that borders another face.
+fn get_bordering_half_edge(
+    voronoi: &Diagram,
+    face: FaceKey,
+    other_face: FaceKey,
+) -> Option<HalfEdgeKey> {
+    voronoi.get_face_outer_component(face)?;
+    voronoi.outer_edge_iter(face).find(|&edge| {
+        voronoi
+            .get_half_edge_twin(edge)
+            .and_then(|twin| voronoi.get_half_edge_incident_face(twin))
+            == Some(other_face)
+    })
+}
+
+/// Checks that a set of cells can replace the regions of their faces.
+///
+/// Every cell must agree with its neighbouring cells about which edges they share, and the faces
+/// that are not being replaced must keep exactly the same edges.
+fn validate_cells(voronoi: &Diagram, cells: &[(FaceKey, Cell)], detached: &[FaceKey]) -> bool {
+    let replaced: HashSet<FaceKey> = cells
+        .iter()
+        .map(|&(face, _)| face)
+        .chain(detached.iter().cloned())
+        .collect();
+    let cell_neighbours: HashMap<FaceKey, Vec<FaceKey>> = cells
+        .iter()
+        .map(|(face, cell)| (*face, cell.neighbours().collect()))
+        .collect();
+
+    for (face, neighbours) in &cell_neighbours {
+        for neighbour in neighbours {
+            if detached.contains(neighbour) {
+                return false;
+            }
+            match cell_neighbours.get(neighbour) {
+                Some(other_neighbours) => {
+                    if other_neighbours.iter().filter(|&f| f == face).count() != 1 {
+                        return false;
+                    }
+                }
+                None => {
+                    if get_bordering_half_edge(voronoi, *neighbour, *face).is_none() {
+                        return false;
+                    }
+                }
+            }
+        }
+    }
+
+    // Any unchanged face that bordered a replaced face must still border it
+    for &face in &replaced {
+        for neighbour in get_neighbours(voronoi, face) {
+            if !replaced.contains(&neighbour)
+                && !cell_neighbours
+                    .get(&face)
+                    .into_iter()
+                    .flatten()
+                    .any(|&cell_neighbour| cell_neighbour == neighbour)
+            {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// Replaces the half edges and vertices of a set of faces with ones describing new cells.
+///
+/// The regions of all the faces not being replaced must be unchanged, so the new cells exactly
+/// cover the old regions of the replaced and detached faces. Returns false without changing the
+/// diagram if the cells do not fit together, which can happen when sites are very close to being
+/// cocircular.
+///
+/// # Arguments
+/// * `voronoi` - the diagram to update.
+/// * `cells` - the faces to replace the regions of, along with their new cells.
+/// * `detached` - faces that will no longer have a region, their half edges are removed.
+pub fn replace_cells(
+    voronoi: &mut Diagram,
+    cells: &[(FaceKey, Cell)],
+    detached: &[FaceKey],
+) -> bool {
+    if !validate_cells(voronoi, cells, detached) {
+        return false;
+    }
+    let replaced: HashSet<FaceKey> = cells.iter().map(|&(face, _)| face).collect();
+
+    // Collect the parts of the diagram that are being replaced
+    let mut old_half_edges = vec![];
+    let mut old_vertices = HashSet::new();
+    let mut unchanged_neighbours = HashSet::new();
+    for &face in replaced.iter().chain(detached.iter()) {
+        if voronoi.get_face_outer_component(face).is_none() {
+            continue;
+        }
+        for edge in voronoi.outer_edge_iter(face) {
+            old_half_edges.push(edge);
+            old_vertices.extend(voronoi.get_half_edge_origin(edge));
+            old_vertices.extend(voronoi.get_half_edge_destination(edge));
+            if let Some(twin) = voronoi.get_half_edge_twin(edge) {
+                let neighbour = voronoi.get_half_edge_incident_face(twin).unwrap();
+                if !replaced.contains(&neighbour) && !detached.contains(&neighbour) {
+                    unchanged_neighbours.insert(neighbour);
+                }
+            }
+        }
+    }
+    for &face in &unchanged_neighbours {
+        for edge in voronoi.outer_edge_iter(face) {
+            if let Some(origin) = voronoi.get_half_edge_origin(edge) {
+                old_vertices.remove(&origin);
+            }
+        }
+    }
+
+    // Build the new half edges
+    let mut shared_vertices = HashMap::new();
+    let mut shared_half_edges: HashMap<(FaceKey, FaceKey), HalfEdgeKey> = HashMap::new();
+    let mut new_outer_components = vec![];
+    for (face, cell) in cells {
+        let vertices = cell.vertices();
+        let n = vertices.len();
+        let bordering: Vec<Option<HalfEdgeKey>> = vertices
+            .iter()
+            .map(|&(_, neighbour)| match neighbour {
+                Some(neighbour) if !replaced.contains(&neighbour) => {
+                    get_bordering_half_edge(voronoi, neighbour, *face)
+                }
+                _ => None,
+            })
+            .collect();
+
+        let mut vertex_keys = Vec::with_capacity(n);
+        for i in 0..n {
+            let prev = (i + n - 1) % n;
+            let (point, next_face) = vertices[i];
+            let prev_face = vertices[prev].1;
+            let vertex = if let Some(edge) = bordering[i] {
+                voronoi.get_half_edge_destination(edge).unwrap()
+            } else if let Some(edge) = bordering[prev] {
+                voronoi.get_half_edge_origin(edge).unwrap()
+            } else if next_face.is_none() && prev_face.is_none() {
+                // A corner of the bounding box belongs to a single face
+                voronoi.add_vertex(point)
+            } else {
+                *shared_vertices
+                    .entry(get_vertex_key(*face, next_face, prev_face))
+                    .or_insert_with(|| voronoi.add_vertex(point))
+            };
+            vertex_keys.push(vertex);
+        }
+
+        let mut half_edges = Vec::with_capacity(n);
+        for i in 0..n {
+            let half_edge = voronoi.add_half_edge(*face);
+            voronoi.set_half_edge_origin(half_edge, Some(vertex_keys[i]));
+            voronoi.set_half_edge_destination(half_edge, Some(vertex_keys[(i + 1) % n]));
+            let twin = match vertices[i].1 {
+                Some(neighbour) if replaced.contains(&neighbour) => {
+                    shared_half_edges.insert((*face, neighbour), half_edge);
+                    shared_half_edges.get(&(neighbour, *face)).cloned()
+                }
+                _ => bordering[i],
+            };
+            if let Some(twin) = twin {
+                voronoi.set_half_edge_twin(half_edge, Some(twin));
+                voronoi.set_half_edge_twin(twin, Some(half_edge));
+            }
+            half_edges.push(half_edge);
+        }
+        for i in 0..n {
+            voronoi.link_half_edges(half_edges[i], half_edges[(i + 1) % n]);
+        }
+        new_outer_components.push((*face, half_edges.first().cloned()));
+    }
+
+    // Remove the old parts of the diagram
+    for half_edge in old_half_edges {
+        voronoi.remove_half_edge(half_edge);
+    }
+    for vertex in old_vertices {
+        voronoi.remove_vertex(vertex);
+    }
+    for (face, outer_component) in new_outer_components {
+        voronoi.set_face_outer_component(face, outer_component);
+    }
+    for &face in detached {
+        voronoi.set_face_outer_component(face, None);
+    }
+    true
+}
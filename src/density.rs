@@ -0,0 +1,173 @@
+use super::*;
+
+/// The number of times each side of a triangle is divided when integrating a density over it.
+const QUADRATURE_SUBDIVISIONS: usize = 3;
+
+/// A density over the unit square, used to concentrate points where it is high.
+pub trait Density {
+    /// Returns the density at a point in the range [0,1] X [0,1], this should not be negative.
+    fn density(&self, point: cgmath::Point2<f64>) -> f64;
+}
+
+impl<F: Fn(cgmath::Point2<f64>) -> f64> Density for F {
+    fn density(&self, point: cgmath::Point2<f64>) -> f64 {
+        self(point)
+    }
+}
+
+/// A density read from a grayscale raster stretched over the unit square.
+///
+/// The first row of pixels is at the top of the square, where y is 0. The density is interpolated
+/// between the centers of the pixels.
+#[derive(Clone, Debug)]
+pub struct ImageDensity {
+    width: usize,
+    height: usize,
+    values: Vec<f64>,
+}
+
+impl ImageDensity {
+    /// Constructs a density from the pixels of a grayscale image, with brighter pixels giving a
+    /// higher density.
+    /// # Arguments
+    /// * `width` - the number of pixels in each row.
+    /// * `height` - the number of rows.
+    /// * `pixels` - the brightness of each pixel, row by row.
+    ///
+    /// # Panics
+    /// If the image is empty or the number of pixels does not match its size.
+    pub fn from_grayscale(width: usize, height: usize, pixels: &[u8]) -> Self {
+        assert!(width > 0 && height > 0, "The image must not be empty");
+        assert_eq!(
+            pixels.len(),
+            width * height,
+            "There must be a pixel for every row and column of the image"
+        );
+        ImageDensity {
+            width,
+            height,
+            values: pixels
+                .iter()
+                .map(|&pixel| f64::from(pixel) / 255.0)
+                .collect(),
+        }
+    }
+
+    /// Returns the density with darker pixels giving a higher density, as used for stippling.
+    pub fn inverted(mut self) -> Self {
+        for value in &mut self.values {
+            *value = 1.0 - *value;
+        }
+        self
+    }
+
+    fn get_value(&self, column: usize, row: usize) -> f64 {
+        self.values[row * self.width + column]
+    }
+}
+
+impl Density for ImageDensity {
+    fn density(&self, point: cgmath::Point2<f64>) -> f64 {
+        // Find the pixel centers either side of the point along each axis
+        let locate = |coordinate: f64, size: usize| {
+            let position = (coordinate * size as f64 - 0.5)
+                .max(0.0)
+                .min((size - 1) as f64);
+            let before = position.floor() as usize;
+            (before, (before + 1).min(size - 1), position - before as f64)
+        };
+        let (left, right, tx) = locate(point.x, self.width);
+        let (top, bottom, ty) = locate(point.y, self.height);
+        let top_value = self.get_value(left, top) * (1.0 - tx) + self.get_value(right, top) * tx;
+        let bottom_value =
+            self.get_value(left, bottom) * (1.0 - tx) + self.get_value(right, bottom) * tx;
+        top_value * (1.0 - ty) + bottom_value * ty
+    }
+}
+
+/// Calculates the centroid of the region of a face weighted by a density.
+///
+/// The density is integrated over a fan of triangles from the point of the face to each of its
+/// edges, each of which is divided into smaller triangles that are sampled at their centroids.
+/// If the density is zero over the whole region this falls back to the unweighted centroid.
+/// # Arguments
+/// * `voronoi` - the diagram containing the face.
+/// * `face` - the index of the face to calculate the centroid of.
+/// * `density` - the density to weight the region by.
+///
+/// # Panics
+/// If the face index is invalid.
+pub fn weighted_face_centroid<D: Density + ?Sized>(
+    voronoi: &Diagram,
+    face: FaceKey,
+    density: &D,
+) -> cgmath::Point2<f64> {
+    let site = voronoi.get_face_point(face);
+    let n = QUADRATURE_SUBDIVISIONS;
+    let mut mass = 0.0;
+    let mut acc = cgmath::Vector2::new(0.0, 0.0);
+    for edge in voronoi.outer_edge_iter(face) {
+        let origin = voronoi.get_half_edge_origin_point(edge);
+        let destination =
+            voronoi.get_vertex_point(voronoi.get_half_edge_destination(edge).unwrap());
+        let u = (origin - site) / n as f64;
+        let v = (destination - site) / n as f64;
+        let area = vector2::get_det(u, v).abs() * 0.5;
+        let mut sample = |centroid: cgmath::Point2<f64>| {
+            let weight = density.density(centroid) * area;
+            mass += weight;
+            acc += (centroid - site) * weight;
+        };
+        for i in 0..n {
+            for j in 0..n - i {
+                // Each triangle pointing the same way as the whole triangle, and the one pointing
+                // the other way beside it unless it is along the edge
+                let (i, j) = (i as f64, j as f64);
+                sample(site + u * (i + 1.0 / 3.0) + v * (j + 1.0 / 3.0));
+                if i + j + 1.0 < n as f64 {
+                    sample(site + u * (i + 2.0 / 3.0) + v * (j + 2.0 / 3.0));
+                }
+            }
+        }
+    }
+    if mass <= 0.0 {
+        return voronoi.face_centroid(face);
+    }
+    site + acc / mass
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::random_points;
+    use cgmath::MetricSpace;
+
+    #[test]
+    fn weighted_face_centroid_test() {
+        let voronoi = build_voronoi(&random_points(16, 50));
+        for face in voronoi.get_face_indices() {
+            // A constant density gives the unweighted centroid
+            let centroid = weighted_face_centroid(&voronoi, face, &|_| 2.0);
+            assert!(centroid.distance(voronoi.face_centroid(face)) < 1e-12);
+
+            // A density increasing to the right moves it right
+            let centroid =
+                weighted_face_centroid(&voronoi, face, &|point: cgmath::Point2<f64>| point.x);
+            assert!(centroid.x > voronoi.face_centroid(face).x);
+        }
+    }
+
+    #[test]
+    fn image_density_test() {
+        let density = ImageDensity::from_grayscale(2, 2, &[0, 255, 255, 0]);
+        assert_eq!(density.density(cgmath::Point2::new(0.0, 0.0)), 0.0);
+        assert_eq!(density.density(cgmath::Point2::new(0.75, 0.25)), 1.0);
+        assert_eq!(density.density(cgmath::Point2::new(0.25, 1.0)), 1.0);
+        assert_eq!(density.density(cgmath::Point2::new(0.5, 0.5)), 0.5);
+        assert_eq!(density.density(cgmath::Point2::new(0.5, 0.25)), 0.5);
+
+        let density = density.inverted();
+        assert_eq!(density.density(cgmath::Point2::new(0.0, 0.0)), 1.0);
+        assert_eq!(density.density(cgmath::Point2::new(1.0, 0.0)), 0.0);
+    }
+}
@@ -1,6 +1,10 @@
 use super::*;
 use cgmath::EuclideanSpace;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use slotmap::{new_key_type, SlotMap};
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
 
 new_key_type! { pub struct VertexKey; }
 new_key_type! { pub struct HalfEdgeKey; }
@@ -10,7 +14,9 @@ new_key_type! { pub struct FaceKey; }
 ///
 /// Consists only of the points at which the vertex is located.
 #[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 struct Vertex {
+    #[cfg_attr(feature = "serde", serde(with = "Point2Def"))]
     point: cgmath::Point2<f64>,
 }
 
@@ -32,6 +38,7 @@ impl Vertex {
 /// edge's destination is the origin of this one and the next half edge's origin is the destination
 /// of this one.
 #[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 struct HalfEdge {
     origin: Option<VertexKey>,
     destination: Option<VertexKey>,
@@ -60,7 +67,9 @@ impl HalfEdge {
 /// all the part of the plane closer to take point than any other. It all holds the index of a
 /// single bordering half edge.
 #[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 struct Face {
+    #[cfg_attr(feature = "serde", serde(with = "Point2Def"))]
     point: cgmath::Point2<f64>,
     outer_component: Option<HalfEdgeKey>,
 }
@@ -101,13 +110,56 @@ impl<'a> Iterator for EdgeIterator<'a> {
 /// A diagram represented by a doubly connected edge list.
 /// At it's most basic this is a struct that contains the Faces, Vertices and Half Edges that define
 /// the diagram and the methods to manipulate and access them.
+///
+/// With the `serde` feature the diagram can be serialized along with the keys of its faces, half
+/// edges and vertices, so keys taken from it remain valid for the deserialized diagram. The links
+/// between the half edges are checked when deserializing, failing if a key refers to nothing,
+/// twins and neighbouring half edges do not agree or the edges of a face do not form a loop.
 #[derive(Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(try_from = "DiagramData")
+)]
 pub struct Diagram {
     faces: SlotMap<FaceKey, Face>,
     vertices: SlotMap<VertexKey, Vertex>,
     half_edges: SlotMap<HalfEdgeKey, HalfEdge>,
 }
 
+/// Serializes points as their coordinates.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "cgmath::Point2<f64>")]
+struct Point2Def {
+    x: f64,
+    y: f64,
+}
+
+/// A diagram as it is deserialized, before it has been checked.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct DiagramData {
+    faces: SlotMap<FaceKey, Face>,
+    vertices: SlotMap<VertexKey, Vertex>,
+    half_edges: SlotMap<HalfEdgeKey, HalfEdge>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<DiagramData> for Diagram {
+    type Error = String;
+
+    fn try_from(data: DiagramData) -> Result<Self, Self::Error> {
+        let diagram = Diagram {
+            faces: data.faces,
+            vertices: data.vertices,
+            half_edges: data.half_edges,
+        };
+        diagram.validate()?;
+        Ok(diagram)
+    }
+}
+
 impl Diagram {
     /// Constructs a new empty diagram
     pub fn new() -> Self {
@@ -125,11 +177,64 @@ impl Diagram {
         self.faces.insert(Face::new(point));
     }
 
+    /// Removes a face from the diagram.
+    ///
+    /// # Remarks
+    /// This does not remove the half edges incident to the face, these should be removed first.
+    ///
+    /// # Arguments
+    /// * `face` - the index of the face to remove.
+    pub(crate) fn remove_face(&mut self, face: FaceKey) {
+        self.faces.remove(face);
+    }
+
+    /// Replaces the faces of the diagram with one face for each of the supplied points, removing
+    /// every vertex and half edge.
+    ///
+    /// If the diagram already has one face per point the existing face indices are kept and their
+    /// points are replaced in order.
+    /// # Arguments
+    /// * `points` - the points of the faces.
+    pub(crate) fn set_face_points(&mut self, points: &[cgmath::Point2<f64>]) {
+        self.clear_edges();
+        if self.faces.len() == points.len() {
+            for ((_, face), &point) in self.faces.iter_mut().zip(points) {
+                face.point = point;
+            }
+        } else {
+            // Clearing the faces would reuse their slots in reverse order, so start afresh to
+            // keep the faces in the order of the points
+            self.faces = SlotMap::with_capacity_and_key(points.len());
+            for &point in points {
+                self.add_face(point);
+            }
+        }
+    }
+
+    /// Removes every vertex and half edge from the diagram, leaving only the faces.
+    pub(crate) fn clear_edges(&mut self) {
+        self.vertices.clear();
+        self.half_edges.clear();
+        for (_, face) in self.faces.iter_mut() {
+            face.outer_component = None;
+        }
+    }
+
     /// Returns the index of every face in the diagram
     pub fn get_face_indices(&self) -> Vec<FaceKey> {
         self.faces.iter().map(|(index, _)| index).collect()
     }
 
+    /// Returns the index of every vertex in the diagram
+    pub fn get_vertex_indices(&self) -> Vec<VertexKey> {
+        self.vertices.iter().map(|(index, _)| index).collect()
+    }
+
+    /// Returns the index of every half edge in the diagram
+    pub fn get_half_edge_indices(&self) -> Vec<HalfEdgeKey> {
+        self.half_edges.iter().map(|(index, _)| index).collect()
+    }
+
     /// Returns the location of every vertex in the diagram
     pub fn get_vertex_points(&self) -> Vec<cgmath::Point2<f64>> {
         self.vertices
@@ -143,6 +248,9 @@ impl Diagram {
     pub fn get_edge_vertices(&self) -> Vec<(VertexKey, VertexKey)> {
         let mut edges = vec![];
         for face in self.get_face_indices() {
+            if self.get_face_outer_component(face).is_none() {
+                continue;
+            }
             for edge in self.outer_edge_iter(face) {
                 if self.get_half_edge_origin(edge).is_some()
                     && self.get_half_edge_destination(edge).is_some()
@@ -160,6 +268,9 @@ impl Diagram {
     pub fn get_edge_endpoints(&self) -> Vec<(cgmath::Point2<f64>, cgmath::Point2<f64>)> {
         let mut edges = vec![];
         for face in self.get_face_indices() {
+            if self.get_face_outer_component(face).is_none() {
+                continue;
+            }
             for edge in self.outer_edge_iter(face) {
                 if self.get_half_edge_origin(edge).is_some()
                     && self.get_half_edge_destination(edge).is_some()
@@ -236,7 +347,19 @@ impl Diagram {
         self.half_edges.remove(half_edge);
     }
 
-    fn set_half_edge_twin(&mut self, half_edge: HalfEdgeKey, twin_half_edge: Option<HalfEdgeKey>) {
+    /// Sets the twin of a half edge.
+    ///
+    /// # Arguments
+    /// * `half_edge` - the index of the half edge.
+    /// * `twin_half_edge` - the index of the half edge running in the opposite direction.
+    ///
+    /// # Panics
+    /// If the half edge index is invalid.
+    pub(crate) fn set_half_edge_twin(
+        &mut self,
+        half_edge: HalfEdgeKey,
+        twin_half_edge: Option<HalfEdgeKey>,
+    ) {
         let half_edge = self.half_edges.get_mut(half_edge).unwrap();
         half_edge.twin = twin_half_edge;
     }
@@ -265,6 +388,22 @@ impl Diagram {
         site.point
     }
 
+    /// Moves the point associated with a face.
+    ///
+    /// # Remarks
+    /// This does not update the region of the face, see `move_site` for that.
+    ///
+    /// # Arguments
+    /// * `face` - the index of the face.
+    /// * `point` - the new point of the face.
+    ///
+    /// # Panics
+    /// If the face index in invalid
+    pub(crate) fn set_face_point(&mut self, face: FaceKey, point: cgmath::Point2<f64>) {
+        let site = self.faces.get_mut(face).unwrap();
+        site.point = point;
+    }
+
     /// Returns the outer half edge associated with a face.
     ///
     /// # Arguments
@@ -437,6 +576,18 @@ impl Diagram {
         vertex.point
     }
 
+    /// Moves a vertex to a new point.
+    /// # Arguments
+    /// * `vertex` - the index of the vertex to move.
+    /// * `point` - the new location of the vertex.
+    ///
+    /// # Panics
+    /// If the vertex index is invalid.
+    pub(crate) fn set_vertex_point(&mut self, vertex: VertexKey, point: cgmath::Point2<f64>) {
+        let vertex = self.vertices.get_mut(vertex).unwrap();
+        vertex.point = point;
+    }
+
     /// Calculates the centroid or geometric center of a face in the diagram.
     ///
     /// This is done by taking the arithmetic mean position of all the points around the face.
@@ -455,6 +606,24 @@ impl Diagram {
         acc * (1.0 / c as f64)
     }
 
+    /// Calculates the centroid of the region of a face in the diagram.
+    ///
+    /// Unlike `calculate_face_center` this is weighted by area, so it does not move towards the
+    /// parts of the region that have more vertices. A region with no area falls back to the mean
+    /// of its vertices.
+    /// # Arguments
+    /// * `face` - the index of the face to calculate the centroid of.
+    ///
+    /// # Panics
+    /// If the face index is invalid.
+    pub fn face_centroid(&self, face: FaceKey) -> cgmath::Point2<f64> {
+        let polygon: Vec<cgmath::Point2<f64>> = self
+            .outer_edge_iter(face)
+            .map(|edge| self.get_half_edge_origin_point(edge))
+            .collect();
+        vector2::get_polygon_centroid(&polygon)
+    }
+
     /// Calculates the area of a face in the diagram.
     /// # Arguments
     /// * `face` - the index of the face to calculate the area of.
@@ -487,4 +656,345 @@ impl Diagram {
         }
         false
     }
+
+    /// Returns the face whose region contains a point, or `None` if the point is outside of the
+    /// diagram or it has no faces. A point on an edge may be given either face.
+    ///
+    /// This walks across the diagram from its first face. To locate many points build a
+    /// `FaceLocator` once, or use `locate_all`.
+    /// # Arguments
+    /// * `point` - the point to locate.
+    pub fn locate(&self, point: cgmath::Point2<f64>) -> Option<FaceKey> {
+        if !locator::is_in_unit_square(point) {
+            return None;
+        }
+        let start = self.faces.keys().next()?;
+        Some(cell::find_nearest_face(self, start, point))
+    }
+
+    /// Returns the face whose region contains each of a series of points, as given by `locate`.
+    /// # Arguments
+    /// * `points` - the points to locate.
+    pub fn locate_all(&self, points: &[cgmath::Point2<f64>]) -> Vec<Option<FaceKey>> {
+        FaceLocator::new(self).locate_all(points)
+    }
+
+    /// Returns the faces whose points are closest to a point along with their distances from it,
+    /// sorted by distance, or nothing if the point is outside of the diagram.
+    ///
+    /// The search spreads out across neighbouring faces from the face whose region contains the
+    /// point, so it only visits a few more faces than it returns.
+    /// # Arguments
+    /// * `point` - the point to find the closest faces to.
+    /// * `count` - the most faces to return.
+    pub fn nearest_faces(&self, point: cgmath::Point2<f64>, count: usize) -> Vec<(FaceKey, f64)> {
+        match self.locate(point) {
+            Some(start) => locator::nearest_faces_from(self, start, point, count),
+            None => vec![],
+        }
+    }
+
+    /// Returns the faces whose points are within a distance of a point along with their distances
+    /// from it, sorted by distance, or nothing if the point is outside of the diagram.
+    /// # Arguments
+    /// * `point` - the point to find the faces around.
+    /// * `radius` - the furthest distance of the faces to return.
+    pub fn faces_within_radius(
+        &self,
+        point: cgmath::Point2<f64>,
+        radius: f64,
+    ) -> Vec<(FaceKey, f64)> {
+        match self.locate(point) {
+            Some(start) => locator::faces_within_radius_from(self, start, point, radius),
+            None => vec![],
+        }
+    }
+
+    /// Checks that every key in the diagram refers to an element of it and that the half edges
+    /// are linked consistently, returning a description of the first problem found.
+    ///
+    /// The half edges around each face must form a closed loop of edges with origins, so the
+    /// region of every face can be walked with `outer_edge_iter`.
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        for (key, face) in self.faces.iter() {
+            if let Some(outer_component) = face.outer_component {
+                let invalid = || Err(format!("Invalid outer component of face {:?}", key));
+                let mut current = outer_component;
+                // A loop can be no longer than the number of half edges
+                for _ in 0..self.half_edges.len() {
+                    match self.half_edges.get(current) {
+                        Some(half_edge)
+                            if half_edge.incident_face == Some(key)
+                                && half_edge.origin.is_some() =>
+                        {
+                            match half_edge.next {
+                                Some(next) => current = next,
+                                None => return invalid(),
+                            }
+                        }
+                        _ => return invalid(),
+                    }
+                    if current == outer_component {
+                        break;
+                    }
+                }
+                if current != outer_component {
+                    return invalid();
+                }
+            }
+        }
+        for (key, half_edge) in self.half_edges.iter() {
+            let invalid = |field: &str| Err(format!("Invalid {} of half edge {:?}", field, key));
+            if matches!(half_edge.origin, Some(origin) if !self.vertices.contains_key(origin)) {
+                return invalid("origin");
+            }
+            if matches!(half_edge.destination, Some(destination) if !self.vertices.contains_key(destination))
+            {
+                return invalid("destination");
+            }
+            if matches!(half_edge.incident_face, Some(face) if !self.faces.contains_key(face)) {
+                return invalid("incident face");
+            }
+            if let Some(twin) = half_edge.twin {
+                match self.half_edges.get(twin) {
+                    Some(other)
+                        if twin != key
+                            && other.twin == Some(key)
+                            && other.origin == half_edge.destination
+                            && other.destination == half_edge.origin
+                            && (other.incident_face.is_none()
+                                || other.incident_face != half_edge.incident_face) => {}
+                    _ => return invalid("twin"),
+                }
+            }
+            if let Some(next) = half_edge.next {
+                match self.half_edges.get(next) {
+                    Some(other)
+                        if other.prev == Some(key)
+                            && other.incident_face == half_edge.incident_face
+                            && other.origin == half_edge.destination => {}
+                    _ => return invalid("next"),
+                }
+            }
+            if let Some(prev) = half_edge.prev {
+                match self.half_edges.get(prev) {
+                    Some(other) if other.next == Some(key) => {}
+                    _ => return invalid("prev"),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::random_points;
+
+    #[test]
+    fn face_centroid_test() {
+        // Two faces split the box in half, the centroids do not depend on where the sites are
+        let voronoi =
+            build_voronoi(&[cgmath::Point2::new(0.2, 0.3), cgmath::Point2::new(0.2, 0.7)]);
+        let faces = voronoi.get_face_indices();
+        let centroid = voronoi.face_centroid(faces[0]);
+        assert!((centroid.x - 0.5).abs() < 1e-12);
+        assert!((centroid.y - 0.25).abs() < 1e-12);
+        let centroid = voronoi.face_centroid(faces[1]);
+        assert!((centroid.x - 0.5).abs() < 1e-12);
+        assert!((centroid.y - 0.75).abs() < 1e-12);
+
+        // The centroids of the regions weighted by their areas give the centroid of the box
+        let voronoi = build_voronoi(&random_points(14, 100));
+        let mut acc = cgmath::Vector2::new(0.0, 0.0);
+        for face in voronoi.get_face_indices() {
+            acc += voronoi.face_centroid(face).to_vec() * voronoi.get_face_area(face);
+        }
+        assert!((acc.x - 0.5).abs() < 1e-9);
+        assert!((acc.y - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn locate_test() {
+        let voronoi = build_voronoi(&random_points(15, 200));
+        let queries = random_points(16, 100);
+        let faces = voronoi.locate_all(&queries);
+        for (&query, face) in queries.iter().zip(faces) {
+            assert_eq!(voronoi.locate(query), face);
+            // The point is inside the region of the face
+            let face = face.unwrap();
+            for edge in voronoi.outer_edge_iter(face) {
+                let origin = voronoi.get_half_edge_origin_point(edge);
+                let destination =
+                    voronoi.get_vertex_point(voronoi.get_half_edge_destination(edge).unwrap());
+                let side = vector2::get_det(destination - origin, query - origin);
+                assert!(side * get_winding(&voronoi, face) >= -1e-12);
+            }
+        }
+        assert_eq!(voronoi.locate(cgmath::Point2::new(-0.5, 0.5)), None);
+        assert_eq!(Diagram::new().locate(cgmath::Point2::new(0.5, 0.5)), None);
+    }
+
+    /// Returns the sign of the signed area of the region of a face.
+    fn get_winding(voronoi: &Diagram, face: FaceKey) -> f64 {
+        voronoi
+            .outer_edge_iter(face)
+            .map(|edge| {
+                let origin = voronoi.get_half_edge_origin_point(edge);
+                let destination =
+                    voronoi.get_vertex_point(voronoi.get_half_edge_destination(edge).unwrap());
+                origin.x * destination.y - destination.x * origin.y
+            })
+            .sum::<f64>()
+            .signum()
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn validate_test() {
+        // Built diagrams are valid, including those with sites in rows and columns
+        let mut grid = vec![];
+        for row in 0..5 {
+            for column in 0..5 {
+                grid.push(cgmath::Point2::new(
+                    (column as f64 + 0.5) / 5.0,
+                    (row as f64 + 0.5) / 5.0,
+                ));
+            }
+        }
+        for points in &[
+            random_points(17, 300),
+            grid,
+            vec![cgmath::Point2::new(0.5, 0.5)],
+        ] {
+            assert_eq!(build_voronoi(points).validate(), Ok(()));
+        }
+
+        let voronoi = build_voronoi(&random_points(18, 50));
+        let data = || DiagramData {
+            faces: voronoi.faces.clone(),
+            vertices: voronoi.vertices.clone(),
+            half_edges: voronoi.half_edges.clone(),
+        };
+        assert!(Diagram::try_from(data()).is_ok());
+
+        let faces = voronoi.get_face_indices();
+        let edge = voronoi.get_face_outer_component(faces[0]).unwrap();
+        let mut broken = data();
+        broken
+            .vertices
+            .remove(voronoi.get_half_edge_origin(edge).unwrap());
+        assert!(Diagram::try_from(broken).is_err());
+
+        let mut broken = data();
+        broken.half_edges[edge].twin = Some(edge);
+        assert!(Diagram::try_from(broken).is_err());
+
+        let mut broken = data();
+        broken.half_edges[edge].next = voronoi.get_half_edge_prev(edge);
+        assert!(Diagram::try_from(broken).is_err());
+
+        let mut broken = data();
+        broken.faces[faces[0]].outer_component = voronoi.get_face_outer_component(faces[1]);
+        assert!(Diagram::try_from(broken).is_err());
+
+        // An edge of a face without an origin
+        let mut broken = data();
+        let next = voronoi.get_half_edge_next(edge).unwrap();
+        broken.half_edges[next].origin = None;
+        broken.half_edges[edge].destination = None;
+        if let Some(twin) = voronoi.get_half_edge_twin(next) {
+            broken.half_edges[twin].destination = None;
+        }
+        if let Some(twin) = voronoi.get_half_edge_twin(edge) {
+            broken.half_edges[twin].origin = None;
+        }
+        assert!(Diagram::try_from(broken).is_err());
+
+        // The next edge starting somewhere other than the end of the edge
+        let mut broken = data();
+        broken.half_edges[edge].destination = voronoi.get_half_edge_origin(edge);
+        assert!(Diagram::try_from(broken).is_err());
+
+        // Twins on the same face
+        let twin = voronoi.get_half_edge_twin(edge).unwrap();
+        let mut broken = data();
+        broken.half_edges[twin].incident_face = Some(faces[0]);
+        assert!(Diagram::try_from(broken).is_err());
+
+        // Edges of a face that loop without coming back to its outer component
+        let mut broken = data();
+        let outer_edges: Vec<HalfEdgeKey> = voronoi.outer_edge_iter(faces[0]).collect();
+        let last = outer_edges[outer_edges.len() - 1];
+        broken.half_edges[last].next = Some(next);
+        broken.half_edges[next].prev = Some(last);
+        assert!(Diagram::try_from(broken).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_test() {
+        let voronoi = build_voronoi(&random_points(19, 100));
+        let json = serde_json::to_string(&voronoi).unwrap();
+        let read: Diagram = serde_json::from_str(&json).unwrap();
+
+        // The keys and the links between them are kept
+        let faces = voronoi.get_face_indices();
+        assert_eq!(read.get_face_indices(), faces);
+        for &face in &faces {
+            assert_eq!(read.get_face_point(face), voronoi.get_face_point(face));
+            assert_eq!(
+                read.get_face_outer_component(face),
+                voronoi.get_face_outer_component(face)
+            );
+        }
+        let vertices = voronoi.get_vertex_indices();
+        assert_eq!(read.get_vertex_indices(), vertices);
+        for vertex in vertices {
+            assert_eq!(
+                read.get_vertex_point(vertex),
+                voronoi.get_vertex_point(vertex)
+            );
+        }
+        let half_edges = voronoi.get_half_edge_indices();
+        assert_eq!(read.get_half_edge_indices(), half_edges);
+        for half_edge in half_edges {
+            assert_eq!(
+                read.get_half_edge_origin(half_edge),
+                voronoi.get_half_edge_origin(half_edge)
+            );
+            assert_eq!(
+                read.get_half_edge_destination(half_edge),
+                voronoi.get_half_edge_destination(half_edge)
+            );
+            assert_eq!(
+                read.get_half_edge_incident_face(half_edge),
+                voronoi.get_half_edge_incident_face(half_edge)
+            );
+            assert_eq!(
+                read.get_half_edge_twin(half_edge),
+                voronoi.get_half_edge_twin(half_edge)
+            );
+            assert_eq!(
+                read.get_half_edge_next(half_edge),
+                voronoi.get_half_edge_next(half_edge)
+            );
+            assert_eq!(
+                read.get_half_edge_prev(half_edge),
+                voronoi.get_half_edge_prev(half_edge)
+            );
+        }
+
+        // Broken diagrams are rejected
+        let edge = voronoi.get_face_outer_component(faces[0]).unwrap();
+        let mut broken = Diagram {
+            faces: voronoi.faces.clone(),
+            vertices: voronoi.vertices.clone(),
+            half_edges: voronoi.half_edges.clone(),
+        };
+        broken.half_edges[edge].next = None;
+        let json = serde_json::to_string(&broken).unwrap();
+        assert!(serde_json::from_str::<Diagram>(&json).is_err());
+    }
 }
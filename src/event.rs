@@ -15,25 +15,29 @@ pub enum EventType {
 #[derive(Debug)]
 pub struct Event {
     y: f64,
+    x: f64,
     event_type: EventType,
 }
 
+// Events at the same y are ordered by x, so sites along a horizontal line are swept from left to
+// right
 impl PartialOrd for Event {
     fn partial_cmp(&self, other: &Event) -> Option<Ordering> {
-        self.y.partial_cmp(&other.y)
+        (self.y, self.x).partial_cmp(&(other.y, other.x))
     }
 }
 
 impl PartialEq for Event {
     fn eq(&self, other: &Event) -> bool {
-        self.y == other.y
+        self.y == other.y && self.x == other.x
     }
 }
 
 impl Event {
-    pub fn site_event(y: f64, face: FaceKey) -> Self {
+    pub fn site_event(point: cgmath::Point2<f64>, face: FaceKey) -> Self {
         Event {
-            y,
+            y: point.y,
+            x: point.x,
             event_type: EventType::SiteEvent { face },
         }
     }
@@ -41,6 +45,7 @@ impl Event {
     pub fn circle_event(y: f64, point: cgmath::Point2<f64>, arc: NodeKey) -> Self {
         Event {
             y,
+            x: point.x,
             event_type: EventType::CircleEvent { point, arc },
         }
     }
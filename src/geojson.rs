@@ -0,0 +1,585 @@
+use super::*;
+use std::io::{self, Read, Write};
+
+/// Arrays and objects nested deeper than this are rejected when reading.
+const MAX_DEPTH: usize = 128;
+
+/// The value of a property of a feature written as GeoJSON.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GeoJsonValue {
+    Null,
+    Bool(bool),
+    /// A number, infinite or NaN numbers are written as null as JSON can not hold them.
+    Number(f64),
+    String(String),
+}
+
+impl From<bool> for GeoJsonValue {
+    fn from(value: bool) -> Self {
+        GeoJsonValue::Bool(value)
+    }
+}
+
+impl From<f64> for GeoJsonValue {
+    fn from(value: f64) -> Self {
+        GeoJsonValue::Number(value)
+    }
+}
+
+impl From<&str> for GeoJsonValue {
+    fn from(value: &str) -> Self {
+        GeoJsonValue::String(value.to_string())
+    }
+}
+
+impl From<String> for GeoJsonValue {
+    fn from(value: String) -> Self {
+        GeoJsonValue::String(value)
+    }
+}
+
+/// Writes a diagram as a GeoJSON FeatureCollection, as described in
+/// [RFC 7946](https://tools.ietf.org/html/rfc7946).
+///
+/// Each face is a Feature whose id is its position in `get_face_indices`, with the region as a
+/// Polygon and the properties `site`, holding the coordinates of the point of the face, and
+/// `area`. Faces without a region, such as the face of a diagram with a single site, have a null
+/// geometry and area.
+///
+/// The coordinates are written as they are in the diagram, with x as the longitude and y as the
+/// latitude. The rings wind counter-clockwise as the RFC asks, as described in
+/// [`get_counter_clockwise_ring`].
+/// # Arguments
+/// * `voronoi` - the diagram to write.
+/// * `properties` - further properties of each face, written after `site` and `area`.
+/// * `writer` - where to write the collection.
+pub fn write_geojson<W, F>(voronoi: &Diagram, properties: F, mut writer: W) -> io::Result<()>
+where
+    W: Write,
+    F: Fn(FaceKey) -> Vec<(String, GeoJsonValue)>,
+{
+    write!(writer, r#"{{"type":"FeatureCollection","features":["#)?;
+    for (i, face) in voronoi.get_face_indices().into_iter().enumerate() {
+        if i > 0 {
+            write!(writer, ",")?;
+        }
+        write!(writer, r#"{{"type":"Feature","id":{},"geometry":"#, i)?;
+        let ring = get_counter_clockwise_ring(voronoi, face);
+        if !ring.is_empty() {
+            write!(writer, r#"{{"type":"Polygon","coordinates":[["#)?;
+            for (j, &point) in ring.iter().enumerate() {
+                if j > 0 {
+                    write!(writer, ",")?;
+                }
+                write_position(&mut writer, point)?;
+            }
+            write!(writer, "]]}}")?;
+        } else {
+            write!(writer, "null")?;
+        }
+
+        write!(writer, r#","properties":{{"site":"#)?;
+        write_position(&mut writer, voronoi.get_face_point(face))?;
+        write!(writer, r#","area":"#)?;
+        if voronoi.get_face_outer_component(face).is_some() {
+            write_number(&mut writer, voronoi.get_face_area(face))?;
+        } else {
+            write!(writer, "null")?;
+        }
+        for (name, value) in properties(face) {
+            write!(writer, ",")?;
+            write_string(&mut writer, &name)?;
+            write!(writer, ":")?;
+            match value {
+                GeoJsonValue::Null => write!(writer, "null")?,
+                GeoJsonValue::Bool(value) => write!(writer, "{}", value)?,
+                GeoJsonValue::Number(value) => write_number(&mut writer, value)?,
+                GeoJsonValue::String(value) => write_string(&mut writer, &value)?,
+            }
+        }
+        write!(writer, "}}}}")?;
+    }
+    writeln!(writer, "]}}")
+}
+
+/// Returns a diagram as a GeoJSON FeatureCollection, see `write_geojson`.
+/// # Arguments
+/// * `voronoi` - the diagram to write.
+/// * `properties` - further properties of each face, written after `site` and `area`.
+pub fn to_geojson<F: Fn(FaceKey) -> Vec<(String, GeoJsonValue)>>(
+    voronoi: &Diagram,
+    properties: F,
+) -> String {
+    let mut geojson = vec![];
+    write_geojson(voronoi, properties, &mut geojson).unwrap();
+    String::from_utf8(geojson).unwrap()
+}
+
+/// Reads the positions of the Points and MultiPoints in a GeoJSON document, in the order they
+/// appear.
+///
+/// The document may be a FeatureCollection, a Feature, a GeometryCollection or a single geometry.
+/// Other geometries are skipped, and only the first two coordinates of each position are kept.
+/// The positions are returned as they are, so they need scaling into the unit square before
+/// building a diagram from them.
+/// # Arguments
+/// * `reader` - where to read the document from.
+pub fn read_geojson_sites<R: Read>(mut reader: R) -> io::Result<Vec<cgmath::Point2<f64>>> {
+    let mut text = String::new();
+    reader.read_to_string(&mut text)?;
+    let mut parser = Parser {
+        chars: text.chars().peekable(),
+    };
+    let document = parser.parse_document()?;
+    let mut sites = vec![];
+    collect_sites(&document, &mut sites)?;
+    Ok(sites)
+}
+
+fn write_position<W: Write>(writer: &mut W, point: cgmath::Point2<f64>) -> io::Result<()> {
+    write!(writer, "[")?;
+    write_number(writer, point.x)?;
+    write!(writer, ",")?;
+    write_number(writer, point.y)?;
+    write!(writer, "]")
+}
+
+fn write_number<W: Write>(writer: &mut W, value: f64) -> io::Result<()> {
+    if value.is_finite() {
+        write!(writer, "{}", value)
+    } else {
+        write!(writer, "null")
+    }
+}
+
+fn write_string<W: Write>(writer: &mut W, value: &str) -> io::Result<()> {
+    write!(writer, "\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => write!(writer, "\\\"")?,
+            '\\' => write!(writer, "\\\\")?,
+            '\n' => write!(writer, "\\n")?,
+            '\r' => write!(writer, "\\r")?,
+            '\t' => write!(writer, "\\t")?,
+            c if (c as u32) < 0x20 => write!(writer, "\\u{:04x}", c as u32)?,
+            c => write!(writer, "{}", c)?,
+        }
+    }
+    write!(writer, "\"")
+}
+
+/// A JSON value read from a document.
+#[derive(Debug, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn get(&self, name: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members
+                .iter()
+                .find(|(member, _)| member == name)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    fn elements(&self, name: &str) -> io::Result<&[Json]> {
+        match self.get(name) {
+            Some(Json::Array(elements)) => Ok(elements),
+            _ => Err(invalid_geojson(&format!("Expected an array of {}", name))),
+        }
+    }
+}
+
+/// Adds the positions of the points in a GeoJSON object to a list.
+fn collect_sites(object: &Json, sites: &mut Vec<cgmath::Point2<f64>>) -> io::Result<()> {
+    let object_type = match object.get("type") {
+        Some(Json::String(object_type)) => object_type.as_str(),
+        _ => return Err(invalid_geojson("Expected an object with a type")),
+    };
+    match object_type {
+        "FeatureCollection" => {
+            for feature in object.elements("features")? {
+                collect_sites(feature, sites)?;
+            }
+        }
+        "Feature" => match object.get("geometry") {
+            Some(Json::Null) => {}
+            Some(geometry) => collect_sites(geometry, sites)?,
+            None => return Err(invalid_geojson("Expected a feature with a geometry")),
+        },
+        "GeometryCollection" => {
+            for geometry in object.elements("geometries")? {
+                collect_sites(geometry, sites)?;
+            }
+        }
+        "Point" => sites.push(parse_position(object.get("coordinates"))?),
+        "MultiPoint" => {
+            for position in object.elements("coordinates")? {
+                sites.push(parse_position(Some(position))?);
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn parse_position(position: Option<&Json>) -> io::Result<cgmath::Point2<f64>> {
+    match position {
+        Some(Json::Array(coordinates)) => match coordinates[..] {
+            [Json::Number(x), Json::Number(y), ..] => Ok(cgmath::Point2::new(x, y)),
+            _ => Err(invalid_geojson("Expected a position of two numbers")),
+        },
+        _ => Err(invalid_geojson("Expected a position")),
+    }
+}
+
+/// A recursive descent parser for JSON documents.
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    fn parse_document(&mut self) -> io::Result<Json> {
+        let value = self.parse_value(0)?;
+        self.skip_whitespace();
+        match self.chars.next() {
+            None => Ok(value),
+            Some(c) => Err(invalid_geojson(&format!("Unexpected {}", c))),
+        }
+    }
+
+    fn parse_value(&mut self, depth: usize) -> io::Result<Json> {
+        if depth > MAX_DEPTH {
+            return Err(invalid_geojson("Too deeply nested"));
+        }
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some('{') => {
+                self.chars.next();
+                let mut members = vec![];
+                if !self.consume('}') {
+                    loop {
+                        self.skip_whitespace();
+                        let name = self.parse_string()?;
+                        self.expect(':')?;
+                        members.push((name, self.parse_value(depth + 1)?));
+                        if self.consume('}') {
+                            break;
+                        }
+                        self.expect(',')?;
+                    }
+                }
+                Ok(Json::Object(members))
+            }
+            Some('[') => {
+                self.chars.next();
+                let mut elements = vec![];
+                if !self.consume(']') {
+                    loop {
+                        elements.push(self.parse_value(depth + 1)?);
+                        if self.consume(']') {
+                            break;
+                        }
+                        self.expect(',')?;
+                    }
+                }
+                Ok(Json::Array(elements))
+            }
+            Some('"') => Ok(Json::String(self.parse_string()?)),
+            Some(c) if *c == '-' || c.is_ascii_digit() => {
+                let mut number = String::new();
+                while let Some(&c) = self.chars.peek() {
+                    if !(c.is_ascii_digit() || "+-.eE".contains(c)) {
+                        break;
+                    }
+                    number.push(c);
+                    self.chars.next();
+                }
+                number
+                    .parse()
+                    .map(Json::Number)
+                    .map_err(|_| invalid_geojson(&format!("Invalid number {}", number)))
+            }
+            Some(c) if c.is_ascii_alphabetic() => {
+                let mut word = String::new();
+                while let Some(&c) = self.chars.peek() {
+                    if !c.is_ascii_alphabetic() {
+                        break;
+                    }
+                    word.push(c);
+                    self.chars.next();
+                }
+                match word.as_str() {
+                    "null" => Ok(Json::Null),
+                    "true" => Ok(Json::Bool(true)),
+                    "false" => Ok(Json::Bool(false)),
+                    _ => Err(invalid_geojson(&format!("Unexpected {}", word))),
+                }
+            }
+            Some(c) => Err(invalid_geojson(&format!("Unexpected {}", c))),
+            None => Err(invalid_geojson("Unexpected end of document")),
+        }
+    }
+
+    fn parse_string(&mut self) -> io::Result<String> {
+        if self.chars.next() != Some('"') {
+            return Err(invalid_geojson("Expected a string"));
+        }
+        let mut string = String::new();
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(string),
+                Some('\\') => match self.chars.next() {
+                    Some('"') => string.push('"'),
+                    Some('\\') => string.push('\\'),
+                    Some('/') => string.push('/'),
+                    Some('b') => string.push('\u{8}'),
+                    Some('f') => string.push('\u{c}'),
+                    Some('n') => string.push('\n'),
+                    Some('r') => string.push('\r'),
+                    Some('t') => string.push('\t'),
+                    Some('u') => {
+                        let mut code = self.parse_hex()?;
+                        // Characters outside of the basic plane are escaped as surrogate pairs
+                        if (0xd800..0xdc00).contains(&code) {
+                            if self.chars.next() != Some('\\') || self.chars.next() != Some('u') {
+                                return Err(invalid_geojson("Invalid escape in string"));
+                            }
+                            let low = self.parse_hex()?;
+                            if !(0xdc00..0xe000).contains(&low) {
+                                return Err(invalid_geojson("Invalid escape in string"));
+                            }
+                            code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                        }
+                        string.push(std::char::from_u32(code).unwrap_or('\u{fffd}'));
+                    }
+                    _ => return Err(invalid_geojson("Invalid escape in string")),
+                },
+                Some(c) => string.push(c),
+                None => return Err(invalid_geojson("Unterminated string")),
+            }
+        }
+    }
+
+    fn parse_hex(&mut self) -> io::Result<u32> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self
+                .chars
+                .next()
+                .and_then(|c| c.to_digit(16))
+                .ok_or_else(|| invalid_geojson("Invalid escape in string"))?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.chars.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.chars.next();
+        }
+    }
+
+    /// Skips whitespace and then a character if it is next, returning whether it was.
+    fn consume(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&c) {
+            self.chars.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> io::Result<()> {
+        if self.consume(c) {
+            Ok(())
+        } else {
+            Err(invalid_geojson(&format!("Expected {}", c)))
+        }
+    }
+}
+
+fn invalid_geojson(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid GeoJSON: {}", message),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::random_points;
+
+    fn parse(text: &str) -> Json {
+        Parser {
+            chars: text.chars().peekable(),
+        }
+        .parse_document()
+        .unwrap()
+    }
+
+    fn number(value: &Json) -> f64 {
+        match value {
+            Json::Number(value) => *value,
+            _ => panic!("Expected a number, found {:?}", value),
+        }
+    }
+
+    #[test]
+    fn to_geojson_test() {
+        let points = random_points(70, 50);
+        let voronoi = build_voronoi(&points);
+        let faces = voronoi.get_face_indices();
+        let geojson = to_geojson(&voronoi, |face| {
+            vec![
+                ("name".to_string(), "a \"cell\"\n".into()),
+                ("border".to_string(), voronoi.is_face_on_border(face).into()),
+                ("nothing".to_string(), f64::NAN.into()),
+            ]
+        });
+
+        let document = parse(&geojson);
+        let features = document.elements("features").unwrap();
+        assert_eq!(features.len(), faces.len());
+        let mut total_area = 0.0;
+        for (i, (feature, &face)) in features.iter().zip(&faces).enumerate() {
+            assert_eq!(feature.get("id"), Some(&Json::Number(i as f64)));
+            let properties = feature.get("properties").unwrap();
+            let site = properties.elements("site").unwrap();
+            assert_eq!(number(&site[0]), points[i].x);
+            assert_eq!(number(&site[1]), points[i].y);
+            assert_eq!(
+                properties.get("name"),
+                Some(&Json::String("a \"cell\"\n".to_string()))
+            );
+            assert_eq!(
+                properties.get("border"),
+                Some(&Json::Bool(voronoi.is_face_on_border(face)))
+            );
+            assert_eq!(properties.get("nothing"), Some(&Json::Null));
+
+            // The ring is closed and winds counter-clockwise, enclosing the area of the face
+            let geometry = feature.get("geometry").unwrap();
+            assert_eq!(
+                geometry.get("type"),
+                Some(&Json::String("Polygon".to_string()))
+            );
+            let rings = geometry.elements("coordinates").unwrap();
+            assert_eq!(rings.len(), 1);
+            let ring: Vec<cgmath::Point2<f64>> = match &rings[0] {
+                Json::Array(positions) => positions
+                    .iter()
+                    .map(|position| parse_position(Some(position)).unwrap())
+                    .collect(),
+                _ => panic!("Expected a ring"),
+            };
+            assert_eq!(ring.len(), voronoi.outer_edge_iter(face).count() + 1);
+            assert_eq!(ring.first(), ring.last());
+            let signed_area: f64 = ring
+                .windows(2)
+                .map(|pair| pair[0].x * pair[1].y - pair[1].x * pair[0].y)
+                .sum::<f64>()
+                / 2.0;
+            let area = number(properties.get("area").unwrap());
+            assert!((signed_area - area).abs() < 1e-12);
+            assert!((area - voronoi.get_face_area(face)).abs() < 1e-12);
+            total_area += area;
+        }
+        assert!((total_area - 1.0).abs() < 1e-9);
+
+        // A single site has no region
+        let single = build_voronoi(&[cgmath::Point2::new(0.5, 0.5)]);
+        let document = parse(&to_geojson(&single, |_| vec![]));
+        let features = document.elements("features").unwrap();
+        assert_eq!(features[0].get("geometry"), Some(&Json::Null));
+        let properties = features[0].get("properties").unwrap();
+        assert_eq!(properties.get("area"), Some(&Json::Null));
+    }
+
+    #[test]
+    fn read_geojson_sites_test() {
+        let geojson = r#"
+            {
+                "type": "FeatureCollection",
+                "features": [
+                    {"type": "Feature", "geometry": {"type": "Point", "coordinates": [0.25, 0.5]},
+                     "properties": {"name": "café 😀", "empty": {}, "list": []}},
+                    {"type": "Feature", "geometry": null, "properties": null},
+                    {"type": "Feature", "properties": {}, "geometry": {
+                        "type": "GeometryCollection", "geometries": [
+                            {"type": "MultiPoint", "coordinates": [[1e-1, -2.5E1, 3], [4, 5]]},
+                            {"type": "LineString", "coordinates": [[0, 0], [1, 1]]}
+                        ]
+                    }}
+                ]
+            }
+        "#;
+        let sites = read_geojson_sites(geojson.as_bytes()).unwrap();
+        assert_eq!(
+            sites,
+            vec![
+                cgmath::Point2::new(0.25, 0.5),
+                cgmath::Point2::new(0.1, -25.0),
+                cgmath::Point2::new(4.0, 5.0),
+            ]
+        );
+        assert_eq!(
+            parse(r#""caf\u00e9 \ud83d\ude00""#),
+            Json::String("caf\u{e9} \u{1f600}".to_string())
+        );
+
+        let point = r#"{"type": "Point", "coordinates": [0.5, 0.75]}"#;
+        assert_eq!(
+            read_geojson_sites(point.as_bytes()).unwrap(),
+            vec![cgmath::Point2::new(0.5, 0.75)]
+        );
+
+        for invalid in &[
+            "",
+            "[1, 2]",
+            r#"{"type": "Point", "coordinates": [0.5]}"#,
+            r#"{"type": "Point", "coordinates": [0.5, 0.5]"#,
+            r#"{"type": "Point", "coordinates": [0.5, 0.5]} x"#,
+            r#"{"type": "FeatureCollection", "features": {}}"#,
+            r#"{"type": "Feature"}"#,
+            r#"{"type": "Point", "coordinates": [0.5, nul]}"#,
+            r#"{"type": "Point\"}"#,
+        ] {
+            let error = read_geojson_sites(invalid.as_bytes()).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+        let nested = "[".repeat(1000);
+        assert!(read_geojson_sites(nested.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn geojson_round_trip_test() {
+        // Positions are written precisely enough to read back the same points
+        let points = random_points(71, 20);
+        let mut geojson = String::from(r#"{"type":"MultiPoint","coordinates":["#);
+        for (i, &point) in points.iter().enumerate() {
+            if i > 0 {
+                geojson.push(',');
+            }
+            let mut position = vec![];
+            write_position(&mut position, point).unwrap();
+            geojson.push_str(std::str::from_utf8(&position).unwrap());
+        }
+        geojson.push_str("]}");
+        let sites = read_geojson_sites(geojson.as_bytes()).unwrap();
+        assert_eq!(sites, points);
+    }
+}
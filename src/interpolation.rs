@@ -0,0 +1,262 @@
+use super::*;
+use cell::{get_neighbours, Cell};
+use cgmath::MetricSpace;
+use std::ops::{Add, Mul};
+
+/// Points closer than this to a site take its value without being inserted into the diagram.
+const SITE_TOLERANCE: f64 = 1e-12;
+
+/// Interpolates values given at the sites of a diagram using their
+/// [natural neighbours](https://en.wikipedia.org/wiki/Natural_neighbor_interpolation).
+///
+/// The weights of a point come from inserting it into the diagram without changing it. The new
+/// region takes parts of the regions of its natural neighbours, and Sibson's weights are the
+/// areas taken from each. Laplace's weights are the lengths of the edges of the new region
+/// divided by the distances to the neighbours across them, which is cheaper and also smooth
+/// everywhere except at the sites.
+///
+/// Both reproduce linear functions exactly away from the border of the diagram. Near the border
+/// the regions are cut off by it, which biases the weights towards the sites along it.
+pub struct NaturalNeighbourInterpolator<'a> {
+    voronoi: &'a Diagram,
+    locator: FaceLocator<'a>,
+}
+
+impl<'a> NaturalNeighbourInterpolator<'a> {
+    /// Constructs an interpolator over the faces of a diagram.
+    pub fn new(voronoi: &'a Diagram) -> Self {
+        NaturalNeighbourInterpolator {
+            voronoi,
+            locator: FaceLocator::new(voronoi),
+        }
+    }
+
+    /// Returns Sibson's weights of the natural neighbours of a point, which sum to one, or nothing
+    /// if the point is outside of the unit square.
+    /// # Arguments
+    /// * `point` - the point to find the weights at.
+    pub fn sibson_weights(&self, point: cgmath::Point2<f64>) -> Vec<(FaceKey, f64)> {
+        let cell = match self.insert_point(point) {
+            Ok(cell) => cell,
+            Err(weights) => return weights,
+        };
+        let mut weights: Vec<(FaceKey, f64)> = cell
+            .neighbours()
+            .map(|face| {
+                // The part of the new region that was in the region of the face
+                let site = self.voronoi.get_face_point(face);
+                let mut stolen = cell.clone();
+                for neighbour in get_neighbours(self.voronoi, face) {
+                    stolen.clip(site, neighbour, self.voronoi.get_face_point(neighbour));
+                }
+                (face, stolen.area())
+            })
+            .collect();
+        normalize(&mut weights);
+        weights
+    }
+
+    /// Returns Laplace's weights of the natural neighbours of a point, which sum to one, or
+    /// nothing if the point is outside of the unit square.
+    /// # Arguments
+    /// * `point` - the point to find the weights at.
+    pub fn laplace_weights(&self, point: cgmath::Point2<f64>) -> Vec<(FaceKey, f64)> {
+        let cell = match self.insert_point(point) {
+            Ok(cell) => cell,
+            Err(weights) => return weights,
+        };
+        let vertices = cell.vertices();
+        let mut weights: Vec<(FaceKey, f64)> = (0..vertices.len())
+            .filter_map(|i| {
+                let (origin, face) = vertices[i];
+                let destination = vertices[(i + 1) % vertices.len()].0;
+                face.map(|face| {
+                    let distance = point.distance(self.voronoi.get_face_point(face));
+                    (face, origin.distance(destination) / distance)
+                })
+            })
+            .collect();
+        normalize(&mut weights);
+        weights
+    }
+
+    /// Interpolates the values at the sites using Sibson's weights, returning `None` if the point
+    /// is outside of the unit square.
+    ///
+    /// This gives the value at a site exactly and is smooth everywhere else.
+    /// # Arguments
+    /// * `point` - the point to interpolate at.
+    /// * `value` - the value at the site of each face, such as a scalar or a vector.
+    pub fn interpolate<T, F>(&self, point: cgmath::Point2<f64>, value: F) -> Option<T>
+    where
+        T: Copy + Add<Output = T> + Mul<f64, Output = T>,
+        F: Fn(FaceKey) -> T,
+    {
+        combine(&self.sibson_weights(point), value)
+    }
+
+    /// Interpolates the values at the sites using Laplace's weights, returning `None` if the point
+    /// is outside of the unit square.
+    /// # Arguments
+    /// * `point` - the point to interpolate at.
+    /// * `value` - the value at the site of each face, such as a scalar or a vector.
+    pub fn interpolate_laplace<T, F>(&self, point: cgmath::Point2<f64>, value: F) -> Option<T>
+    where
+        T: Copy + Add<Output = T> + Mul<f64, Output = T>,
+        F: Fn(FaceKey) -> T,
+    {
+        combine(&self.laplace_weights(point), value)
+    }
+
+    /// Computes the region a point would have if it were inserted into the diagram.
+    ///
+    /// Returns the weights directly instead if the point is outside of the unit square, on a site
+    /// or no other face would border it.
+    fn insert_point(&self, point: cgmath::Point2<f64>) -> Result<Cell, Vec<(FaceKey, f64)>> {
+        let nearest = match self.locator.locate(point) {
+            Some(nearest) => nearest,
+            None => return Err(vec![]),
+        };
+        if point.distance(self.voronoi.get_face_point(nearest)) < SITE_TOLERANCE {
+            return Err(vec![(nearest, 1.0)]);
+        }
+
+        // A face can only take part of the region if it is closer to the point than twice the
+        // distance to the furthest vertex of the region. The region clipped by the neighbours of
+        // the nearest face can only be larger, so it bounds that distance.
+        let candidates: Vec<FaceKey> = std::iter::once(nearest)
+            .chain(get_neighbours(self.voronoi, nearest))
+            .collect();
+        let radius = self.compute_cell(point, &candidates).radius(point);
+        let candidates: Vec<FaceKey> = self
+            .locator
+            .faces_within_radius(point, 2.0 * radius)
+            .into_iter()
+            .map(|(face, _)| face)
+            .collect();
+        let cell = self.compute_cell(point, &candidates);
+        if cell.neighbours().next().is_none() {
+            return Err(vec![(nearest, 1.0)]);
+        }
+        Ok(cell)
+    }
+
+    fn compute_cell(&self, point: cgmath::Point2<f64>, candidates: &[FaceKey]) -> Cell {
+        let mut cell = Cell::new(&BoundingBox::new(0.0, 1.0, 0.0, 1.0));
+        for &candidate in candidates {
+            cell.clip(point, candidate, self.voronoi.get_face_point(candidate));
+        }
+        cell
+    }
+}
+
+/// Scales weights so that they sum to one.
+fn normalize(weights: &mut [(FaceKey, f64)]) {
+    let total: f64 = weights.iter().map(|&(_, weight)| weight).sum();
+    for (_, weight) in weights {
+        *weight /= total;
+    }
+}
+
+/// Sums the values at faces scaled by their weights.
+fn combine<T, F>(weights: &[(FaceKey, f64)], value: F) -> Option<T>
+where
+    T: Copy + Add<Output = T> + Mul<f64, Output = T>,
+    F: Fn(FaceKey) -> T,
+{
+    let (&(first_face, first_weight), rest) = weights.split_first()?;
+    Some(
+        rest.iter()
+            .fold(value(first_face) * first_weight, |acc, &(face, weight)| {
+                acc + value(face) * weight
+            }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::random_points;
+    use cgmath::EuclideanSpace;
+
+    #[test]
+    fn sibson_weights_test() {
+        let voronoi = build_voronoi(&random_points(30, 200));
+        let interpolator = NaturalNeighbourInterpolator::new(&voronoi);
+        for query in random_points(31, 100) {
+            let query = cgmath::Point2::new(0.2, 0.2) + query.to_vec() * 0.6;
+            for weights in &[
+                interpolator.sibson_weights(query),
+                interpolator.laplace_weights(query),
+            ] {
+                assert!(weights.len() >= 3);
+                let total: f64 = weights.iter().map(|&(_, weight)| weight).sum();
+                assert!((total - 1.0).abs() < 1e-9);
+                assert!(weights.iter().all(|&(_, weight)| weight >= 0.0));
+
+                // The weights reproduce the point itself
+                let position = combine(weights, |face| voronoi.get_face_point(face).to_vec());
+                assert!(position.unwrap().distance(query.to_vec()) < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn sibson_weights_area_test() {
+        // The stolen areas add up to the area of the new region, which can be checked by actually
+        // inserting the point
+        let points = random_points(32, 50);
+        let voronoi = build_voronoi(&points);
+        let interpolator = NaturalNeighbourInterpolator::new(&voronoi);
+        let query = cgmath::Point2::new(0.4, 0.6);
+        let weights = interpolator.sibson_weights(query);
+
+        let mut inserted_points = points.clone();
+        inserted_points.push(query);
+        let inserted = build_voronoi(&inserted_points);
+        let faces = inserted.get_face_indices();
+        let area = inserted.get_face_area(faces[points.len()]);
+        for &(face, weight) in &weights {
+            let index = voronoi
+                .get_face_indices()
+                .iter()
+                .position(|&f| f == face)
+                .unwrap();
+            let stolen = voronoi.get_face_area(face) - inserted.get_face_area(faces[index]);
+            assert!((weight * area - stolen).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn interpolate_test() {
+        let points = random_points(33, 300);
+        let voronoi = build_voronoi(&points);
+        let interpolator = NaturalNeighbourInterpolator::new(&voronoi);
+
+        // Linear functions are reproduced exactly
+        let linear = |point: cgmath::Point2<f64>| 2.0 * point.x - 3.0 * point.y + 1.0;
+        for query in random_points(34, 50) {
+            let query = cgmath::Point2::new(0.2, 0.2) + query.to_vec() * 0.6;
+            let value = interpolator
+                .interpolate(query, |face| linear(voronoi.get_face_point(face)))
+                .unwrap();
+            assert!((value - linear(query)).abs() < 1e-9);
+            let value = interpolator
+                .interpolate_laplace(query, |face| linear(voronoi.get_face_point(face)))
+                .unwrap();
+            assert!((value - linear(query)).abs() < 1e-9);
+        }
+
+        // The values at the sites are kept
+        let face = voronoi.get_face_indices()[7];
+        let value = interpolator.interpolate(voronoi.get_face_point(face), |f| {
+            cgmath::Vector2::new(if f == face { 1.0 } else { 0.0 }, 2.0)
+        });
+        assert_eq!(value, Some(cgmath::Vector2::new(1.0, 2.0)));
+
+        assert_eq!(
+            interpolator.interpolate(cgmath::Point2::new(1.5, 0.5), |_| 1.0),
+            None
+        );
+    }
+}
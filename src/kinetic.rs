@@ -0,0 +1,386 @@
+use super::*;
+use cell::{compute_cell, get_neighbours, replace_cells, Cell};
+use cgmath::MetricSpace;
+use slotmap::SecondaryMap;
+
+/// The fraction of sites that may move further than their nearest neighbour before the diagram
+/// is rebuilt instead of being repaired.
+const MAX_FAR_MOVED_FRACTION: f64 = 0.25;
+
+/// The fraction of faces that may have their regions recomputed because of flipped edges before
+/// it is quicker to rebuild the diagram.
+const MAX_FLIPPED_FRACTION: f64 = 0.1;
+
+/// The number of times the cells around flipped edges are recomputed with more candidates before
+/// giving up and rebuilding the diagram.
+const MAX_FLIP_ROUNDS: usize = 4;
+
+/// The relative amount a site can be closer to a vertex than the sites around it due to rounding.
+const DELAUNEY_TOLERANCE: f64 = 1e-9;
+
+/// How a diagram was brought up to date by `update_voronoi`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KineticUpdate {
+    /// The existing diagram was repaired, `flips` is the number of pairs of faces that became
+    /// neighbours.
+    Repaired { flips: usize },
+    /// The sites moved too far to repair the diagram so it was rebuilt.
+    Rebuilt,
+}
+
+/// Updates a voronoi diagram after each of its sites has moved by a small amount.
+///
+/// Vertices are moved to follow the sites for as long as the triangles of the delauney graph
+/// around them stay delauney. Where they do not an edge of the graph has flipped, and the regions
+/// of the faces around it are recomputed and have their edges replaced. If too many sites move
+/// further than their nearest neighbour, or the flips cannot be repaired locally, the diagram is
+/// rebuilt instead.
+/// # Arguments
+/// * `voronoi` - the diagram to update.
+/// * `points` - the new point of each face, in the order returned by `get_face_indices`. These
+///   should be in the range [0,1] X [0,1].
+///
+/// # Panics
+/// If the number of points does not match the number of faces.
+pub fn update_voronoi(voronoi: &mut Diagram, points: &[cgmath::Point2<f64>]) -> KineticUpdate {
+    let faces = voronoi.get_face_indices();
+    assert_eq!(
+        faces.len(),
+        points.len(),
+        "There must be a point for every face of the diagram"
+    );
+
+    let mut neighbours = SecondaryMap::new();
+    for &face in &faces {
+        neighbours.insert(face, get_neighbours(voronoi, face));
+    }
+    let far_moved = faces
+        .iter()
+        .zip(points)
+        .filter(|&(&face, &point)| {
+            let old_point = voronoi.get_face_point(face);
+            let displacement = old_point.distance2(point);
+            neighbours[face].iter().any(|&neighbour| {
+                old_point.distance2(voronoi.get_face_point(neighbour)) < displacement
+            })
+        })
+        .count();
+
+    for (&face, &point) in faces.iter().zip(points) {
+        voronoi.set_face_point(face, point);
+    }
+    if far_moved as f64 > MAX_FAR_MOVED_FRACTION * faces.len() as f64 {
+        voronoi_builder::compute_voronoi(voronoi);
+        return KineticUpdate::Rebuilt;
+    }
+
+    let mut flipped = vec![];
+    for &face in &faces {
+        let ring: Vec<(HalfEdgeKey, Option<FaceKey>)> = voronoi
+            .outer_edge_iter(face)
+            .map(|edge| {
+                let neighbour = voronoi
+                    .get_half_edge_twin(edge)
+                    .and_then(|twin| voronoi.get_half_edge_incident_face(twin));
+                (edge, neighbour)
+            })
+            .collect();
+        if ring.iter().all(|(_, neighbour)| neighbour.is_some()) {
+            move_interior_vertices(voronoi, face, &ring, &mut flipped);
+        } else {
+            // The border of the diagram cuts the edges of these faces so recompute them in full
+            let site = voronoi.get_face_point(face);
+            let cell = compute_cell(voronoi, face, site, neighbours[face].iter().cloned());
+            if !is_delauney(voronoi, face, &cell, &neighbours)
+                || !move_vertices(voronoi, &ring, &cell)
+            {
+                flipped.push(face);
+                flipped.extend(&neighbours[face]);
+            }
+        }
+    }
+
+    flipped.sort();
+    flipped.dedup();
+    if flipped.len() as f64 > MAX_FLIPPED_FRACTION * faces.len() as f64 {
+        voronoi_builder::compute_voronoi(voronoi);
+        return KineticUpdate::Rebuilt;
+    }
+    let cells = match flip_cells(voronoi, &neighbours, flipped) {
+        Some(cells) => cells,
+        None => {
+            voronoi_builder::compute_voronoi(voronoi);
+            return KineticUpdate::Rebuilt;
+        }
+    };
+    if !replace_cells(voronoi, &cells, &[]) {
+        voronoi_builder::compute_voronoi(voronoi);
+        return KineticUpdate::Rebuilt;
+    }
+
+    let mut new_neighbour_count = 0;
+    for (face, cell) in &cells {
+        let old_neighbours = &neighbours[*face];
+        new_neighbour_count += cell
+            .neighbours()
+            .filter(|neighbour| !old_neighbours.contains(neighbour))
+            .count();
+        neighbours.insert(*face, cell.neighbours().collect());
+    }
+
+    // The faces that kept their neighbours were checked against them above, but the new cells
+    // have to be checked against the new neighbours of the faces around them.
+    let is_valid = cells
+        .iter()
+        .all(|(face, cell)| is_delauney(voronoi, *face, cell, &neighbours));
+    if is_valid {
+        KineticUpdate::Repaired {
+            flips: new_neighbour_count / 2,
+        }
+    } else {
+        voronoi_builder::compute_voronoi(voronoi);
+        KineticUpdate::Rebuilt
+    }
+}
+
+/// Computes the new cells of the faces around flipped edges of the delauney graph.
+///
+/// A site can become a neighbour through several flips in the same update, so each cell is
+/// computed against the neighbours of its neighbours and recomputed with the neighbours of its new
+/// neighbours until every pair of cells agrees about the edges between them. Returns `None` if
+/// they still disagree after `MAX_FLIP_ROUNDS` rounds.
+fn flip_cells(
+    voronoi: &Diagram,
+    neighbours: &SecondaryMap<FaceKey, Vec<FaceKey>>,
+    mut pending: Vec<FaceKey>,
+) -> Option<Vec<(FaceKey, Cell)>> {
+    let mut cells: SecondaryMap<FaceKey, Cell> = SecondaryMap::new();
+    for _ in 0..MAX_FLIP_ROUNDS {
+        pending.sort();
+        pending.dedup();
+        let mut recomputed = Vec::with_capacity(pending.len());
+        for face in pending.drain(..) {
+            let mut candidates = neighbours[face].clone();
+            let add_neighbours =
+                |candidates: &mut Vec<FaceKey>, face: FaceKey| match cells.get(face) {
+                    Some(cell) => candidates.extend(cell.neighbours()),
+                    None => candidates.extend(&neighbours[face]),
+                };
+            add_neighbours(&mut candidates, face);
+            for i in 0..candidates.len() {
+                let neighbour = candidates[i];
+                add_neighbours(&mut candidates, neighbour);
+            }
+            let site = voronoi.get_face_point(face);
+            let cell = compute_cell(voronoi, face, site, candidates);
+            let old_cell = cells.insert(face, cell);
+            recomputed.push((face, old_cell));
+        }
+
+        // A disagreement can only involve a recomputed cell and a face it borders now or bordered
+        // before
+        let borders = |face: FaceKey, other: FaceKey| match cells.get(face) {
+            Some(cell) => cell.neighbours().any(|neighbour| neighbour == other),
+            None => neighbours[face].contains(&other),
+        };
+        for (face, old_cell) in recomputed {
+            let disagreeing = cells[face]
+                .neighbours()
+                .chain(neighbours[face].iter().cloned())
+                .chain(old_cell.iter().flat_map(|cell| cell.neighbours()))
+                .filter(|&neighbour| borders(face, neighbour) != borders(neighbour, face));
+            for neighbour in disagreeing {
+                pending.push(face);
+                pending.push(neighbour);
+            }
+        }
+        if pending.is_empty() {
+            return Some(cells.into_iter().collect());
+        }
+    }
+    None
+}
+
+/// Checks that no site is closer to a vertex of a cell than the site of the cell itself.
+///
+/// The cell is already closer to its site than to any of its neighbours, so only the neighbours of
+/// the faces on the other side of the edges meeting at each vertex are checked. This is enough to
+/// show that the diagram is correct once it holds for every vertex.
+fn is_delauney(
+    voronoi: &Diagram,
+    face: FaceKey,
+    cell: &Cell,
+    neighbours: &SecondaryMap<FaceKey, Vec<FaceKey>>,
+) -> bool {
+    let site = voronoi.get_face_point(face);
+    let vertices = cell.vertices();
+    let n = vertices.len();
+    (0..n).all(|i| {
+        let (point, next_face) = vertices[i];
+        let prev_face = vertices[(i + n - 1) % n].1;
+        // The faces around the vertex are all the same distance from it, allow for rounding
+        let radius = point.distance2(site) * (1.0 - DELAUNEY_TOLERANCE);
+        next_face
+            .into_iter()
+            .chain(prev_face)
+            .flat_map(|around| neighbours[around].iter())
+            .all(|&other| point.distance2(voronoi.get_face_point(other)) >= radius)
+    })
+}
+
+/// Moves the vertices around a face that does not touch the border of the diagram to the centers
+/// of the circles through its site and each pair of neighbouring sites.
+///
+/// If any of these triangles of the delauney graph has turned over or has a neighbouring site
+/// inside its circle an edge has to flip, so the faces around it are added to `flipped` and the
+/// diagram is left unchanged.
+/// # Arguments
+/// * `voronoi` - the diagram to update.
+/// * `face` - the face to move the vertices of.
+/// * `ring` - the half edges around the face, each paired with the face on the other side of it.
+/// * `flipped` - the faces whose regions have to be recomputed.
+fn move_interior_vertices(
+    voronoi: &mut Diagram,
+    face: FaceKey,
+    ring: &[(HalfEdgeKey, Option<FaceKey>)],
+    flipped: &mut Vec<FaceKey>,
+) {
+    let site = voronoi.get_face_point(face);
+    let faces: Vec<FaceKey> = ring
+        .iter()
+        .map(|&(_, neighbour)| neighbour.unwrap())
+        .collect();
+    let sites: Vec<cgmath::Point2<f64>> = faces
+        .iter()
+        .map(|&neighbour| voronoi.get_face_point(neighbour))
+        .collect();
+    let bbox = BoundingBox::new(0.0, 1.0, 0.0, 1.0);
+    let n = ring.len();
+    let flipped_count = flipped.len();
+    let mut centers = Vec::with_capacity(n);
+    for i in 0..n {
+        // The vertex at the start of an edge is shared with the faces across it and the previous
+        // edge, and the faces across the edges either side of those are the opposite corners of
+        // the neighbouring triangles.
+        let (before, prev, next, after) = ((i + n - 2) % n, (i + n - 1) % n, i, (i + 1) % n);
+        let mut corners: Vec<usize> = vec![];
+        if vector2::get_det(sites[next] - site, sites[prev] - site) <= 0.0 {
+            corners.extend(&[prev, next]);
+        } else {
+            let center = vector2::compute_circumcircle_center(site, sites[prev], sites[next]);
+            let radius = center.distance2(site) * (1.0 - DELAUNEY_TOLERANCE);
+            if !bbox.contains(&center) {
+                corners.extend(&[prev, next]);
+            }
+            if center.distance2(sites[before]) < radius {
+                corners.extend(&[before, prev, next]);
+            }
+            if center.distance2(sites[after]) < radius {
+                corners.extend(&[prev, next, after]);
+            }
+            centers.push(center);
+        }
+        if !corners.is_empty() {
+            flipped.push(face);
+            flipped.extend(corners.into_iter().map(|corner| faces[corner]));
+        }
+    }
+    if flipped.len() == flipped_count {
+        for (&(edge, _), center) in ring.iter().zip(centers) {
+            let origin = voronoi.get_half_edge_origin(edge).unwrap();
+            voronoi.set_vertex_point(origin, center);
+        }
+    }
+}
+
+/// Moves the vertices around a face to match a new cell that borders the same faces in the same
+/// order as the existing edges.
+///
+/// Returns false without changing the diagram if the cell borders different faces.
+/// # Arguments
+/// * `voronoi` - the diagram to update.
+/// * `ring` - the half edges around the face, each paired with the face on the other side of it.
+/// * `cell` - the new cell of the face.
+fn move_vertices(
+    voronoi: &mut Diagram,
+    ring: &[(HalfEdgeKey, Option<FaceKey>)],
+    cell: &Cell,
+) -> bool {
+    let vertices = cell.vertices();
+    let n = ring.len();
+    if vertices.len() != n {
+        return false;
+    }
+
+    // The cell may start at a different edge, and along the border several rotations can match
+    // so pick the one that moves the vertices the least.
+    let shifts: Vec<usize> = (0..n)
+        .filter(|&shift| (0..n).all(|i| ring[i].1 == vertices[(i + shift) % n].1))
+        .collect();
+    let get_cost = |shift: usize| {
+        (0..n)
+            .map(|i| {
+                voronoi
+                    .get_half_edge_origin_point(ring[i].0)
+                    .distance2(vertices[(i + shift) % n].0)
+            })
+            .sum::<f64>()
+    };
+    let shift = match shifts[..] {
+        [] => return false,
+        [shift] => shift,
+        _ => {
+            shifts
+                .into_iter()
+                .map(|shift| (shift, get_cost(shift)))
+                .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+                .unwrap()
+                .0
+        }
+    };
+
+    for i in 0..n {
+        let origin = voronoi.get_half_edge_origin(ring[i].0).unwrap();
+        voronoi.set_vertex_point(origin, vertices[(i + shift) % n].0);
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{assert_consistent, assert_matches_rebuild, random_points};
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn update_voronoi_test() {
+        let mut points = random_points(5, 300);
+        let mut voronoi = build_voronoi(&points);
+        let mut rng = rand::rngs::StdRng::seed_from_u64(6);
+        let mut total_flips = 0;
+        for _ in 0..20 {
+            for point in points.iter_mut() {
+                point.x = (point.x + rng.gen_range(-0.0005, 0.0005)).clamp(0.0, 1.0);
+                point.y = (point.y + rng.gen_range(-0.0005, 0.0005)).clamp(0.0, 1.0);
+            }
+            match update_voronoi(&mut voronoi, &points) {
+                KineticUpdate::Repaired { flips } => total_flips += flips,
+                KineticUpdate::Rebuilt => {}
+            }
+            assert_consistent(&voronoi);
+            assert_matches_rebuild(&voronoi);
+        }
+        assert!(total_flips > 0);
+    }
+
+    #[test]
+    fn update_voronoi_rebuild_test() {
+        let mut voronoi = build_voronoi(&random_points(7, 100));
+        let points = random_points(8, 100);
+        assert_eq!(
+            update_voronoi(&mut voronoi, &points),
+            KineticUpdate::Rebuilt
+        );
+        assert_matches_rebuild(&voronoi);
+    }
+}
@@ -27,28 +27,90 @@
 //! let voronoi = fortunes_algorithm::generate_diagram(&points);
 //! ```
 mod beachline;
+mod binary;
 mod boundingbox;
+mod cell;
 mod delauney;
+mod density;
 pub mod diagram;
 mod event;
+mod geojson;
+mod interpolation;
+mod kinetic;
+mod locator;
+mod mesh;
+mod noise;
+mod observer;
+#[cfg(feature = "parallel")]
+mod parallel;
+mod priority_queue;
+mod raster;
+mod render;
+mod sampling;
+mod site_update;
+mod svg;
+#[cfg(test)]
+mod test_utils;
+mod tree;
 pub mod vector2;
 mod voronoi_builder;
+mod wkt;
 
 use beachline::Beachline;
-use binary_search_tree::NodeKey;
+use tree::NodeKey;
 use boundingbox::BoundingBox;
+use cgmath::MetricSpace;
 use diagram::{Diagram, FaceKey, HalfEdgeKey, VertexKey};
 use event::Event;
 use event::EventType;
 use std::{collections::HashMap, f64};
 use vector2::compute_circumcircle_center;
+pub use binary::{read_binary, write_binary, write_binary_f32};
+pub use cell::get_counter_clockwise_ring;
 pub use delauney::{DelauneyGraph, DelauneyVertex, get_delauney_graph};
-pub use voronoi_builder::build_voronoi;
+pub use density::{weighted_face_centroid, Density, ImageDensity};
+pub use geojson::{read_geojson_sites, to_geojson, write_geojson, GeoJsonValue};
+pub use interpolation::NaturalNeighbourInterpolator;
+pub use kinetic::{update_voronoi, KineticUpdate};
+pub use locator::FaceLocator;
+pub use mesh::{
+    extrude_cells, write_obj, write_obj_triangles, write_ply, write_ply_triangles, write_stl, Prism,
+};
+pub use noise::{CellularNoise, CellularSample, TileableCellularNoise};
+pub use observer::{EventRecorder, RecordedEvent, ReplayMismatch, SweepObserver};
+#[cfg(feature = "parallel")]
+pub use parallel::{build_voronoi_parallel, lloyds_relaxation_parallel};
+pub use raster::{rasterize, rasterize_coverage, CoverageRaster, FaceRaster};
+pub use render::{to_render_mesh, FanCenter, RenderMesh};
+pub use sampling::{
+    hexagonal_lattice, jittered_grid, poisson_disk_sampling, poisson_disk_sampling_with_radius,
+};
+pub use svg::{to_svg, write_svg, SvgStyle};
+pub use voronoi_builder::{
+    build_voronoi, build_voronoi_with_observer, BeachlineArc, SweepEvent, VoronoiBuilder,
+    VoronoiSweep,
+};
+pub use wkt::{
+    edges_to_wkb, edges_to_wkt, face_to_wkb, face_to_wkt, to_wkb_geometry_collection,
+    to_wkb_multipolygon, to_wkt_geometry_collection, to_wkt_multipolygon,
+};
+
+/// The result of performing Lloyd's algorithm until the points settle.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Relaxation {
+    /// The relaxed points, in the same order as the initial points.
+    pub points: Vec<cgmath::Point2<f64>>,
+    /// The number of iterations that were performed.
+    pub iterations: usize,
+    /// The furthest any point moved in the last iteration, or infinity if there were none.
+    pub displacement: f64,
+}
 
 /// Perform [Lloyd's algorithm](https://en.wikipedia.org/wiki/Lloyd%27s_algorithm) on the supplied points.
 ///
 /// This will attempt to spread the supplied points more evenly by calculating the voronoi diagram
-/// of the plane and generate a new series of points which are the centers of the resultant regions.
+/// of the plane and generate a new series of points which are the centroids of the resultant
+/// regions.
 /// # Arguments
 /// * `points` - The initial points, these should be in the range [0, 1] X [0,1].
 /// * `iterations` - The number of iterations of that we should perform.
@@ -57,12 +119,203 @@ pub fn lloyds_relaxation(
     iterations: usize,
 ) -> Vec<cgmath::Point2<f64>> {
     let mut points = points.to_vec();
+    let mut builder = VoronoiBuilder::new();
+    let mut voronoi = Diagram::new();
+    for _ in 0..iterations {
+        builder.build(&points, &mut voronoi);
+        points.clear();
+        for face in voronoi.get_face_indices() {
+            points.push(voronoi.face_centroid(face));
+        }
+    }
+    points
+}
+
+/// Perform [Lloyd's algorithm](https://en.wikipedia.org/wiki/Lloyd%27s_algorithm) on the supplied
+/// points until they settle.
+///
+/// Iterations are performed until no point moves as far as `tolerance`, or `max_iterations` have
+/// been performed.
+/// # Arguments
+/// * `points` - The initial points, these should be in the range [0, 1] X [0,1].
+/// * `tolerance` - The distance below which a point is considered to have stopped moving.
+/// * `max_iterations` - The most iterations that we should perform.
+pub fn lloyds_relaxation_with_tolerance(
+    points: &[cgmath::Point2<f64>],
+    tolerance: f64,
+    max_iterations: usize,
+) -> Relaxation {
+    let mut points = points.to_vec();
+    let mut builder = VoronoiBuilder::new();
+    let mut voronoi = Diagram::new();
+    let mut iterations = 0;
+    let mut displacement = f64::INFINITY;
+    while iterations < max_iterations && displacement >= tolerance {
+        builder.build(&points, &mut voronoi);
+        displacement = 0.0;
+        for (point, face) in points.iter_mut().zip(voronoi.get_face_indices()) {
+            let centroid = voronoi.face_centroid(face);
+            displacement = displacement.max(point.distance(centroid));
+            *point = centroid;
+        }
+        iterations += 1;
+    }
+    Relaxation {
+        points,
+        iterations,
+        displacement,
+    }
+}
+
+/// Perform Lloyd's algorithm weighted by a density, giving a
+/// [centroidal voronoi tessellation](https://en.wikipedia.org/wiki/Centroidal_Voronoi_tessellation)
+/// in which the points are concentrated where the density is high.
+///
+/// Each iteration moves the points to the centroids of their regions weighted by the density.
+/// # Arguments
+/// * `points` - The initial points, these should be in the range [0, 1] X [0,1].
+/// * `density` - The density to weight the regions by.
+/// * `iterations` - The number of iterations of that we should perform.
+pub fn lloyds_relaxation_with_density<D: Density + ?Sized>(
+    points: &[cgmath::Point2<f64>],
+    density: &D,
+    iterations: usize,
+) -> Vec<cgmath::Point2<f64>> {
+    let mut points = points.to_vec();
+    let mut builder = VoronoiBuilder::new();
+    let mut voronoi = Diagram::new();
     for _ in 0..iterations {
-        let voronoi = build_voronoi(&points);
+        builder.build(&points, &mut voronoi);
         points.clear();
         for face in voronoi.get_face_indices() {
-            points.push(voronoi.calculate_face_center(face));
+            points.push(weighted_face_centroid(&voronoi, face, density));
         }
     }
     points
 }
+
+/// Perform Lloyd's algorithm moving each point only part of the way to the centroid of its region.
+///
+/// # Arguments
+/// * `points` - The initial points, these should be in the range [0, 1] X [0,1].
+/// * `strengths` - How far each point moves towards its centroid in each iteration, from 0 to
+///   leave it fixed up to 1 for a full step of Lloyd's algorithm.
+/// * `iterations` - The number of iterations of that we should perform.
+///
+/// # Panics
+/// If the number of strengths does not match the number of points.
+pub fn lloyds_relaxation_with_strengths(
+    points: &[cgmath::Point2<f64>],
+    strengths: &[f64],
+    iterations: usize,
+) -> Vec<cgmath::Point2<f64>> {
+    assert_eq!(
+        points.len(),
+        strengths.len(),
+        "There must be a strength for every point"
+    );
+    let mut points = points.to_vec();
+    let mut builder = VoronoiBuilder::new();
+    let mut voronoi = Diagram::new();
+    for _ in 0..iterations {
+        builder.build(&points, &mut voronoi);
+        for ((point, &strength), face) in points
+            .iter_mut()
+            .zip(strengths)
+            .zip(voronoi.get_face_indices())
+        {
+            if strength != 0.0 {
+                *point += (voronoi.face_centroid(face) - *point) * strength;
+            }
+        }
+    }
+    points
+}
+
+/// Perform Lloyd's algorithm on the supplied points while keeping some of them where they are.
+///
+/// # Arguments
+/// * `points` - The initial points, these should be in the range [0, 1] X [0,1].
+/// * `pinned` - Whether each point is fixed in place.
+/// * `iterations` - The number of iterations of that we should perform.
+///
+/// # Panics
+/// If the number of pinned flags does not match the number of points.
+pub fn lloyds_relaxation_with_pinned(
+    points: &[cgmath::Point2<f64>],
+    pinned: &[bool],
+    iterations: usize,
+) -> Vec<cgmath::Point2<f64>> {
+    assert_eq!(
+        points.len(),
+        pinned.len(),
+        "There must be a pinned flag for every point"
+    );
+    let strengths: Vec<f64> = pinned
+        .iter()
+        .map(|&pinned| if pinned { 0.0 } else { 1.0 })
+        .collect();
+    lloyds_relaxation_with_strengths(points, &strengths, iterations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::random_points;
+    use cgmath::EuclideanSpace;
+
+    #[test]
+    fn lloyds_relaxation_with_tolerance_test() {
+        let points = random_points(15, 200);
+        let relaxation = lloyds_relaxation_with_tolerance(&points, 1e-4, 1000);
+        assert!(relaxation.iterations < 1000);
+        assert!(relaxation.displacement < 1e-4);
+        assert_eq!(relaxation.points.len(), points.len());
+
+        // Running the same number of fixed iterations gives the same points
+        assert_eq!(
+            lloyds_relaxation(&points, relaxation.iterations),
+            relaxation.points
+        );
+
+        let relaxation = lloyds_relaxation_with_tolerance(&points, 0.0, 3);
+        assert_eq!(relaxation.iterations, 3);
+        assert!(relaxation.displacement > 0.0);
+    }
+
+    #[test]
+    fn lloyds_relaxation_with_density_test() {
+        // The points settle with their spacing proportional to the inverse square root of the
+        // density, leaving about one in eight of them in the left half
+        let points = random_points(17, 30);
+        let density = |point: cgmath::Point2<f64>| (8.0 * point.x).exp();
+        let relaxed = lloyds_relaxation_with_density(&points, &density, 300);
+        assert_eq!(relaxed.len(), points.len());
+        let left = relaxed.iter().filter(|point| point.x < 0.5).count();
+        assert!((2..=6).contains(&left));
+    }
+
+    #[test]
+    fn lloyds_relaxation_with_strengths_test() {
+        let points = random_points(19, 100);
+        let pinned: Vec<bool> = (0..points.len()).map(|i| i % 10 == 0).collect();
+        let relaxed = lloyds_relaxation_with_pinned(&points, &pinned, 5);
+        for (i, (point, relaxed)) in points.iter().zip(&relaxed).enumerate() {
+            assert_eq!(pinned[i], point == relaxed);
+        }
+
+        let strengths = vec![1.0; points.len()];
+        assert_eq!(
+            lloyds_relaxation_with_strengths(&points, &strengths, 5),
+            lloyds_relaxation(&points, 5)
+        );
+
+        // A half step stops half way to the centroid
+        let strengths = vec![0.5; points.len()];
+        let relaxed = lloyds_relaxation_with_strengths(&points, &strengths, 1);
+        let centroids = lloyds_relaxation(&points, 1);
+        for i in 0..points.len() {
+            assert!(relaxed[i].distance(points[i].midpoint(centroids[i])) < 1e-12);
+        }
+    }
+}
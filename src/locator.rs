@@ -0,0 +1,297 @@
+use super::*;
+use cell::{find_nearest_face, get_neighbours};
+use cgmath::MetricSpace;
+use priority_queue::PriorityQueue;
+use slotmap::SecondaryMap;
+
+/// A spatial index for finding the faces of a diagram whose regions contain points.
+///
+/// The unit square is divided into a grid with about one cell for each face, and each cell stores
+/// the face closest to its center. A query walks from the face stored for the cell containing the
+/// point across neighbouring faces to the face closest to the point, which takes a step or two for
+/// evenly spread sites.
+pub struct FaceLocator<'a> {
+    voronoi: &'a Diagram,
+    grid: HintGrid,
+}
+
+impl<'a> FaceLocator<'a> {
+    /// Constructs an index over the faces of a diagram, this takes time proportional to the
+    /// number of faces.
+    ///
+    /// The index borrows the diagram, so it can not become out of date.
+    pub fn new(voronoi: &'a Diagram) -> Self {
+        FaceLocator {
+            voronoi,
+            grid: HintGrid::new(voronoi),
+        }
+    }
+
+    /// Returns the face whose region contains a point, or `None` if the point is outside of the
+    /// unit square or the diagram has no faces. A point on an edge may be given either face.
+    /// # Arguments
+    /// * `point` - the point to locate.
+    pub fn locate(&self, point: cgmath::Point2<f64>) -> Option<FaceKey> {
+        self.grid.locate(self.voronoi, point)
+    }
+
+    /// Returns the face whose region contains each of a series of points, as given by `locate`.
+    /// # Arguments
+    /// * `points` - the points to locate.
+    pub fn locate_all(&self, points: &[cgmath::Point2<f64>]) -> Vec<Option<FaceKey>> {
+        points.iter().map(|&point| self.locate(point)).collect()
+    }
+
+    /// Returns the faces whose points are closest to a point along with their distances from it,
+    /// sorted by distance, or nothing if the point is outside of the unit square.
+    /// # Arguments
+    /// * `point` - the point to find the closest faces to.
+    /// * `count` - the most faces to return.
+    pub fn nearest_faces(&self, point: cgmath::Point2<f64>, count: usize) -> Vec<(FaceKey, f64)> {
+        match self.locate(point) {
+            Some(start) => nearest_faces_from(self.voronoi, start, point, count),
+            None => vec![],
+        }
+    }
+
+    /// Returns the faces whose points are within a distance of a point along with their distances
+    /// from it, sorted by distance, or nothing if the point is outside of the unit square.
+    /// # Arguments
+    /// * `point` - the point to find the faces around.
+    /// * `radius` - the furthest distance of the faces to return.
+    pub fn faces_within_radius(
+        &self,
+        point: cgmath::Point2<f64>,
+        radius: f64,
+    ) -> Vec<(FaceKey, f64)> {
+        match self.locate(point) {
+            Some(start) => faces_within_radius_from(self.voronoi, start, point, radius),
+            None => vec![],
+        }
+    }
+}
+
+/// The grid of starting faces behind a `FaceLocator`, kept apart from the diagram so that it can
+/// be stored alongside one.
+pub(crate) struct HintGrid {
+    resolution: usize,
+    hints: Vec<FaceKey>,
+}
+
+impl HintGrid {
+    pub fn new(voronoi: &Diagram) -> Self {
+        let faces = voronoi.get_face_indices();
+        let resolution = (faces.len() as f64).sqrt().ceil() as usize;
+        let mut hints = vec![];
+        if let Some(&first) = faces.first() {
+            hints = vec![first; resolution * resolution];
+            let mut hint = first;
+            for row in 0..resolution {
+                for column in 0..resolution {
+                    // The cells are visited in a snaking order and each walk starts from the face
+                    // of the cell before, so every walk is short
+                    let column = if row % 2 == 0 {
+                        column
+                    } else {
+                        resolution - 1 - column
+                    };
+                    let center = cgmath::Point2::new(
+                        (column as f64 + 0.5) / resolution as f64,
+                        (row as f64 + 0.5) / resolution as f64,
+                    );
+                    hint = find_nearest_face(voronoi, hint, center);
+                    hints[row * resolution + column] = hint;
+                }
+            }
+        }
+        HintGrid { resolution, hints }
+    }
+
+    /// Locates a point in the diagram the grid was constructed from.
+    pub fn locate(&self, voronoi: &Diagram, point: cgmath::Point2<f64>) -> Option<FaceKey> {
+        if self.hints.is_empty() || !is_in_unit_square(point) {
+            return None;
+        }
+        let column = ((point.x * self.resolution as f64) as usize).min(self.resolution - 1);
+        let row = ((point.y * self.resolution as f64) as usize).min(self.resolution - 1);
+        let hint = self.hints[row * self.resolution + column];
+        Some(find_nearest_face(voronoi, hint, point))
+    }
+}
+
+/// Visits faces in order of the distance from their points to a point, until `visit` returns
+/// false.
+///
+/// The search spreads out across neighbouring faces from the face whose region contains the point.
+/// This finds every face in order because the line from the point to any face only crosses the
+/// regions of faces that are closer, which are all visited first.
+fn visit_nearest_faces<F: FnMut(FaceKey, f64) -> bool>(
+    voronoi: &Diagram,
+    start: FaceKey,
+    point: cgmath::Point2<f64>,
+    mut visit: F,
+) {
+    let mut queue = PriorityQueue::new();
+    let mut queued = SecondaryMap::new();
+    queue.push((point.distance(voronoi.get_face_point(start)), start));
+    queued.insert(start, ());
+    while let Some((distance, face)) = queue.pop() {
+        if !visit(face, distance) {
+            return;
+        }
+        for neighbour in get_neighbours(voronoi, face) {
+            if queued.insert(neighbour, ()).is_none() {
+                queue.push((point.distance(voronoi.get_face_point(neighbour)), neighbour));
+            }
+        }
+    }
+}
+
+/// Returns the closest faces to a point, starting from the face whose region contains it.
+pub(crate) fn nearest_faces_from(
+    voronoi: &Diagram,
+    start: FaceKey,
+    point: cgmath::Point2<f64>,
+    count: usize,
+) -> Vec<(FaceKey, f64)> {
+    let mut faces = Vec::with_capacity(count);
+    if count > 0 {
+        visit_nearest_faces(voronoi, start, point, |face, distance| {
+            faces.push((face, distance));
+            faces.len() < count
+        });
+    }
+    faces
+}
+
+/// Returns the faces within a distance of a point, starting from the face whose region contains
+/// it.
+pub(crate) fn faces_within_radius_from(
+    voronoi: &Diagram,
+    start: FaceKey,
+    point: cgmath::Point2<f64>,
+    radius: f64,
+) -> Vec<(FaceKey, f64)> {
+    let mut faces = vec![];
+    visit_nearest_faces(voronoi, start, point, |face, distance| {
+        if distance <= radius {
+            faces.push((face, distance));
+            true
+        } else {
+            false
+        }
+    });
+    faces
+}
+
+/// Returns if a point is inside of the unit square, or on its border.
+pub(crate) fn is_in_unit_square(point: cgmath::Point2<f64>) -> bool {
+    (0.0..=1.0).contains(&point.x) && (0.0..=1.0).contains(&point.y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::random_points;
+
+    /// Returns the face whose point is closest to a point, or `None` if the diagram has no faces.
+    ///
+    /// This checks every face, it is used to check the faces found by walking across the diagram.
+    fn find_nearest_face_brute_force(
+        voronoi: &Diagram,
+        point: cgmath::Point2<f64>,
+    ) -> Option<FaceKey> {
+        voronoi.get_face_indices().into_iter().min_by(|&a, &b| {
+            let distance = |face| point.distance2(voronoi.get_face_point(face));
+            distance(a).partial_cmp(&distance(b)).unwrap()
+        })
+    }
+
+    #[test]
+    fn face_locator_test() {
+        for &count in &[1, 2, 10, 1000] {
+            let voronoi = build_voronoi(&random_points(count as u64, count));
+            let locator = FaceLocator::new(&voronoi);
+            let queries = random_points(100 + count as u64, 500);
+            let faces = locator.locate_all(&queries);
+            for (&query, face) in queries.iter().zip(faces) {
+                let face = face.unwrap();
+                let expected = find_nearest_face_brute_force(&voronoi, query).unwrap();
+                // Ties between faces may be broken either way
+                assert_eq!(
+                    query.distance2(voronoi.get_face_point(face)),
+                    query.distance2(voronoi.get_face_point(expected))
+                );
+            }
+        }
+    }
+
+    /// Returns every face with its distance from a point, sorted by distance.
+    fn sort_faces_brute_force(
+        voronoi: &Diagram,
+        point: cgmath::Point2<f64>,
+    ) -> Vec<(FaceKey, f64)> {
+        let mut faces: Vec<(FaceKey, f64)> = voronoi
+            .get_face_indices()
+            .into_iter()
+            .map(|face| (face, point.distance(voronoi.get_face_point(face))))
+            .collect();
+        faces.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        faces
+    }
+
+    #[test]
+    fn nearest_faces_test() {
+        let voronoi = build_voronoi(&random_points(20, 500));
+        let locator = FaceLocator::new(&voronoi);
+        for query in random_points(21, 50) {
+            let expected = sort_faces_brute_force(&voronoi, query);
+            for &count in &[0, 1, 5, 40, 1000] {
+                let faces = locator.nearest_faces(query, count);
+                assert_eq!(faces.len(), count.min(500));
+                assert_eq!(faces, voronoi.nearest_faces(query, count));
+                for (&(_, distance), &(_, expected_distance)) in faces.iter().zip(&expected) {
+                    assert_eq!(distance, expected_distance);
+                }
+            }
+        }
+        assert!(locator
+            .nearest_faces(cgmath::Point2::new(2.0, 0.5), 3)
+            .is_empty());
+    }
+
+    #[test]
+    fn faces_within_radius_test() {
+        let voronoi = build_voronoi(&random_points(22, 500));
+        let locator = FaceLocator::new(&voronoi);
+        for query in random_points(23, 50) {
+            let expected = sort_faces_brute_force(&voronoi, query);
+            for &radius in &[0.0, 0.01, 0.1, 0.5, 2.0] {
+                let faces = locator.faces_within_radius(query, radius);
+                assert_eq!(faces, voronoi.faces_within_radius(query, radius));
+                let expected: Vec<f64> = expected
+                    .iter()
+                    .map(|&(_, distance)| distance)
+                    .take_while(|&distance| distance <= radius)
+                    .collect();
+                let distances: Vec<f64> = faces.iter().map(|&(_, distance)| distance).collect();
+                assert_eq!(distances, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn face_locator_outside_test() {
+        let voronoi = build_voronoi(&random_points(3, 50));
+        let locator = FaceLocator::new(&voronoi);
+        assert!(locator.locate(cgmath::Point2::new(1.5, 0.5)).is_none());
+        assert!(locator.locate(cgmath::Point2::new(0.5, -0.1)).is_none());
+        assert!(locator.locate(cgmath::Point2::new(f64::NAN, 0.5)).is_none());
+        assert!(locator.locate(cgmath::Point2::new(1.0, 1.0)).is_some());
+
+        let empty = Diagram::new();
+        assert!(FaceLocator::new(&empty)
+            .locate(cgmath::Point2::new(0.5, 0.5))
+            .is_none());
+    }
+}
@@ -0,0 +1,509 @@
+use super::*;
+use cell::get_counter_clockwise_ring;
+use cgmath::InnerSpace;
+use std::io::{self, Write};
+
+/// A closed prism standing on the region of a face, as made by `extrude_cells`.
+#[derive(Clone, Debug)]
+pub struct Prism {
+    /// The face whose region the prism stands on.
+    pub face: FaceKey,
+    /// The triangles of the surface of the prism, wound counter-clockwise when seen from outside.
+    pub triangles: Vec<[cgmath::Point3<f64>; 3]>,
+}
+
+/// Writes the regions of a diagram as a [Wavefront OBJ](https://en.wikipedia.org/wiki/Wavefront_.obj_file)
+/// mesh lying in the plane z = 0, with one polygon for each region.
+///
+/// The corners shared by regions are written once. Each region is a group named `cell_<i>`, where
+/// `i` is the position of its face in `get_face_indices`, and the polygons wind as
+/// [`get_counter_clockwise_ring`] describes, so they face up the z axis.
+/// # Arguments
+/// * `voronoi` - the diagram to write.
+/// * `material` - the material of the region of each face, or `None` to leave it as it was.
+/// * `writer` - where to write the mesh.
+pub fn write_obj<W, F>(voronoi: &Diagram, material: F, writer: W) -> io::Result<()>
+where
+    W: Write,
+    F: Fn(FaceKey) -> Option<String>,
+{
+    write_obj_polygons(&PolygonMesh::new(voronoi), false, material, writer)
+}
+
+/// Writes the regions of a diagram as a Wavefront OBJ mesh like `write_obj`, with each region
+/// split into a fan of triangles around its first corner.
+/// # Arguments
+/// * `voronoi` - the diagram to write.
+/// * `material` - the material of the region of each face, or `None` to leave it as it was.
+/// * `writer` - where to write the mesh.
+pub fn write_obj_triangles<W, F>(voronoi: &Diagram, material: F, writer: W) -> io::Result<()>
+where
+    W: Write,
+    F: Fn(FaceKey) -> Option<String>,
+{
+    write_obj_polygons(&PolygonMesh::new(voronoi), true, material, writer)
+}
+
+/// Writes the regions of a diagram as an ASCII [PLY](https://en.wikipedia.org/wiki/PLY_(file_format))
+/// mesh lying in the plane z = 0, with one polygon for each region.
+///
+/// The corners shared by regions are written once. Each polygon has a `cell` property holding the
+/// position of its face in `get_face_indices`, followed by the attributes, and winds as
+/// [`get_counter_clockwise_ring`] describes.
+/// # Arguments
+/// * `voronoi` - the diagram to write.
+/// * `attributes` - the names of further properties of each face.
+/// * `values` - the values of the properties of each face, in the order of their names.
+/// * `writer` - where to write the mesh.
+///
+/// # Panics
+/// If the number of values for a face is not the number of attributes.
+pub fn write_ply<W, F>(
+    voronoi: &Diagram,
+    attributes: &[&str],
+    values: F,
+    writer: W,
+) -> io::Result<()>
+where
+    W: Write,
+    F: Fn(FaceKey) -> Vec<f64>,
+{
+    write_ply_polygons(
+        &PolygonMesh::new(voronoi),
+        false,
+        attributes,
+        values,
+        writer,
+    )
+}
+
+/// Writes the regions of a diagram as an ASCII PLY mesh like `write_ply`, with each region split
+/// into a fan of triangles around its first corner. Each triangle has the properties of its face.
+/// # Arguments
+/// * `voronoi` - the diagram to write.
+/// * `attributes` - the names of further properties of each face.
+/// * `values` - the values of the properties of each face, in the order of their names.
+/// * `writer` - where to write the mesh.
+///
+/// # Panics
+/// If the number of values for a face is not the number of attributes.
+pub fn write_ply_triangles<W, F>(
+    voronoi: &Diagram,
+    attributes: &[&str],
+    values: F,
+    writer: W,
+) -> io::Result<()>
+where
+    W: Write,
+    F: Fn(FaceKey) -> Vec<f64>,
+{
+    write_ply_polygons(&PolygonMesh::new(voronoi), true, attributes, values, writer)
+}
+
+/// Extrudes the region of each face into a closed prism rising from the plane z = 0.
+///
+/// The top and bottom of each prism are fans of triangles around the first corner of the region,
+/// and each side is a pair of triangles, so neighbouring prisms touch without sharing triangles.
+/// Faces without a region or with a height that is not positive are left out.
+/// # Arguments
+/// * `voronoi` - the diagram whose regions to extrude.
+/// * `height` - the height of the prism of each face.
+pub fn extrude_cells<F: Fn(FaceKey) -> f64>(voronoi: &Diagram, height: F) -> Vec<Prism> {
+    let mesh = PolygonMesh::new(voronoi);
+    let mut prisms = vec![];
+    for (_, face, corners) in &mesh.polygons {
+        let height = height(*face);
+        if height.is_nan() || height <= 0.0 {
+            continue;
+        }
+        let bottom: Vec<cgmath::Point3<f64>> = corners
+            .iter()
+            .map(|&i| cgmath::Point3::new(mesh.vertices[i].x, mesh.vertices[i].y, 0.0))
+            .collect();
+        let top: Vec<cgmath::Point3<f64>> = bottom
+            .iter()
+            .map(|point| cgmath::Point3::new(point.x, point.y, height))
+            .collect();
+        let mut triangles = vec![];
+        for i in 1..corners.len() - 1 {
+            // The bottom faces down, so it winds the other way
+            triangles.push([bottom[0], bottom[i + 1], bottom[i]]);
+            triangles.push([top[0], top[i], top[i + 1]]);
+        }
+        for i in 0..corners.len() {
+            let j = (i + 1) % corners.len();
+            triangles.push([bottom[i], bottom[j], top[j]]);
+            triangles.push([bottom[i], top[j], top[i]]);
+        }
+        prisms.push(Prism {
+            face: *face,
+            triangles,
+        });
+    }
+    prisms
+}
+
+/// Writes prisms as a binary [STL](https://en.wikipedia.org/wiki/STL_(file_format)) mesh.
+/// # Arguments
+/// * `prisms` - the prisms to write, as made by `extrude_cells`.
+/// * `writer` - where to write the mesh.
+pub fn write_stl<W: Write>(prisms: &[Prism], mut writer: W) -> io::Result<()> {
+    let mut header = [0; 80];
+    let title = b"fortunes_algorithm prisms";
+    header[..title.len()].copy_from_slice(title);
+    writer.write_all(&header)?;
+    let count: usize = prisms.iter().map(|prism| prism.triangles.len()).sum();
+    writer.write_all(&(count as u32).to_le_bytes())?;
+    for triangle in prisms.iter().flat_map(|prism| &prism.triangles) {
+        let normal = (triangle[1] - triangle[0]).cross(triangle[2] - triangle[0]);
+        let normal = if normal.magnitude2() > 0.0 {
+            normal.normalize()
+        } else {
+            normal
+        };
+        let mut coordinates = vec![normal.x, normal.y, normal.z];
+        for point in triangle {
+            coordinates.extend_from_slice(&[point.x, point.y, point.z]);
+        }
+        for coordinate in coordinates {
+            writer.write_all(&(coordinate as f32).to_le_bytes())?;
+        }
+        // The attribute byte count, which is unused
+        writer.write_all(&[0, 0])?;
+    }
+    Ok(())
+}
+
+/// The regions of a diagram as polygons indexing into a list of their corners.
+struct PolygonMesh {
+    vertices: Vec<cgmath::Point2<f64>>,
+    /// The position of the face of each region in `get_face_indices` and the face, along with the
+    /// corners of the region wound as [`get_counter_clockwise_ring`] describes.
+    polygons: Vec<(usize, FaceKey, Vec<usize>)>,
+}
+
+impl PolygonMesh {
+    fn new(voronoi: &Diagram) -> Self {
+        let mut vertices = vec![];
+        let mut vertex_indices = HashMap::new();
+        let mut polygons = vec![];
+        for (cell, face) in voronoi.get_face_indices().into_iter().enumerate() {
+            let mut ring = get_counter_clockwise_ring(voronoi, face);
+            if ring.len() < 4 {
+                continue;
+            }
+            // The ring repeats its first corner
+            ring.pop();
+            // A corner shared by regions is the same vertex, so its coordinates identify it
+            let corners: Vec<usize> = ring
+                .into_iter()
+                .map(|point| {
+                    let key = (point.x.to_bits(), point.y.to_bits());
+                    *vertex_indices.entry(key).or_insert_with(|| {
+                        vertices.push(point);
+                        vertices.len() - 1
+                    })
+                })
+                .collect();
+            polygons.push((cell, face, corners));
+        }
+        PolygonMesh { vertices, polygons }
+    }
+
+    /// Returns the polygons, or the triangles of a fan around the first corner of each polygon.
+    fn get_polygons(&self, triangulate: bool) -> Vec<(usize, FaceKey, Vec<usize>)> {
+        if !triangulate {
+            return self.polygons.clone();
+        }
+        let mut triangles = vec![];
+        for (cell, face, corners) in &self.polygons {
+            for i in 1..corners.len() - 1 {
+                triangles.push((*cell, *face, vec![corners[0], corners[i], corners[i + 1]]));
+            }
+        }
+        triangles
+    }
+}
+
+fn write_obj_polygons<W, F>(
+    mesh: &PolygonMesh,
+    triangulate: bool,
+    material: F,
+    mut writer: W,
+) -> io::Result<()>
+where
+    W: Write,
+    F: Fn(FaceKey) -> Option<String>,
+{
+    for vertex in &mesh.vertices {
+        writeln!(writer, "v {} {} 0", vertex.x, vertex.y)?;
+    }
+    let mut last_cell = None;
+    for (cell, face, corners) in mesh.get_polygons(triangulate) {
+        if last_cell != Some(cell) {
+            writeln!(writer, "g cell_{}", cell)?;
+            if let Some(material) = material(face) {
+                writeln!(writer, "usemtl {}", material)?;
+            }
+            last_cell = Some(cell);
+        }
+        write!(writer, "f")?;
+        for corner in corners {
+            // The vertices are numbered from one
+            write!(writer, " {}", corner + 1)?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+fn write_ply_polygons<W, F>(
+    mesh: &PolygonMesh,
+    triangulate: bool,
+    attributes: &[&str],
+    values: F,
+    mut writer: W,
+) -> io::Result<()>
+where
+    W: Write,
+    F: Fn(FaceKey) -> Vec<f64>,
+{
+    let polygons = mesh.get_polygons(triangulate);
+    writeln!(writer, "ply")?;
+    writeln!(writer, "format ascii 1.0")?;
+    writeln!(writer, "element vertex {}", mesh.vertices.len())?;
+    writeln!(writer, "property double x")?;
+    writeln!(writer, "property double y")?;
+    writeln!(writer, "property double z")?;
+    writeln!(writer, "element face {}", polygons.len())?;
+    writeln!(writer, "property list uint int vertex_indices")?;
+    writeln!(writer, "property int cell")?;
+    for attribute in attributes {
+        writeln!(writer, "property double {}", attribute)?;
+    }
+    writeln!(writer, "end_header")?;
+    for vertex in &mesh.vertices {
+        writeln!(writer, "{} {} 0", vertex.x, vertex.y)?;
+    }
+    let mut last_cell = None;
+    let mut face_values = vec![];
+    for (cell, face, corners) in polygons {
+        if last_cell != Some(cell) {
+            face_values = values(face);
+            assert_eq!(face_values.len(), attributes.len());
+            last_cell = Some(cell);
+        }
+        write!(writer, "{}", corners.len())?;
+        for corner in corners {
+            write!(writer, " {}", corner)?;
+        }
+        write!(writer, " {}", cell)?;
+        for value in &face_values {
+            write!(writer, " {}", value)?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::random_points;
+    use cgmath::EuclideanSpace;
+    use std::convert::TryInto;
+
+    fn to_string<F: Fn(&mut Vec<u8>) -> io::Result<()>>(write: F) -> String {
+        let mut output = vec![];
+        write(&mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn write_obj_test() {
+        let voronoi = build_voronoi(&random_points(100, 40));
+        let faces = voronoi.get_face_indices();
+        let corners: Vec<usize> = faces
+            .iter()
+            .map(|&face| voronoi.outer_edge_iter(face).count())
+            .collect();
+        let material = |face| {
+            if face == faces[2] {
+                Some("red".to_string())
+            } else {
+                None
+            }
+        };
+        let obj = to_string(|output| write_obj(&voronoi, material, output));
+        let vertices: Vec<cgmath::Point2<f64>> = obj
+            .lines()
+            .filter(|line| line.starts_with("v "))
+            .map(|line| {
+                let coordinates: Vec<f64> = line[2..]
+                    .split(' ')
+                    .map(|coordinate| coordinate.parse().unwrap())
+                    .collect();
+                assert_eq!(coordinates[2], 0.0);
+                cgmath::Point2::new(coordinates[0], coordinates[1])
+            })
+            .collect();
+        assert_eq!(vertices.len(), voronoi.get_vertex_points().len());
+        assert_eq!(obj.matches("\ng cell_").count(), faces.len());
+        assert_eq!(obj.matches("usemtl red").count(), 1);
+        assert!(obj.contains("g cell_2\nusemtl red\nf "));
+
+        // Each polygon has the corners of its region and faces up
+        let polygons: Vec<Vec<usize>> = obj
+            .lines()
+            .filter(|line| line.starts_with("f "))
+            .map(|line| {
+                line[2..]
+                    .split(' ')
+                    .map(|index| index.parse::<usize>().unwrap() - 1)
+                    .collect()
+            })
+            .collect();
+        assert_eq!(polygons.len(), faces.len());
+        for ((polygon, &face), &count) in polygons.iter().zip(&faces).zip(&corners) {
+            assert_eq!(polygon.len(), count);
+            let points: Vec<cgmath::Point2<f64>> = polygon.iter().map(|&i| vertices[i]).collect();
+            let signed_area = vector2::get_polygon_signed_area(&points);
+            assert!((signed_area - voronoi.get_face_area(face)).abs() < 1e-12);
+        }
+
+        let obj = to_string(|output| write_obj_triangles(&voronoi, |_| None, output));
+        let triangles = obj.lines().filter(|line| line.starts_with("f ")).count();
+        let expected: usize = corners.iter().map(|count| count - 2).sum();
+        assert_eq!(triangles, expected);
+        assert_eq!(obj.matches("g cell_").count(), faces.len());
+        assert_eq!(obj.matches("usemtl").count(), 0);
+    }
+
+    #[test]
+    fn write_ply_test() {
+        let voronoi = build_voronoi(&random_points(101, 40));
+        let faces = voronoi.get_face_indices();
+        let values = |face| vec![voronoi.get_face_area(face), voronoi.get_face_point(face).x];
+        let ply =
+            to_string(|output| write_ply_triangles(&voronoi, &["area", "site_x"], values, output));
+        let (header, body) = ply.split_at(ply.find("end_header\n").unwrap() + 11);
+        let vertex_count = voronoi.get_vertex_points().len();
+        let triangle_count: usize = faces
+            .iter()
+            .map(|&face| voronoi.outer_edge_iter(face).count() - 2)
+            .sum();
+        assert!(header.starts_with("ply\nformat ascii 1.0\n"));
+        assert!(header.contains(&format!("element vertex {}\n", vertex_count)));
+        assert!(header.contains(&format!("element face {}\n", triangle_count)));
+        assert!(
+            header.contains("property int cell\nproperty double area\nproperty double site_x\n")
+        );
+
+        let lines: Vec<&str> = body.lines().collect();
+        assert_eq!(lines.len(), vertex_count + triangle_count);
+        let mut areas = vec![0.0; faces.len()];
+        for line in &lines[vertex_count..] {
+            let fields: Vec<&str> = line.split(' ').collect();
+            assert_eq!(fields.len(), 7);
+            assert_eq!(fields[0], "3");
+            let cell: usize = fields[4].parse().unwrap();
+            let area: f64 = fields[5].parse().unwrap();
+            assert_eq!(area, voronoi.get_face_area(faces[cell]));
+            let corners: Vec<cgmath::Point2<f64>> = fields[1..4]
+                .iter()
+                .map(|index| {
+                    let vertex: Vec<f64> = lines[index.parse::<usize>().unwrap()]
+                        .split(' ')
+                        .map(|coordinate| coordinate.parse().unwrap())
+                        .collect();
+                    cgmath::Point2::new(vertex[0], vertex[1])
+                })
+                .collect();
+            areas[cell] += vector2::get_det(corners[1] - corners[0], corners[2] - corners[0]) / 2.0;
+        }
+        for (&face, area) in faces.iter().zip(areas) {
+            assert!((area - voronoi.get_face_area(face)).abs() < 1e-12);
+        }
+
+        let ply = to_string(|output| write_ply(&voronoi, &[], |_| vec![], output));
+        assert!(ply.contains(&format!("element face {}\n", faces.len())));
+        assert!(
+            ply.contains("property list uint int vertex_indices\nproperty int cell\nend_header")
+        );
+    }
+
+    #[test]
+    fn extrude_cells_test() {
+        let voronoi = build_voronoi(&random_points(102, 30));
+        let faces = voronoi.get_face_indices();
+        let height = |face| {
+            if face == faces[0] {
+                0.0
+            } else {
+                voronoi.get_face_point(face).x + 0.1
+            }
+        };
+        let prisms = extrude_cells(&voronoi, height);
+        assert_eq!(prisms.len(), faces.len() - 1);
+        for prism in &prisms {
+            let corners = voronoi.outer_edge_iter(prism.face).count();
+            assert_eq!(prism.triangles.len(), 2 * (corners - 2) + 2 * corners);
+
+            // The surface is closed, with each edge shared by two triangles in opposite directions
+            let mut edges = HashMap::new();
+            for triangle in &prism.triangles {
+                for i in 0..3 {
+                    let (a, b) = (triangle[i], triangle[(i + 1) % 3]);
+                    let key = |a: cgmath::Point3<f64>, b: cgmath::Point3<f64>| {
+                        [
+                            a.x.to_bits(),
+                            a.y.to_bits(),
+                            a.z.to_bits(),
+                            b.x.to_bits(),
+                            b.y.to_bits(),
+                            b.z.to_bits(),
+                        ]
+                    };
+                    *edges.entry(key(a, b)).or_insert(0) += 1;
+                    assert!(edges.get(&key(a, b)) <= Some(&1));
+                }
+            }
+            for key in edges.keys() {
+                let reversed = [key[3], key[4], key[5], key[0], key[1], key[2]];
+                assert!(edges.contains_key(&reversed));
+            }
+
+            // The triangles wind outwards, so the volume they enclose is positive
+            let volume: f64 = prism
+                .triangles
+                .iter()
+                .map(|triangle| {
+                    triangle[0]
+                        .to_vec()
+                        .dot(triangle[1].to_vec().cross(triangle[2].to_vec()))
+                        / 6.0
+                })
+                .sum();
+            let expected = voronoi.get_face_area(prism.face) * height(prism.face);
+            assert!((volume - expected).abs() < 1e-12);
+        }
+
+        let mut stl = vec![];
+        write_stl(&prisms, &mut stl).unwrap();
+        let count: usize = prisms.iter().map(|prism| prism.triangles.len()).sum();
+        assert_eq!(stl.len(), 84 + 50 * count);
+        assert_eq!(
+            u32::from_le_bytes(stl[80..84].try_into().unwrap()) as usize,
+            count
+        );
+        let float = |offset: usize| f32::from_le_bytes(stl[offset..offset + 4].try_into().unwrap());
+        let triangle = prisms[0].triangles[0];
+        let normal = (triangle[1] - triangle[0])
+            .cross(triangle[2] - triangle[0])
+            .normalize();
+        for i in 0..3 {
+            assert_eq!(float(84 + 4 * i), normal[i] as f32);
+            assert_eq!(float(96 + 4 * i), triangle[0][i] as f32);
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use super::*;
+use cell::get_neighbours;
+use locator::HintGrid;
+use slotmap::SecondaryMap;
+use vector2::get_segment_distance;
+
+/// The distances from a point to its closest sites and to the edges of the diagram, as used for
+/// [cellular noise](https://en.wikipedia.org/wiki/Worley_noise).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CellularSample<K> {
+    /// The closest site.
+    pub nearest: K,
+    /// The second closest site, or `None` if there is only one site.
+    pub second_nearest: Option<K>,
+    /// The distance to the closest site, known as F1.
+    pub f1: f64,
+    /// The distance to the second closest site, known as F2, or infinity if there is only one.
+    pub f2: f64,
+    /// The distance to the nearest edge between the region of the closest site and another, or
+    /// infinity if there is only one site.
+    pub edge_distance: f64,
+}
+
+impl<K> CellularSample<K> {
+    /// Returns F2 - F1, which is zero along the edges of the diagram and rises towards the sites.
+    pub fn f2_minus_f1(&self) -> f64 {
+        self.f2 - self.f1
+    }
+}
+
+/// Samples cellular noise over the unit square from the sites of a diagram.
+///
+/// Each sample locates the region containing the point, after which the second closest site and
+/// the nearest edge are found among its neighbours.
+pub struct CellularNoise<'a> {
+    voronoi: &'a Diagram,
+    grid: HintGrid,
+}
+
+impl<'a> CellularNoise<'a> {
+    /// Constructs a sampler over the faces of a diagram.
+    pub fn new(voronoi: &'a Diagram) -> Self {
+        CellularNoise {
+            voronoi,
+            grid: HintGrid::new(voronoi),
+        }
+    }
+
+    /// Samples the noise at a point, returning `None` if it is outside of the unit square or the
+    /// diagram has no faces.
+    /// # Arguments
+    /// * `point` - the point to sample at.
+    pub fn sample(&self, point: cgmath::Point2<f64>) -> Option<CellularSample<FaceKey>> {
+        let nearest = self.grid.locate(self.voronoi, point)?;
+        Some(sample_at(self.voronoi, nearest, point))
+    }
+
+    /// Returns the distance from a point to the border of the region of a face, which is positive
+    /// inside of the region and negative outside of it.
+    /// # Arguments
+    /// * `point` - the point to measure from.
+    /// * `face` - the face whose region to measure to.
+    ///
+    /// # Panics
+    /// If the face index is invalid or it has no region.
+    pub fn signed_distance(&self, point: cgmath::Point2<f64>, face: FaceKey) -> f64 {
+        let polygon: Vec<cgmath::Point2<f64>> = self
+            .voronoi
+            .outer_edge_iter(face)
+            .map(|edge| self.voronoi.get_half_edge_origin_point(edge))
+            .collect();
+        let n = polygon.len();
+        let orientation = vector2::get_polygon_signed_area(&polygon);
+        let mut distance = f64::INFINITY;
+        let mut is_inside = true;
+        for i in 0..n {
+            let (start, end) = (polygon[i], polygon[(i + 1) % n]);
+            distance = distance.min(get_segment_distance(point, start, end));
+            if vector2::get_det(end - start, point - start) * orientation < 0.0 {
+                is_inside = false;
+            }
+        }
+        if is_inside {
+            distance
+        } else {
+            -distance
+        }
+    }
+}
+
+/// Samples cellular noise that repeats every unit along both axes.
+///
+/// The sites are repeated in a three by three block around the unit square, which is shrunk to
+/// fit the middle of the block into the diagram. Samples are taken in the middle, so every site
+/// within a unit of the point is present.
+pub struct TileableCellularNoise {
+    voronoi: Diagram,
+    grid: HintGrid,
+    sites: SecondaryMap<FaceKey, usize>,
+}
+
+impl TileableCellularNoise {
+    /// Constructs a sampler from a series of sites.
+    /// # Arguments
+    /// * `points` - the sites of one tile, these are wrapped into the range [0, 1) X [0, 1).
+    pub fn new(points: &[cgmath::Point2<f64>]) -> Self {
+        let mut tiled_points = Vec::with_capacity(points.len() * 9);
+        for offset_y in -1..=1 {
+            for offset_x in -1..=1 {
+                let offset = cgmath::Vector2::new(offset_x as f64 + 1.0, offset_y as f64 + 1.0);
+                for &point in points {
+                    tiled_points.push((wrap(point) + offset) / 3.0);
+                }
+            }
+        }
+        let voronoi = build_voronoi(&tiled_points);
+        let mut sites = SecondaryMap::new();
+        for (i, face) in voronoi.get_face_indices().into_iter().enumerate() {
+            sites.insert(face, i % points.len());
+        }
+        TileableCellularNoise {
+            grid: HintGrid::new(&voronoi),
+            voronoi,
+            sites,
+        }
+    }
+
+    /// Samples the noise at a point, returning `None` if there are no sites. Points a whole number
+    /// of units apart give the same sample.
+    /// # Arguments
+    /// * `point` - the point to sample at.
+    pub fn sample(&self, point: cgmath::Point2<f64>) -> Option<CellularSample<usize>> {
+        let point = (wrap(point) + cgmath::Vector2::new(1.0, 1.0)) / 3.0;
+        let nearest = self.grid.locate(&self.voronoi, point)?;
+        let sample = sample_at(&self.voronoi, nearest, point);
+        Some(CellularSample {
+            nearest: self.sites[sample.nearest],
+            second_nearest: sample.second_nearest.map(|face| self.sites[face]),
+            f1: sample.f1 * 3.0,
+            f2: sample.f2 * 3.0,
+            edge_distance: sample.edge_distance * 3.0,
+        })
+    }
+}
+
+/// Wraps a point into the range [0, 1) X [0, 1).
+fn wrap(point: cgmath::Point2<f64>) -> cgmath::Point2<f64> {
+    // Tiny negative coordinates would round up to one
+    let wrap_coordinate = |coordinate: f64| {
+        let wrapped = coordinate.rem_euclid(1.0);
+        if wrapped < 1.0 {
+            wrapped
+        } else {
+            0.0
+        }
+    };
+    cgmath::Point2::new(wrap_coordinate(point.x), wrap_coordinate(point.y))
+}
+
+/// Samples the noise at a point in the region of a face.
+///
+/// The second closest site always borders the region of the closest, as the line from the point
+/// to it only crosses the regions of closer sites.
+fn sample_at(
+    voronoi: &Diagram,
+    nearest: FaceKey,
+    point: cgmath::Point2<f64>,
+) -> CellularSample<FaceKey> {
+    let mut sample = CellularSample {
+        nearest,
+        second_nearest: None,
+        f1: point.distance(voronoi.get_face_point(nearest)),
+        f2: f64::INFINITY,
+        edge_distance: f64::INFINITY,
+    };
+    for neighbour in get_neighbours(voronoi, nearest) {
+        let distance = point.distance(voronoi.get_face_point(neighbour));
+        if distance < sample.f2 {
+            sample.f2 = distance;
+            sample.second_nearest = Some(neighbour);
+        }
+    }
+    if voronoi.get_face_outer_component(nearest).is_some() {
+        for edge in voronoi.outer_edge_iter(nearest) {
+            if voronoi.get_half_edge_twin(edge).is_some() {
+                let destination = voronoi.get_half_edge_destination(edge).unwrap();
+                sample.edge_distance = sample.edge_distance.min(get_segment_distance(
+                    point,
+                    voronoi.get_half_edge_origin_point(edge),
+                    voronoi.get_vertex_point(destination),
+                ));
+            }
+        }
+    }
+    sample
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::random_points;
+    use cgmath::{EuclideanSpace, InnerSpace};
+
+    /// Returns the two closest sites and their distances, and the distance to the nearest line
+    /// between the closest site and another.
+    fn sample_brute_force<
+        F: Fn(cgmath::Point2<f64>, cgmath::Point2<f64>) -> cgmath::Vector2<f64>,
+    >(
+        points: &[cgmath::Point2<f64>],
+        point: cgmath::Point2<f64>,
+        offset: F,
+    ) -> (usize, usize, f64, f64, f64) {
+        let mut order: Vec<usize> = (0..points.len()).collect();
+        order.sort_by(|&a, &b| {
+            let distance = |i: usize| offset(point, points[i]).magnitude();
+            distance(a).partial_cmp(&distance(b)).unwrap()
+        });
+        let (first, second) = (order[0], order[1]);
+        let to_first = offset(point, points[first]);
+        let edge_distance = order[1..]
+            .iter()
+            .map(|&i| {
+                let to_other = offset(point, points[i]);
+                (to_other.magnitude2() - to_first.magnitude2())
+                    / (2.0 * (to_other - to_first).magnitude())
+            })
+            .fold(f64::INFINITY, f64::min);
+        (
+            first,
+            second,
+            to_first.magnitude(),
+            offset(point, points[second]).magnitude(),
+            edge_distance,
+        )
+    }
+
+    #[test]
+    fn cellular_noise_test() {
+        let points = random_points(50, 300);
+        let voronoi = build_voronoi(&points);
+        let faces = voronoi.get_face_indices();
+        let noise = CellularNoise::new(&voronoi);
+        for query in random_points(51, 200) {
+            let query = cgmath::Point2::new(0.2, 0.2) + query.to_vec() * 0.6;
+            let sample = noise.sample(query).unwrap();
+            let (first, second, f1, f2, edge_distance) =
+                sample_brute_force(&points, query, |a, b| b - a);
+            assert_eq!(sample.nearest, faces[first]);
+            assert_eq!(sample.second_nearest, Some(faces[second]));
+            assert!((sample.f1 - f1).abs() < 1e-12);
+            assert!((sample.f2 - f2).abs() < 1e-12);
+            assert!((sample.f2_minus_f1() - (f2 - f1)).abs() < 1e-12);
+            assert!((sample.edge_distance - edge_distance).abs() < 1e-9);
+
+            assert!((noise.signed_distance(query, sample.nearest) - edge_distance).abs() < 1e-9);
+            assert!(noise.signed_distance(query, faces[second]) < 0.0);
+        }
+        assert!(noise.sample(cgmath::Point2::new(-0.1, 0.5)).is_none());
+    }
+
+    #[test]
+    fn tileable_cellular_noise_test() {
+        let points = random_points(52, 100);
+        let noise = TileableCellularNoise::new(&points);
+        // The shortest offset between two points when the square repeats
+        let wrapped_offset = |a: cgmath::Point2<f64>, b: cgmath::Point2<f64>| {
+            let offset = b - a;
+            cgmath::Vector2::new(offset.x - offset.x.round(), offset.y - offset.y.round())
+        };
+        for query in random_points(53, 200) {
+            let sample = noise.sample(query).unwrap();
+            let (first, second, f1, f2, edge_distance) =
+                sample_brute_force(&points, query, wrapped_offset);
+            assert_eq!(sample.nearest, first);
+            assert_eq!(sample.second_nearest, Some(second));
+            assert!((sample.f1 - f1).abs() < 1e-9);
+            assert!((sample.f2 - f2).abs() < 1e-9);
+            assert!((sample.edge_distance - edge_distance).abs() < 1e-9);
+
+            let shifted = noise
+                .sample(query + cgmath::Vector2::new(2.0, -1.0))
+                .unwrap();
+            assert_eq!(shifted.nearest, sample.nearest);
+            assert!((shifted.f1 - sample.f1).abs() < 1e-9);
+        }
+    }
+}
@@ -0,0 +1,479 @@
+use super::*;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+/// Receives a notification for every change fortune's algorithm makes while sweeping.
+///
+/// Arcs of the beachline are identified by the face of their site along with the faces of their
+/// left and right neighbours. All methods do nothing by default.
+pub trait SweepObserver {
+    /// Called before the first event with the diagram holding the faces that will be swept.
+    fn sweep_started(&mut self, _voronoi: &Diagram) {}
+
+    /// The sweep line reached the site of `face`, splitting the arc of `split_face` or creating
+    /// the first arc of the beachline if that is `None`.
+    fn site_event(&mut self, _face: FaceKey, _split_face: Option<FaceKey>, _y: f64) {}
+
+    /// The arc of `face` disappeared at `y`, adding `vertex` to the diagram.
+    fn circle_event(&mut self, _face: FaceKey, _vertex: VertexKey, _y: f64) {}
+
+    /// A circle event was scheduled at `y` for the arc of `face`.
+    fn event_added(
+        &mut self,
+        _left_face: FaceKey,
+        _face: FaceKey,
+        _right_face: FaceKey,
+        _y: f64,
+        _center: cgmath::Point2<f64>,
+    ) {
+    }
+
+    /// The circle event scheduled at `y` for an arc of `face` was cancelled.
+    fn event_deleted(&mut self, _face: FaceKey, _y: f64) {}
+
+    /// The arc of `face` was removed from the beachline, ending its edges at `vertex`.
+    fn arc_removed(
+        &mut self,
+        _left_face: FaceKey,
+        _face: FaceKey,
+        _right_face: FaceKey,
+        _vertex: VertexKey,
+    ) {
+    }
+}
+
+impl SweepObserver for () {}
+
+impl<T: SweepObserver + ?Sized> SweepObserver for &mut T {
+    fn sweep_started(&mut self, voronoi: &Diagram) {
+        (**self).sweep_started(voronoi)
+    }
+
+    fn site_event(&mut self, face: FaceKey, split_face: Option<FaceKey>, y: f64) {
+        (**self).site_event(face, split_face, y)
+    }
+
+    fn circle_event(&mut self, face: FaceKey, vertex: VertexKey, y: f64) {
+        (**self).circle_event(face, vertex, y)
+    }
+
+    fn event_added(
+        &mut self,
+        left_face: FaceKey,
+        face: FaceKey,
+        right_face: FaceKey,
+        y: f64,
+        center: cgmath::Point2<f64>,
+    ) {
+        (**self).event_added(left_face, face, right_face, y, center)
+    }
+
+    fn event_deleted(&mut self, face: FaceKey, y: f64) {
+        (**self).event_deleted(face, y)
+    }
+
+    fn arc_removed(
+        &mut self,
+        left_face: FaceKey,
+        face: FaceKey,
+        right_face: FaceKey,
+        vertex: VertexKey,
+    ) {
+        (**self).arc_removed(left_face, face, right_face, vertex)
+    }
+}
+
+/// An event recorded by an `EventRecorder`.
+///
+/// Faces are identified by the index of their site and vertices by the order in which circle
+/// events created them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecordedEvent {
+    Site {
+        face: usize,
+        split_face: Option<usize>,
+        y: f64,
+    },
+    Circle {
+        face: usize,
+        vertex: usize,
+        y: f64,
+    },
+    EventAdded {
+        left_face: usize,
+        face: usize,
+        right_face: usize,
+        y: f64,
+        center: cgmath::Point2<f64>,
+    },
+    EventDeleted {
+        face: usize,
+        y: f64,
+    },
+    ArcRemoved {
+        left_face: usize,
+        face: usize,
+        right_face: usize,
+        vertex: usize,
+    },
+}
+
+/// The first difference between the events of a log and the events of sweeping its sites again,
+/// returned by `EventRecorder::replay`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReplayMismatch {
+    /// The position of the event in the log.
+    pub index: usize,
+    /// The event in the log, `None` if the sweep had more events than the log.
+    pub recorded: Option<RecordedEvent>,
+    /// The event of the sweep, `None` if the sweep ended before the log.
+    pub replayed: Option<RecordedEvent>,
+}
+
+impl fmt::Display for ReplayMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Event {} was {:?} in the log but {:?} when replayed",
+            self.index, self.recorded, self.replayed
+        )
+    }
+}
+
+impl std::error::Error for ReplayMismatch {}
+
+/// A `SweepObserver` which records the sites and events of a sweep into a log that can be
+/// written out, read back and replayed.
+#[derive(Debug, Clone, Default)]
+pub struct EventRecorder {
+    sites: Vec<cgmath::Point2<f64>>,
+    events: Vec<RecordedEvent>,
+    faces: HashMap<FaceKey, usize>,
+    vertices: HashMap<VertexKey, usize>,
+}
+
+impl EventRecorder {
+    pub fn new() -> Self {
+        EventRecorder::default()
+    }
+
+    /// Returns the sites of the recorded sweep in the order they were supplied.
+    pub fn sites(&self) -> &[cgmath::Point2<f64>] {
+        &self.sites
+    }
+
+    /// Returns the recorded events in the order they happened.
+    pub fn events(&self) -> &[RecordedEvent] {
+        &self.events
+    }
+
+    /// Sweep the recorded sites again one event at a time, checking that each event matches the
+    /// log, and return the diagram.
+    ///
+    /// This fails with the first event that differs, so a log from an older version of the
+    /// sweep or one that has been edited is not mistaken for the current behaviour.
+    pub fn replay(&self) -> Result<Diagram, ReplayMismatch> {
+        let mut sweep = VoronoiSweep::with_observer(&self.sites, EventRecorder::new());
+        let mut checked = 0;
+        loop {
+            let stepped = sweep.step();
+            let replayed = sweep.observer().events();
+            for (index, &event) in replayed.iter().enumerate().skip(checked) {
+                if self.events.get(index) != Some(&event) {
+                    return Err(ReplayMismatch {
+                        index,
+                        recorded: self.events.get(index).cloned(),
+                        replayed: Some(event),
+                    });
+                }
+            }
+            checked = replayed.len();
+            if !stepped {
+                break;
+            }
+        }
+        if let Some(&event) = self.events.get(checked) {
+            return Err(ReplayMismatch {
+                index: checked,
+                recorded: Some(event),
+                replayed: None,
+            });
+        }
+        Ok(sweep.finish())
+    }
+
+    /// Write the log, one site or event per line.
+    pub fn write_log<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "sites {}", self.sites.len())?;
+        for site in &self.sites {
+            writeln!(writer, "{} {}", site.x, site.y)?;
+        }
+        for event in &self.events {
+            match *event {
+                RecordedEvent::Site {
+                    face,
+                    split_face,
+                    y,
+                } => match split_face {
+                    Some(split_face) => writeln!(writer, "site {} {} {}", face, split_face, y)?,
+                    None => writeln!(writer, "site {} - {}", face, y)?,
+                },
+                RecordedEvent::Circle { face, vertex, y } => {
+                    writeln!(writer, "circle {} {} {}", face, vertex, y)?
+                }
+                RecordedEvent::EventAdded {
+                    left_face,
+                    face,
+                    right_face,
+                    y,
+                    center,
+                } => writeln!(
+                    writer,
+                    "add {} {} {} {} {} {}",
+                    left_face, face, right_face, y, center.x, center.y
+                )?,
+                RecordedEvent::EventDeleted { face, y } => {
+                    writeln!(writer, "delete {} {}", face, y)?
+                }
+                RecordedEvent::ArcRemoved {
+                    left_face,
+                    face,
+                    right_face,
+                    vertex,
+                } => writeln!(
+                    writer,
+                    "remove {} {} {} {}",
+                    left_face, face, right_face, vertex
+                )?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Read a log written by `write_log`, restoring the recorded sites and events.
+    pub fn read_log<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut recorder = EventRecorder::new();
+        let mut lines = reader.lines();
+
+        let header = lines.next().unwrap_or_else(|| Ok(String::new()))?;
+        let site_count: usize = match header.split_whitespace().collect::<Vec<_>>()[..] {
+            ["sites", count] => parse_field(count)?,
+            _ => return Err(invalid_log(&header)),
+        };
+        for _ in 0..site_count {
+            let line = lines.next().unwrap_or_else(|| Ok(String::new()))?;
+            match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [x, y] => recorder
+                    .sites
+                    .push(cgmath::Point2::new(parse_field(x)?, parse_field(y)?)),
+                _ => return Err(invalid_log(&line)),
+            }
+        }
+
+        for line in lines {
+            let line = line?;
+            let event = match line.split_whitespace().collect::<Vec<_>>()[..] {
+                ["site", face, split_face, y] => RecordedEvent::Site {
+                    face: parse_field(face)?,
+                    split_face: match split_face {
+                        "-" => None,
+                        _ => Some(parse_field(split_face)?),
+                    },
+                    y: parse_field(y)?,
+                },
+                ["circle", face, vertex, y] => RecordedEvent::Circle {
+                    face: parse_field(face)?,
+                    vertex: parse_field(vertex)?,
+                    y: parse_field(y)?,
+                },
+                ["add", left_face, face, right_face, y, x, center_y] => RecordedEvent::EventAdded {
+                    left_face: parse_field(left_face)?,
+                    face: parse_field(face)?,
+                    right_face: parse_field(right_face)?,
+                    y: parse_field(y)?,
+                    center: cgmath::Point2::new(parse_field(x)?, parse_field(center_y)?),
+                },
+                ["delete", face, y] => RecordedEvent::EventDeleted {
+                    face: parse_field(face)?,
+                    y: parse_field(y)?,
+                },
+                ["remove", left_face, face, right_face, vertex] => RecordedEvent::ArcRemoved {
+                    left_face: parse_field(left_face)?,
+                    face: parse_field(face)?,
+                    right_face: parse_field(right_face)?,
+                    vertex: parse_field(vertex)?,
+                },
+                [] => continue,
+                _ => return Err(invalid_log(&line)),
+            };
+            recorder.events.push(event);
+        }
+        Ok(recorder)
+    }
+
+    fn face_index(&self, face: FaceKey) -> usize {
+        self.faces[&face]
+    }
+
+    fn vertex_index(&mut self, vertex: VertexKey) -> usize {
+        let count = self.vertices.len();
+        *self.vertices.entry(vertex).or_insert(count)
+    }
+}
+
+impl SweepObserver for EventRecorder {
+    fn sweep_started(&mut self, voronoi: &Diagram) {
+        self.sites.clear();
+        self.events.clear();
+        self.faces.clear();
+        self.vertices.clear();
+        for face in voronoi.get_face_indices() {
+            self.faces.insert(face, self.sites.len());
+            self.sites.push(voronoi.get_face_point(face));
+        }
+    }
+
+    fn site_event(&mut self, face: FaceKey, split_face: Option<FaceKey>, y: f64) {
+        self.events.push(RecordedEvent::Site {
+            face: self.face_index(face),
+            split_face: split_face.map(|split_face| self.face_index(split_face)),
+            y,
+        });
+    }
+
+    fn circle_event(&mut self, face: FaceKey, vertex: VertexKey, y: f64) {
+        let event = RecordedEvent::Circle {
+            face: self.face_index(face),
+            vertex: self.vertex_index(vertex),
+            y,
+        };
+        self.events.push(event);
+    }
+
+    fn event_added(
+        &mut self,
+        left_face: FaceKey,
+        face: FaceKey,
+        right_face: FaceKey,
+        y: f64,
+        center: cgmath::Point2<f64>,
+    ) {
+        self.events.push(RecordedEvent::EventAdded {
+            left_face: self.face_index(left_face),
+            face: self.face_index(face),
+            right_face: self.face_index(right_face),
+            y,
+            center,
+        });
+    }
+
+    fn event_deleted(&mut self, face: FaceKey, y: f64) {
+        self.events.push(RecordedEvent::EventDeleted {
+            face: self.face_index(face),
+            y,
+        });
+    }
+
+    fn arc_removed(
+        &mut self,
+        left_face: FaceKey,
+        face: FaceKey,
+        right_face: FaceKey,
+        vertex: VertexKey,
+    ) {
+        let event = RecordedEvent::ArcRemoved {
+            left_face: self.face_index(left_face),
+            face: self.face_index(face),
+            right_face: self.face_index(right_face),
+            vertex: self.vertex_index(vertex),
+        };
+        self.events.push(event);
+    }
+}
+
+fn parse_field<T: std::str::FromStr>(field: &str) -> io::Result<T> {
+    field.parse().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid field {}", field),
+        )
+    })
+}
+
+fn invalid_log(line: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid event log line {}", line),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::random_points;
+
+    #[test]
+    fn event_recorder_test() {
+        let points = random_points(12, 200);
+        let mut recorder = EventRecorder::new();
+        build_voronoi_with_observer(&points, &mut recorder);
+        assert_eq!(recorder.sites(), &points[..]);
+
+        let site_events = recorder
+            .events()
+            .iter()
+            .filter(|event| matches!(event, RecordedEvent::Site { .. }))
+            .count();
+        assert_eq!(site_events, points.len());
+
+        let mut log = vec![];
+        recorder.write_log(&mut log).unwrap();
+        let read = EventRecorder::read_log(&log[..]).unwrap();
+        assert_eq!(read.sites(), recorder.sites());
+        assert_eq!(read.events(), recorder.events());
+
+        // Replaying the sites goes through the same events and builds the same diagram
+        let voronoi = read.replay().unwrap();
+        let rebuilt = build_voronoi(&points);
+        assert_eq!(voronoi.get_edge_endpoints(), rebuilt.get_edge_endpoints());
+    }
+
+    #[test]
+    fn replay_mismatch_test() {
+        let points = random_points(13, 50);
+        let mut recorder = EventRecorder::new();
+        build_voronoi_with_observer(&points, &mut recorder);
+        let count = recorder.events().len();
+
+        // A changed event
+        let mut changed = recorder.clone();
+        let index = changed
+            .events
+            .iter()
+            .position(|event| matches!(event, RecordedEvent::Circle { .. }))
+            .unwrap();
+        if let RecordedEvent::Circle { ref mut y, .. } = changed.events[index] {
+            *y += 0.1;
+        }
+        let mismatch = changed.replay().err().unwrap();
+        assert_eq!(mismatch.index, index);
+        assert_eq!(mismatch.recorded, Some(changed.events[index]));
+        assert_eq!(mismatch.replayed, Some(recorder.events()[index]));
+
+        // A missing event
+        let mut truncated = recorder.clone();
+        truncated.events.pop();
+        let mismatch = truncated.replay().err().unwrap();
+        assert_eq!(mismatch.index, count - 1);
+        assert_eq!(mismatch.recorded, None);
+
+        // An extra event
+        let mut extended = recorder.clone();
+        extended
+            .events
+            .push(RecordedEvent::EventDeleted { face: 0, y: 1.0 });
+        let mismatch = extended.replay().err().unwrap();
+        assert_eq!(mismatch.index, count);
+        assert_eq!(mismatch.replayed, None);
+    }
+}
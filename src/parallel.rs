@@ -0,0 +1,293 @@
+use super::*;
+use cell::{replace_cells, Cell};
+use cgmath::MetricSpace;
+use log::warn;
+use rayon::prelude::*;
+use slotmap::SecondaryMap;
+
+/// The fewest sites that are given to each strip, below this the cost of sweeping the sites around
+/// the strips outweighs sweeping them in parallel.
+const MIN_STRIP_SITES: usize = 10_000;
+
+/// The number of sites either side of a strip that are first swept along with it, as a multiple of
+/// the square root of the number of sites. For evenly spread sites this covers a few regions
+/// beyond each side of the strip.
+const HALO_SITES_FACTOR: f64 = 3.0;
+
+/// Generates a voronoi diagram from a series of points, sweeping vertical strips of the diagram in
+/// parallel.
+///
+/// The sites are split by their x coordinate into a strip for each thread. Each strip is swept
+/// along with a halo of the sites either side of it, which is widened until no site left out could
+/// be closer to any part of the regions of the strip's own sites. The regions are then stitched
+/// together along the seams between the strips, giving the same faces, in the same order, and
+/// edges as `build_voronoi`. If the regions do not fit together, which can happen when sites are
+/// very close to being cocircular, the diagram is built sequentially instead.
+/// # Arguments
+/// * `points` - the points to generate the diagram from. These should be in the range
+///   [0,1] X [0,1].
+pub fn build_voronoi_parallel(points: &[cgmath::Point2<f64>]) -> Diagram {
+    build_voronoi_in_strips(points, get_strip_count(points.len()))
+}
+
+/// Returns the number of strips to sweep in parallel for a number of sites.
+fn get_strip_count(site_count: usize) -> usize {
+    rayon::current_num_threads().min(site_count / MIN_STRIP_SITES)
+}
+
+fn build_voronoi_in_strips(points: &[cgmath::Point2<f64>], strip_count: usize) -> Diagram {
+    if strip_count < 2 {
+        return build_voronoi(points);
+    }
+
+    let mut order: Vec<usize> = (0..points.len()).collect();
+    order.par_sort_unstable_by(|&a, &b| points[a].x.partial_cmp(&points[b].x).unwrap());
+
+    let mut voronoi = Diagram::new();
+    voronoi.set_face_points(points);
+    let faces = voronoi.get_face_indices();
+
+    // usize::div_ceil needs a newer compiler than the crate otherwise supports
+    #[allow(clippy::manual_div_ceil)]
+    let strip_size = (points.len() + strip_count - 1) / strip_count;
+    let strip_starts: Vec<usize> = (0..points.len()).step_by(strip_size).collect();
+    let cells: Vec<(FaceKey, Cell)> = strip_starts
+        .into_par_iter()
+        .flat_map(|start| {
+            let end = (start + strip_size).min(points.len());
+            build_strip(points, &order, &faces, start, end)
+        })
+        .collect();
+
+    if !replace_cells(&mut voronoi, &cells, &[]) {
+        warn!("The regions of the strips do not fit together, building the diagram sequentially");
+        return build_voronoi(points);
+    }
+    voronoi
+}
+
+/// Performs [Lloyd's algorithm](https://en.wikipedia.org/wiki/Lloyd%27s_algorithm) on the supplied
+/// points, building each diagram in strips as `build_voronoi_parallel` does and computing the
+/// centroids of the regions in parallel.
+///
+/// This gives the same points as `lloyds_relaxation`, up to rounding in the centroids. With too few
+/// points to split into strips each diagram is swept sequentially, reusing the builder and diagram
+/// between iterations.
+/// # Arguments
+/// * `points` - The initial points, these should be in the range [0, 1] X [0,1].
+/// * `iterations` - The number of iterations of that we should perform.
+pub fn lloyds_relaxation_parallel(
+    points: &[cgmath::Point2<f64>],
+    iterations: usize,
+) -> Vec<cgmath::Point2<f64>> {
+    lloyds_relaxation_in_strips(points, iterations, get_strip_count(points.len()))
+}
+
+fn lloyds_relaxation_in_strips(
+    points: &[cgmath::Point2<f64>],
+    iterations: usize,
+    strip_count: usize,
+) -> Vec<cgmath::Point2<f64>> {
+    let mut points = points.to_vec();
+    let mut builder = VoronoiBuilder::new();
+    let mut voronoi = Diagram::new();
+    for _ in 0..iterations {
+        if strip_count < 2 {
+            builder.build(&points, &mut voronoi);
+        } else {
+            voronoi = build_voronoi_in_strips(&points, strip_count);
+        }
+        voronoi
+            .get_face_indices()
+            .par_iter()
+            .map(|&face| voronoi.face_centroid(face))
+            .collect_into_vec(&mut points);
+    }
+    points
+}
+
+/// Computes the regions of the sites from `start` to `end` in order of their x coordinate.
+///
+/// # Arguments
+/// * `points` - all of the sites.
+/// * `order` - the indices of the sites sorted by their x coordinate.
+/// * `faces` - the face of each site in the final diagram.
+/// * `start` - the position in `order` of the first site of the strip.
+/// * `end` - the position in `order` after the last site of the strip.
+fn build_strip(
+    points: &[cgmath::Point2<f64>],
+    order: &[usize],
+    faces: &[FaceKey],
+    start: usize,
+    end: usize,
+) -> Vec<(FaceKey, Cell)> {
+    let mut halo = (((order.len() as f64).sqrt() * HALO_SITES_FACTOR) as usize).max(1);
+    loop {
+        let halo_start = start.saturating_sub(halo);
+        let halo_end = (end + halo).min(order.len());
+        // Every site that is left out lies on or beyond these
+        let left_bound = if halo_start == 0 {
+            f64::NEG_INFINITY
+        } else {
+            points[order[halo_start - 1]].x
+        };
+        let right_bound = if halo_end == order.len() {
+            f64::INFINITY
+        } else {
+            points[order[halo_end]].x
+        };
+
+        let sites: Vec<cgmath::Point2<f64>> = order[halo_start..halo_end]
+            .iter()
+            .map(|&index| points[index])
+            .collect();
+        let strip = build_voronoi(&sites);
+        let strip_faces = strip.get_face_indices();
+        let mut site_indices = SecondaryMap::new();
+        for (i, &face) in strip_faces.iter().enumerate() {
+            site_indices.insert(face, order[halo_start + i]);
+        }
+
+        // A region is complete if the circle around each of its vertices that passes through its
+        // site is clear of the sites that were left out
+        let cells: Option<Vec<(FaceKey, Cell)>> = (start..end)
+            .map(|i| {
+                let face = strip_faces[i - halo_start];
+                let site = points[order[i]];
+                let mut vertices = vec![];
+                for edge in strip.outer_edge_iter(face) {
+                    let point = strip.get_half_edge_origin_point(edge);
+                    let radius = point.distance(site);
+                    if point.x - radius <= left_bound || point.x + radius >= right_bound {
+                        return None;
+                    }
+                    let neighbour = strip.get_half_edge_twin(edge).map(|twin| {
+                        faces[site_indices[strip.get_half_edge_incident_face(twin).unwrap()]]
+                    });
+                    vertices.push((point, neighbour));
+                }
+                Some((faces[order[i]], Cell::from_vertices(vertices)))
+            })
+            .collect();
+
+        match cells {
+            Some(cells) => return cells,
+            None => halo *= 2,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{assert_consistent, random_points};
+    use rand::{Rng, SeedableRng};
+
+    /// Checks every face has the same edges, with the same neighbours and vertices, in both
+    /// diagrams.
+    fn assert_same_regions(voronoi: &Diagram, expected: &Diagram) {
+        let faces = voronoi.get_face_indices();
+        assert_eq!(faces, expected.get_face_indices());
+        assert_eq!(
+            voronoi.get_vertex_points().len(),
+            expected.get_vertex_points().len()
+        );
+        let get_region = |diagram: &Diagram, face| -> Vec<(cgmath::Point2<f64>, Option<FaceKey>)> {
+            diagram
+                .outer_edge_iter(face)
+                .map(|edge| {
+                    let neighbour = diagram
+                        .get_half_edge_twin(edge)
+                        .and_then(|twin| diagram.get_half_edge_incident_face(twin));
+                    (diagram.get_half_edge_origin_point(edge), neighbour)
+                })
+                .collect()
+        };
+        for face in faces {
+            let region = get_region(voronoi, face);
+            let expected_region = get_region(expected, face);
+            assert_eq!(region.len(), expected_region.len());
+            // The regions may start from different edges
+            let shift = (0..expected_region.len())
+                .min_by(|&a, &b| {
+                    let distance = |i: usize| expected_region[i].0.distance2(region[0].0);
+                    distance(a).partial_cmp(&distance(b)).unwrap()
+                })
+                .unwrap();
+            for (i, &(point, neighbour)) in region.iter().enumerate() {
+                let (expected_point, expected_neighbour) =
+                    expected_region[(i + shift) % expected_region.len()];
+                assert!(point.distance(expected_point) < 1e-9);
+                assert_eq!(neighbour, expected_neighbour);
+            }
+        }
+    }
+
+    #[test]
+    fn build_voronoi_in_strips_test() {
+        for seed in 0..5 {
+            let points = random_points(seed, 2000);
+            let voronoi = build_voronoi_in_strips(&points, 4);
+            assert_consistent(&voronoi);
+            assert_same_regions(&voronoi, &build_voronoi(&points));
+        }
+    }
+
+    #[test]
+    fn build_voronoi_in_strips_clustered_test() {
+        // Most sites are in a narrow column, so the regions of the sites either side reach far
+        // across the strips
+        let mut rng = rand::rngs::StdRng::seed_from_u64(3);
+        let points: Vec<cgmath::Point2<f64>> = (0..1000)
+            .map(|i| {
+                if i % 50 == 0 {
+                    cgmath::Point2::new(rng.gen(), rng.gen())
+                } else {
+                    cgmath::Point2::new(0.5 + rng.gen::<f64>() * 0.01, rng.gen())
+                }
+            })
+            .collect();
+        let voronoi = build_voronoi_in_strips(&points, 8);
+        assert_consistent(&voronoi);
+        assert_same_regions(&voronoi, &build_voronoi(&points));
+    }
+
+    #[test]
+    fn lloyds_relaxation_parallel_test() {
+        let points = random_points(10, 500);
+        assert_eq!(
+            lloyds_relaxation_parallel(&points, 5),
+            lloyds_relaxation(&points, 5)
+        );
+    }
+
+    #[test]
+    fn lloyds_relaxation_in_strips_test() {
+        let points = random_points(11, 2000);
+        let relaxed = lloyds_relaxation_in_strips(&points, 5, 4);
+        for (point, expected) in relaxed.iter().zip(lloyds_relaxation(&points, 5)) {
+            assert!(point.distance(expected) < 1e-9);
+        }
+    }
+
+    #[test]
+    #[ignore = "compares timings, run with cargo test --release --features parallel -- --ignored"]
+    fn lloyds_relaxation_parallel_speed_test() {
+        let points = random_points(12, 200_000);
+        let start = std::time::Instant::now();
+        lloyds_relaxation(&points, 3);
+        let sequential = start.elapsed();
+        let start = std::time::Instant::now();
+        lloyds_relaxation_parallel(&points, 3);
+        let parallel = start.elapsed();
+        if rayon::current_num_threads() >= 4 {
+            assert!(parallel * 2 < sequential);
+        }
+    }
+
+    #[test]
+    fn build_voronoi_parallel_test() {
+        let points = random_points(9, 500);
+        assert_same_regions(&build_voronoi_parallel(&points), &build_voronoi(&points));
+    }
+}
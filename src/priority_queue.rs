@@ -0,0 +1,226 @@
+/// A handle to an item in a `PriorityQueue` that can be used to remove it before it is popped.
+///
+/// Once the item has left the queue the handle becomes stale and removing it has no effect.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QueueIndex {
+    slot: usize,
+    generation: u32,
+}
+
+impl QueueIndex {
+    /// Constructs a handle that does not refer to any item.
+    pub fn new() -> Self {
+        QueueIndex {
+            slot: usize::MAX,
+            generation: 0,
+        }
+    }
+}
+
+impl Default for QueueIndex {
+    fn default() -> Self {
+        QueueIndex::new()
+    }
+}
+
+struct Entry<T> {
+    item: T,
+    position: usize,
+}
+
+/// A slot in the queue's storage, the generation is bumped every time the slot is vacated so that
+/// stale handles can be detected.
+struct Slot<T> {
+    entry: Option<Entry<T>>,
+    generation: u32,
+}
+
+/// A binary min heap whose items can be removed from the middle of the heap.
+///
+/// Items are stored in an arena and the heap itself holds only their keys, each item records its
+/// position in the heap so that it can be found from its `QueueIndex`.
+pub struct PriorityQueue<T: PartialOrd> {
+    slots: Vec<Slot<T>>,
+    free: Vec<usize>,
+    heap: Vec<usize>,
+}
+
+impl<T: PartialOrd> Default for PriorityQueue<T> {
+    fn default() -> Self {
+        PriorityQueue::new()
+    }
+}
+
+impl<T: PartialOrd> PriorityQueue<T> {
+    /// Constructs a new empty queue.
+    pub fn new() -> Self {
+        PriorityQueue {
+            slots: vec![],
+            free: vec![],
+            heap: vec![],
+        }
+    }
+
+    /// Removes every item from the queue, keeping the allocated storage.
+    pub fn clear(&mut self) {
+        self.free.clear();
+        for (slot_index, slot) in self.slots.iter_mut().enumerate() {
+            if slot.entry.take().is_some() {
+                slot.generation = slot.generation.wrapping_add(1);
+            }
+            self.free.push(slot_index);
+        }
+        self.heap.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    /// Adds an item to the queue, returning a handle that can be used to remove it.
+    pub fn push(&mut self, item: T) -> QueueIndex {
+        let position = self.heap.len();
+        let entry = Some(Entry { item, position });
+        let slot = match self.free.pop() {
+            Some(slot) => {
+                self.slots[slot].entry = entry;
+                slot
+            }
+            None => {
+                self.slots.push(Slot {
+                    entry,
+                    generation: 0,
+                });
+                self.slots.len() - 1
+            }
+        };
+        self.heap.push(slot);
+        self.sift_up(position);
+        QueueIndex {
+            slot,
+            generation: self.slots[slot].generation,
+        }
+    }
+
+    /// Removes and returns the smallest item in the queue.
+    pub fn pop(&mut self) -> Option<T> {
+        if self.heap.is_empty() {
+            None
+        } else {
+            Some(self.remove_at(0))
+        }
+    }
+
+    /// Removes the item a handle refers to, returning it if it was still in the queue.
+    pub fn remove(&mut self, index: QueueIndex) -> Option<T> {
+        let position = self.entry(index)?.position;
+        Some(self.remove_at(position))
+    }
+
+    /// Iterates over the items in the queue in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        self.heap.iter().map(move |&slot| self.item(slot))
+    }
+
+    fn remove_at(&mut self, position: usize) -> T {
+        let last = self.heap.len() - 1;
+        self.swap(position, last);
+        let slot = self.heap.pop().unwrap();
+        if position < self.heap.len() {
+            self.sift_down(position);
+            self.sift_up(position);
+        }
+        let entry = self.slots[slot].entry.take().unwrap();
+        self.slots[slot].generation = self.slots[slot].generation.wrapping_add(1);
+        self.free.push(slot);
+        entry.item
+    }
+
+    fn entry(&self, index: QueueIndex) -> Option<&Entry<T>> {
+        let slot = self.slots.get(index.slot)?;
+        if slot.generation == index.generation {
+            slot.entry.as_ref()
+        } else {
+            None
+        }
+    }
+
+    fn item(&self, slot: usize) -> &T {
+        &self.slots[slot].entry.as_ref().unwrap().item
+    }
+
+    fn less(&self, a: usize, b: usize) -> bool {
+        self.item(self.heap[a]) < self.item(self.heap[b])
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        self.heap.swap(a, b);
+        for &position in &[a, b] {
+            let slot = self.heap[position];
+            self.slots[slot].entry.as_mut().unwrap().position = position;
+        }
+    }
+
+    fn sift_up(&mut self, mut position: usize) {
+        while position > 0 {
+            let parent = (position - 1) / 2;
+            if !self.less(position, parent) {
+                break;
+            }
+            self.swap(position, parent);
+            position = parent;
+        }
+    }
+
+    fn sift_down(&mut self, mut position: usize) {
+        loop {
+            let left = 2 * position + 1;
+            let right = left + 1;
+            let mut smallest = position;
+            if left < self.heap.len() && self.less(left, smallest) {
+                smallest = left;
+            }
+            if right < self.heap.len() && self.less(right, smallest) {
+                smallest = right;
+            }
+            if smallest == position {
+                break;
+            }
+            self.swap(position, smallest);
+            position = smallest;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn priority_queue_test() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+        let mut queue = PriorityQueue::new();
+        let mut items: Vec<(f64, QueueIndex)> = vec![];
+        for _ in 0..1000 {
+            let item: f64 = rng.gen();
+            items.push((item, queue.push(item)));
+        }
+
+        // Remove half of the items from the middle of the queue
+        for (item, index) in items.drain(..500) {
+            assert_eq!(queue.remove(index), Some(item));
+            assert_eq!(queue.remove(index), None);
+        }
+        assert_eq!(queue.len(), 500);
+        assert_eq!(queue.iter().count(), 500);
+
+        items.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        for (item, index) in items {
+            assert_eq!(queue.pop(), Some(item));
+            assert_eq!(queue.remove(index), None);
+        }
+        assert_eq!(queue.pop(), None);
+        assert_eq!(queue.remove(QueueIndex::new()), None);
+    }
+}
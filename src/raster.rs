@@ -0,0 +1,407 @@
+use super::*;
+use slotmap::SecondaryMap;
+
+/// A grid of pixels over the unit square, each holding the face whose region contains its center.
+///
+/// Pixel (`column`, `row`) covers x from `column / width` to `(column + 1) / width` and y from
+/// `row / height` to `(row + 1) / height`, so the first row is at the top of the square where y
+/// is 0.
+#[derive(Clone, Debug)]
+pub struct FaceRaster {
+    width: usize,
+    height: usize,
+    faces: Vec<Option<FaceKey>>,
+}
+
+impl FaceRaster {
+    /// Returns the number of pixels in each row.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the number of rows.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns the face of every pixel, row by row. Pixels are only empty if the diagram has no
+    /// regions.
+    pub fn faces(&self) -> &[Option<FaceKey>] {
+        &self.faces
+    }
+
+    /// Returns the face of a pixel.
+    ///
+    /// # Panics
+    /// If the pixel is outside of the grid.
+    pub fn get_face(&self, column: usize, row: usize) -> Option<FaceKey> {
+        assert!(column < self.width && row < self.height);
+        self.faces[row * self.width + column]
+    }
+
+    /// Returns the position of the face of every pixel in `get_face_indices`, row by row, which is
+    /// the index of its site for a diagram built from a series of points.
+    /// # Arguments
+    /// * `voronoi` - the diagram that was rasterized.
+    pub fn site_indices(&self, voronoi: &Diagram) -> Vec<Option<usize>> {
+        let mut indices = SecondaryMap::new();
+        for (index, face) in voronoi.get_face_indices().into_iter().enumerate() {
+            indices.insert(face, index);
+        }
+        self.faces
+            .iter()
+            .map(|face| face.and_then(|face| indices.get(face).cloned()))
+            .collect()
+    }
+
+    /// Returns the distance from the center of every pixel to the nearest edge between its face
+    /// and another, row by row. The border of the diagram is not an edge, so this is infinite
+    /// for pixels whose face has no neighbours.
+    /// # Arguments
+    /// * `voronoi` - the diagram that was rasterized.
+    pub fn edge_distances(&self, voronoi: &Diagram) -> Vec<f64> {
+        let mut face_edges = SecondaryMap::new();
+        for face in voronoi.get_face_indices() {
+            if voronoi.get_face_outer_component(face).is_none() {
+                continue;
+            }
+            let edges: Vec<(cgmath::Point2<f64>, cgmath::Point2<f64>)> = voronoi
+                .outer_edge_iter(face)
+                .filter(|&edge| voronoi.get_half_edge_twin(edge).is_some())
+                .map(|edge| {
+                    let destination = voronoi.get_half_edge_destination(edge).unwrap();
+                    (
+                        voronoi.get_half_edge_origin_point(edge),
+                        voronoi.get_vertex_point(destination),
+                    )
+                })
+                .collect();
+            face_edges.insert(face, edges);
+        }
+
+        (0..self.faces.len())
+            .map(|i| {
+                let center = pixel_center(i % self.width, i / self.width, self.width, self.height);
+                match self.faces[i].and_then(|face| face_edges.get(face)) {
+                    Some(edges) => edges
+                        .iter()
+                        .map(|&(start, end)| vector2::get_segment_distance(center, start, end))
+                        .fold(f64::INFINITY, f64::min),
+                    None => f64::INFINITY,
+                }
+            })
+            .collect()
+    }
+}
+
+/// A grid of pixels over the unit square, each holding the fraction of its area covered by each
+/// of the regions that overlap it, for drawing the regions with smooth edges.
+///
+/// The pixels are laid out in the same way as a `FaceRaster`.
+#[derive(Clone, Debug)]
+pub struct CoverageRaster {
+    width: usize,
+    height: usize,
+    // The coverage of pixel i is in coverage[offsets[i]..offsets[i + 1]]
+    offsets: Vec<usize>,
+    coverage: Vec<(FaceKey, f64)>,
+}
+
+impl CoverageRaster {
+    /// Returns the number of pixels in each row.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the number of rows.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns the faces whose regions overlap a pixel, each with the fraction of the pixel that
+    /// it covers. The fractions add up to one.
+    ///
+    /// # Panics
+    /// If the pixel is outside of the grid.
+    pub fn get_coverage(&self, column: usize, row: usize) -> &[(FaceKey, f64)] {
+        assert!(column < self.width && row < self.height);
+        let i = row * self.width + column;
+        &self.coverage[self.offsets[i]..self.offsets[i + 1]]
+    }
+}
+
+/// Scan converts the regions of a diagram into a grid, giving each pixel the face whose region
+/// contains its center.
+///
+/// Every pixel center on an edge is given to exactly one of the faces either side of it.
+/// # Arguments
+/// * `voronoi` - the diagram to rasterize.
+/// * `width` - the number of pixels in each row.
+/// * `height` - the number of rows.
+pub fn rasterize(voronoi: &Diagram, width: usize, height: usize) -> FaceRaster {
+    let mut faces = vec![None; width * height];
+    for face in voronoi.get_face_indices() {
+        let polygon = get_face_polygon(voronoi, face);
+        if polygon.is_empty() {
+            continue;
+        }
+        // Only the rows whose centers may be within the region
+        let (top, bottom) = get_extent(&polygon, |point| point.y);
+        let first_row = (top * height as f64).floor().max(0.0) as usize;
+        let last_row = ((bottom * height as f64).ceil() as usize).min(height);
+        for row in first_row..last_row {
+            let y = (row as f64 + 0.5) / height as f64;
+            if let Some((left, right)) = get_span(&polygon, y) {
+                // Pixels whose centers are in the half open span
+                let start = (left * width as f64 - 0.5).ceil().max(0.0) as usize;
+                let end = ((right * width as f64 - 0.5).ceil().max(0.0) as usize).min(width);
+                for pixel in &mut faces[row * width + start.min(end)..row * width + end] {
+                    *pixel = Some(face);
+                }
+            }
+        }
+    }
+    FaceRaster {
+        width,
+        height,
+        faces,
+    }
+}
+
+/// Computes how much of each pixel of a grid is covered by each region of a diagram.
+///
+/// The regions are clipped to the rows and then to the pixels that they overlap, so the coverage
+/// is exact rather than sampled.
+/// # Arguments
+/// * `voronoi` - the diagram to rasterize.
+/// * `width` - the number of pixels in each row.
+/// * `height` - the number of rows.
+pub fn rasterize_coverage(voronoi: &Diagram, width: usize, height: usize) -> CoverageRaster {
+    let pixel_area = 1.0 / (width * height) as f64;
+    let mut pixels: Vec<(usize, FaceKey, f64)> = vec![];
+    for face in voronoi.get_face_indices() {
+        let polygon = get_face_polygon(voronoi, face);
+        if polygon.is_empty() {
+            continue;
+        }
+        let (top, bottom) = get_extent(&polygon, |point| point.y);
+        let first_row = (top * height as f64).floor().max(0.0) as usize;
+        let last_row = ((bottom * height as f64).ceil() as usize).min(height);
+        for row in first_row..last_row {
+            let strip = clip_polygon(&polygon, |point| point.y, row as f64 / height as f64, true);
+            let strip = clip_polygon(
+                &strip,
+                |point| point.y,
+                (row + 1) as f64 / height as f64,
+                false,
+            );
+            if strip.is_empty() {
+                continue;
+            }
+            let (left, right) = get_extent(&strip, |point| point.x);
+            let first_column = (left * width as f64).floor().max(0.0) as usize;
+            let last_column = ((right * width as f64).ceil() as usize).min(width);
+            for column in first_column..last_column {
+                let x = column as f64 / width as f64;
+                let pixel = clip_polygon(&strip, |point| point.x, x, true);
+                let pixel = clip_polygon(
+                    &pixel,
+                    |point| point.x,
+                    (column + 1) as f64 / width as f64,
+                    false,
+                );
+                let coverage = vector2::get_polygon_signed_area(&pixel).abs() / pixel_area;
+                if coverage > 0.0 {
+                    pixels.push((row * width + column, face, coverage.min(1.0)));
+                }
+            }
+        }
+    }
+
+    pixels.sort_by_key(|&(pixel, _, _)| pixel);
+    let mut offsets = Vec::with_capacity(width * height + 1);
+    let mut coverage = Vec::with_capacity(pixels.len());
+    let mut next = 0;
+    for pixel in 0..width * height {
+        offsets.push(coverage.len());
+        let start = coverage.len();
+        while next < pixels.len() && pixels[next].0 == pixel {
+            coverage.push((pixels[next].1, pixels[next].2));
+            next += 1;
+        }
+        // Remove the rounding errors so the pixel is exactly covered
+        let total: f64 = coverage[start..].iter().map(|&(_, amount)| amount).sum();
+        for (_, amount) in &mut coverage[start..] {
+            *amount /= total;
+        }
+    }
+    offsets.push(coverage.len());
+    CoverageRaster {
+        width,
+        height,
+        offsets,
+        coverage,
+    }
+}
+
+fn pixel_center(column: usize, row: usize, width: usize, height: usize) -> cgmath::Point2<f64> {
+    cgmath::Point2::new(
+        (column as f64 + 0.5) / width as f64,
+        (row as f64 + 0.5) / height as f64,
+    )
+}
+
+fn get_face_polygon(voronoi: &Diagram, face: FaceKey) -> Vec<cgmath::Point2<f64>> {
+    if voronoi.get_face_outer_component(face).is_none() {
+        return vec![];
+    }
+    voronoi
+        .outer_edge_iter(face)
+        .map(|edge| voronoi.get_half_edge_origin_point(edge))
+        .collect()
+}
+
+/// Returns the part of a convex polygon along a horizontal line, including the left end but not
+/// the right.
+///
+/// The points where edges cross the line are computed from their endpoints in a fixed order, so
+/// neighbouring regions give the same point for the edge they share.
+fn get_span(polygon: &[cgmath::Point2<f64>], y: f64) -> Option<(f64, f64)> {
+    let mut crossings = vec![];
+    for i in 0..polygon.len() {
+        let (a, b) = (polygon[i], polygon[(i + 1) % polygon.len()]);
+        let (a, b) = if (a.y, a.x) < (b.y, b.x) {
+            (a, b)
+        } else {
+            (b, a)
+        };
+        if a.y <= y && y < b.y {
+            crossings.push(a.x + (b.x - a.x) * (y - a.y) / (b.y - a.y));
+        }
+    }
+    let (left, right) = get_extent_of(crossings.into_iter());
+    if left < right {
+        Some((left, right))
+    } else {
+        None
+    }
+}
+
+fn get_extent<F: Fn(cgmath::Point2<f64>) -> f64>(
+    polygon: &[cgmath::Point2<f64>],
+    coordinate: F,
+) -> (f64, f64) {
+    get_extent_of(polygon.iter().map(|&point| coordinate(point)))
+}
+
+fn get_extent_of<I: Iterator<Item = f64>>(values: I) -> (f64, f64) {
+    values.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), value| {
+        (min.min(value), max.max(value))
+    })
+}
+
+/// Clips a convex polygon to the part where a coordinate is above a value, or below it if `above`
+/// is false.
+fn clip_polygon<F: Fn(cgmath::Point2<f64>) -> f64>(
+    polygon: &[cgmath::Point2<f64>],
+    coordinate: F,
+    value: f64,
+    above: bool,
+) -> Vec<cgmath::Point2<f64>> {
+    let distance = |point| {
+        if above {
+            coordinate(point) - value
+        } else {
+            value - coordinate(point)
+        }
+    };
+    let mut clipped = Vec::with_capacity(polygon.len() + 1);
+    for i in 0..polygon.len() {
+        let (point, next_point) = (polygon[i], polygon[(i + 1) % polygon.len()]);
+        let (point_distance, next_distance) = (distance(point), distance(next_point));
+        if point_distance >= 0.0 {
+            clipped.push(point);
+        }
+        if (point_distance >= 0.0) != (next_distance >= 0.0) {
+            let t = point_distance / (point_distance - next_distance);
+            clipped.push(point + (next_point - point) * t);
+        }
+    }
+    clipped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::random_points;
+    use cgmath::MetricSpace;
+
+    #[test]
+    fn rasterize_test() {
+        let voronoi = build_voronoi(&random_points(40, 100));
+        let raster = rasterize(&voronoi, 97, 64);
+        let faces = voronoi.get_face_indices();
+        let indices = raster.site_indices(&voronoi);
+        for row in 0..raster.height() {
+            for column in 0..raster.width() {
+                let center = pixel_center(column, row, raster.width(), raster.height());
+                let face = raster.get_face(column, row).unwrap();
+                // The face is the closest, or tied for closest on an edge
+                let distance = center.distance(voronoi.get_face_point(face));
+                for &other in &faces {
+                    assert!(distance <= center.distance(voronoi.get_face_point(other)) + 1e-12);
+                }
+                let index = indices[row * raster.width() + column].unwrap();
+                assert_eq!(faces[index], face);
+            }
+        }
+        assert!(rasterize(&Diagram::new(), 4, 4)
+            .faces()
+            .iter()
+            .all(Option::is_none));
+    }
+
+    #[test]
+    fn edge_distances_test() {
+        // Two regions split at x = 0.5
+        let voronoi = build_voronoi(&[
+            cgmath::Point2::new(0.25, 0.5),
+            cgmath::Point2::new(0.75, 0.5),
+        ]);
+        let raster = rasterize(&voronoi, 4, 2);
+        let distances = raster.edge_distances(&voronoi);
+        for row in 0..2 {
+            assert!((distances[row * 4] - 0.375).abs() < 1e-12);
+            assert!((distances[row * 4 + 1] - 0.125).abs() < 1e-12);
+            assert!((distances[row * 4 + 2] - 0.125).abs() < 1e-12);
+            assert!((distances[row * 4 + 3] - 0.375).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn rasterize_coverage_test() {
+        let voronoi = build_voronoi(&random_points(41, 50));
+        let coverage = rasterize_coverage(&voronoi, 40, 30);
+        let raster = rasterize(&voronoi, 40, 30);
+        let mut face_coverage = SecondaryMap::new();
+        for row in 0..30 {
+            for column in 0..40 {
+                let pixel = coverage.get_coverage(column, row);
+                let total: f64 = pixel.iter().map(|&(_, amount)| amount).sum();
+                assert!((total - 1.0).abs() < 1e-9);
+                // The face at the center of the pixel covers part of it
+                let face = raster.get_face(column, row).unwrap();
+                assert!(pixel.iter().any(|&(f, _)| f == face));
+                for &(face, amount) in pixel {
+                    let total = face_coverage.get(face).cloned().unwrap_or(0.0);
+                    face_coverage.insert(face, total + amount);
+                }
+            }
+        }
+        // The coverage of each face adds up to its area in pixels
+        for face in voronoi.get_face_indices() {
+            let area = voronoi.get_face_area(face) * 1200.0;
+            assert!((face_coverage[face] - area).abs() < 1e-6);
+        }
+    }
+}
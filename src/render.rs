@@ -0,0 +1,258 @@
+use super::*;
+use cell::get_counter_clockwise_ring;
+use cgmath::InnerSpace;
+
+/// The point each region is split into triangles around by `to_render_mesh`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FanCenter {
+    /// The point of the face.
+    Site,
+    /// The centroid of the region.
+    Centroid,
+}
+
+/// Vertex and index buffers of triangles covering the regions of a diagram, ready to be uploaded
+/// for drawing.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RenderMesh {
+    /// The positions of the vertices. Regions do not share vertices, so attributes given per
+    /// vertex can be flat across each region.
+    pub vertices: Vec<[f32; 2]>,
+    /// The vertices of each triangle, three per triangle.
+    pub indices: Vec<u32>,
+    /// The position in `get_face_indices` of the face of each triangle.
+    pub face_ids: Vec<u32>,
+    /// The position in `get_face_indices` of the face of each vertex.
+    pub vertex_face_ids: Vec<u32>,
+}
+
+/// Splits the region of each face of a diagram into a fan of triangles around a center, returning
+/// them as vertex and index buffers.
+///
+/// The triangles wind the same way as the rings of [`get_counter_clockwise_ring`].
+///
+/// Each region can be inset to leave gaps between them, which moves each of its edges inwards by a
+/// distance. A region that disappears is left out, and if the center would be left outside of it
+/// the triangles are fanned around the centroid of what is left instead.
+/// # Arguments
+/// * `voronoi` - the diagram to split the regions of.
+/// * `center` - the point to split each region around.
+/// * `inset` - how far to move the edges of the region of each face inwards, zero to keep it.
+pub fn to_render_mesh<F: Fn(FaceKey) -> f64>(
+    voronoi: &Diagram,
+    center: FanCenter,
+    inset: F,
+) -> RenderMesh {
+    let mut mesh = RenderMesh::default();
+    for (face_id, face) in voronoi.get_face_indices().into_iter().enumerate() {
+        let mut corners = get_counter_clockwise_ring(voronoi, face);
+        if corners.len() < 4 {
+            continue;
+        }
+        // The ring repeats its first corner
+        corners.pop();
+        let mut fan_center = match center {
+            FanCenter::Site => voronoi.get_face_point(face),
+            FanCenter::Centroid => voronoi.face_centroid(face),
+        };
+        let distance = inset(face);
+        if distance > 0.0 {
+            let inset_corners = inset_polygon(&corners, distance);
+            if inset_corners.len() < 3 {
+                continue;
+            }
+            if !is_inside(&inset_corners, fan_center) {
+                fan_center = vector2::get_polygon_centroid(&inset_corners);
+            }
+            corners = inset_corners;
+        }
+
+        let first = mesh.vertices.len() as u32;
+        for point in std::iter::once(fan_center).chain(corners.iter().cloned()) {
+            mesh.vertices.push([point.x as f32, point.y as f32]);
+            mesh.vertex_face_ids.push(face_id as u32);
+        }
+        let n = corners.len() as u32;
+        for i in 0..n {
+            mesh.indices
+                .extend_from_slice(&[first, first + 1 + i, first + 1 + (i + 1) % n]);
+            mesh.face_ids.push(face_id as u32);
+        }
+    }
+    mesh
+}
+
+/// Moves each edge of a convex polygon wound counter-clockwise inwards by a distance, returning
+/// the polygon left between them, which may be empty.
+fn inset_polygon(corners: &[cgmath::Point2<f64>], distance: f64) -> Vec<cgmath::Point2<f64>> {
+    let mut polygon = corners.to_vec();
+    for (start, normal) in get_inward_normals(corners) {
+        // Clip the polygon by the moved edge
+        let side = |point: cgmath::Point2<f64>| (point - start).dot(normal) - distance;
+        let mut clipped = vec![];
+        for i in 0..polygon.len() {
+            let (a, b) = (polygon[i], polygon[(i + 1) % polygon.len()]);
+            let (side_a, side_b) = (side(a), side(b));
+            if side_a >= 0.0 {
+                clipped.push(a);
+            }
+            if (side_a >= 0.0) != (side_b >= 0.0) {
+                clipped.push(a + (b - a) * (side_a / (side_a - side_b)));
+            }
+        }
+        polygon = clipped;
+    }
+    polygon
+}
+
+/// Returns if a point is inside of a convex polygon wound counter-clockwise, or on its border.
+fn is_inside(corners: &[cgmath::Point2<f64>], point: cgmath::Point2<f64>) -> bool {
+    get_inward_normals(corners)
+        .into_iter()
+        .all(|(start, normal)| (point - start).dot(normal) >= 0.0)
+}
+
+/// Returns the start of each edge of a polygon wound counter-clockwise along with the unit normal
+/// pointing inwards, skipping edges of no length.
+fn get_inward_normals(
+    corners: &[cgmath::Point2<f64>],
+) -> Vec<(cgmath::Point2<f64>, cgmath::Vector2<f64>)> {
+    (0..corners.len())
+        .filter_map(|i| {
+            let (start, end) = (corners[i], corners[(i + 1) % corners.len()]);
+            let direction = end - start;
+            if direction.magnitude2() > 0.0 {
+                Some((start, vector2::get_orthogonal(direction).normalize()))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::random_points;
+
+    fn get_triangles(mesh: &RenderMesh) -> Vec<[cgmath::Point2<f64>; 3]> {
+        mesh.indices
+            .chunks(3)
+            .map(|triangle| {
+                let point = |i: u32| {
+                    let [x, y] = mesh.vertices[i as usize];
+                    cgmath::Point2::new(f64::from(x), f64::from(y))
+                };
+                [point(triangle[0]), point(triangle[1]), point(triangle[2])]
+            })
+            .collect()
+    }
+
+    fn get_area(triangle: &[cgmath::Point2<f64>; 3]) -> f64 {
+        vector2::get_det(triangle[1] - triangle[0], triangle[2] - triangle[0]) / 2.0
+    }
+
+    #[test]
+    fn to_render_mesh_test() {
+        let voronoi = build_voronoi(&random_points(110, 100));
+        let faces = voronoi.get_face_indices();
+        for &center in &[FanCenter::Site, FanCenter::Centroid] {
+            let mesh = to_render_mesh(&voronoi, center, |_| 0.0);
+            assert_eq!(mesh.indices.len(), 3 * mesh.face_ids.len());
+            assert_eq!(mesh.vertices.len(), mesh.vertex_face_ids.len());
+            assert!(mesh
+                .indices
+                .iter()
+                .all(|&index| (index as usize) < mesh.vertices.len()));
+
+            // The triangles of each face cover its region and wind counter-clockwise
+            let mut areas = vec![0.0; faces.len()];
+            for (triangle, &face_id) in get_triangles(&mesh).iter().zip(&mesh.face_ids) {
+                let area = get_area(triangle);
+                assert!(area >= 0.0);
+                areas[face_id as usize] += area;
+            }
+            for (&face, area) in faces.iter().zip(areas) {
+                assert!((area - voronoi.get_face_area(face)).abs() < 1e-6);
+            }
+            for triangle in mesh.indices.chunks(3) {
+                let face_ids: Vec<u32> = triangle
+                    .iter()
+                    .map(|&index| mesh.vertex_face_ids[index as usize])
+                    .collect();
+                assert!(face_ids.iter().all(|&face_id| face_id == face_ids[0]));
+            }
+
+            // Each fan starts at its center
+            let first = mesh.indices[0] as usize;
+            let face = faces[mesh.face_ids[0] as usize];
+            let expected = match center {
+                FanCenter::Site => voronoi.get_face_point(face),
+                FanCenter::Centroid => voronoi.face_centroid(face),
+            };
+            assert_eq!(mesh.vertices[first], [expected.x as f32, expected.y as f32]);
+        }
+    }
+
+    #[test]
+    fn to_render_mesh_inset_test() {
+        let voronoi = build_voronoi(&random_points(111, 100));
+        let faces = voronoi.get_face_indices();
+        let inset = 0.01;
+        let mesh = to_render_mesh(&voronoi, FanCenter::Site, |face| {
+            if face == faces[0] {
+                0.0
+            } else {
+                inset
+            }
+        });
+        let triangles = get_triangles(&mesh);
+        for (triangle, &face_id) in triangles.iter().zip(&mesh.face_ids) {
+            assert!(get_area(triangle) >= 0.0);
+            if face_id == 0 {
+                continue;
+            }
+            // The corners are at least the inset away from the edges of the region
+            let corners = get_counter_clockwise_ring(&voronoi, faces[face_id as usize]);
+            for &point in triangle {
+                for (start, normal) in get_inward_normals(&corners[..corners.len() - 1]) {
+                    assert!((point - start).dot(normal) > inset - 1e-6);
+                }
+            }
+        }
+        let covered: f64 = triangles.iter().map(get_area).sum();
+        assert!(covered < 1.0 - 0.01);
+        assert!(mesh.face_ids.contains(&0));
+
+        // An inset larger than any region leaves nothing
+        let mesh = to_render_mesh(&voronoi, FanCenter::Centroid, |_| 0.5);
+        assert_eq!(mesh, RenderMesh::default());
+    }
+
+    #[test]
+    fn inset_polygon_test() {
+        let square = [
+            cgmath::Point2::new(0.0, 0.0),
+            cgmath::Point2::new(1.0, 0.0),
+            cgmath::Point2::new(1.0, 1.0),
+            cgmath::Point2::new(0.0, 1.0),
+        ];
+        let inset = inset_polygon(&square, 0.25);
+        assert_eq!(
+            inset,
+            vec![
+                cgmath::Point2::new(0.25, 0.25),
+                cgmath::Point2::new(0.75, 0.25),
+                cgmath::Point2::new(0.75, 0.75),
+                cgmath::Point2::new(0.25, 0.75),
+            ]
+        );
+        assert_eq!(
+            vector2::get_polygon_centroid(&inset),
+            cgmath::Point2::new(0.5, 0.5)
+        );
+        assert!(is_inside(&inset, cgmath::Point2::new(0.5, 0.3)));
+        assert!(!is_inside(&inset, cgmath::Point2::new(0.1, 0.5)));
+        assert!(inset_polygon(&square, 0.6).is_empty());
+    }
+}
@@ -0,0 +1,340 @@
+use super::*;
+use rand::Rng;
+
+/// The number of candidates tried around each active point before it is retired.
+const CANDIDATES: usize = 30;
+
+/// Generates points in a rectangle that are no closer than a radius to each other using
+/// [Bridson's algorithm](https://www.cs.ubc.ca/~rbridson/docs/bridson-siggraph07-poissondisk.pdf).
+///
+/// The points are spread evenly without clumping, so they need far less relaxation than uniformly
+/// random points. Pass a seeded generator, such as `rand::rngs::StdRng::seed_from_u64`, to get
+/// the same points each time.
+/// # Arguments
+/// * `rng` - the random number generator to use.
+/// * `min` - the corner of the rectangle with the lowest coordinates.
+/// * `max` - the corner of the rectangle with the highest coordinates.
+/// * `radius` - the smallest distance between any two points.
+///
+/// # Panics
+/// If the rectangle is empty or the radius is not positive.
+pub fn poisson_disk_sampling<R: Rng + ?Sized>(
+    rng: &mut R,
+    min: cgmath::Point2<f64>,
+    max: cgmath::Point2<f64>,
+    radius: f64,
+) -> Vec<cgmath::Point2<f64>> {
+    poisson_disk_sampling_with_radius(rng, min, max, radius, |_| radius)
+}
+
+/// Generates points in a rectangle where the distance between neighbouring points varies, using a
+/// variant of Bridson's algorithm.
+///
+/// No point is closer to another than the radius at either of them, so points are denser where
+/// the radius is smaller.
+/// # Arguments
+/// * `rng` - the random number generator to use.
+/// * `min` - the corner of the rectangle with the lowest coordinates.
+/// * `max` - the corner of the rectangle with the highest coordinates.
+/// * `max_radius` - the largest radius anywhere in the rectangle, larger radii are reduced to this.
+/// * `radius` - the smallest distance to the other points from a point.
+///
+/// # Panics
+/// If the rectangle is empty or a radius is not positive.
+pub fn poisson_disk_sampling_with_radius<R, F>(
+    rng: &mut R,
+    min: cgmath::Point2<f64>,
+    max: cgmath::Point2<f64>,
+    max_radius: f64,
+    radius: F,
+) -> Vec<cgmath::Point2<f64>>
+where
+    R: Rng + ?Sized,
+    F: Fn(cgmath::Point2<f64>) -> f64,
+{
+    assert!(
+        min.x < max.x && min.y < max.y,
+        "The rectangle must not be empty"
+    );
+    assert!(max_radius > 0.0, "The radius must be positive");
+    let get_radius = |point| {
+        let radius = radius(point);
+        assert!(radius > 0.0, "The radius must be positive");
+        radius.min(max_radius)
+    };
+
+    // Any point closer than the largest radius is in the same or a neighbouring cell
+    let columns = ((max.x - min.x) / max_radius).ceil() as usize;
+    let rows = ((max.y - min.y) / max_radius).ceil() as usize;
+    let mut grid: Vec<Vec<usize>> = vec![vec![]; columns * rows];
+    let get_cell = |point: cgmath::Point2<f64>| {
+        let column = (((point.x - min.x) / max_radius) as usize).min(columns - 1);
+        let row = (((point.y - min.y) / max_radius) as usize).min(rows - 1);
+        (column, row)
+    };
+
+    let first = cgmath::Point2::new(rng.gen_range(min.x, max.x), rng.gen_range(min.y, max.y));
+    let (column, row) = get_cell(first);
+    grid[row * columns + column].push(0);
+    let mut points = vec![first];
+    let mut radii = vec![get_radius(first)];
+    let mut active = vec![0];
+
+    while !active.is_empty() {
+        let i = rng.gen_range(0, active.len());
+        let point = points[active[i]];
+        let point_radius = radii[active[i]];
+
+        let mut found = false;
+        for _ in 0..CANDIDATES {
+            // A candidate in the annulus between one and two radii around the point
+            let angle = rng.gen_range(0.0, 2.0 * std::f64::consts::PI);
+            let distance = point_radius * rng.gen_range(1.0, 2.0);
+            let candidate = cgmath::Point2::new(
+                point.x + distance * angle.cos(),
+                point.y + distance * angle.sin(),
+            );
+            if candidate.x < min.x
+                || candidate.x >= max.x
+                || candidate.y < min.y
+                || candidate.y >= max.y
+            {
+                continue;
+            }
+
+            let candidate_radius = get_radius(candidate);
+            let (column, row) = get_cell(candidate);
+            let is_clear = (row.saturating_sub(1)..(row + 2).min(rows)).all(|row| {
+                (column.saturating_sub(1)..(column + 2).min(columns)).all(|column| {
+                    grid[row * columns + column].iter().all(|&other| {
+                        points[other].distance(candidate) >= radii[other].max(candidate_radius)
+                    })
+                })
+            });
+            if is_clear {
+                grid[row * columns + column].push(points.len());
+                active.push(points.len());
+                points.push(candidate);
+                radii.push(candidate_radius);
+                found = true;
+                break;
+            }
+        }
+        if !found {
+            active.swap_remove(i);
+        }
+    }
+    points
+}
+
+/// Generates a point in each cell of a grid over a rectangle, moved randomly within its cell.
+/// # Arguments
+/// * `rng` - the random number generator to use.
+/// * `min` - the corner of the rectangle with the lowest coordinates.
+/// * `max` - the corner of the rectangle with the highest coordinates.
+/// * `columns` - the number of cells across the rectangle.
+/// * `rows` - the number of cells down the rectangle.
+/// * `jitter` - how far the points are moved, from 0 for the centers of the cells to 1 for
+///   anywhere within them.
+///
+/// # Panics
+/// If the jitter is not in the range [0, 1].
+pub fn jittered_grid<R: Rng + ?Sized>(
+    rng: &mut R,
+    min: cgmath::Point2<f64>,
+    max: cgmath::Point2<f64>,
+    columns: usize,
+    rows: usize,
+    jitter: f64,
+) -> Vec<cgmath::Point2<f64>> {
+    assert!(
+        (0.0..=1.0).contains(&jitter),
+        "The jitter must be in the range [0, 1]"
+    );
+    let width = (max.x - min.x) / columns as f64;
+    let height = (max.y - min.y) / rows as f64;
+    let mut points = Vec::with_capacity(columns * rows);
+    for row in 0..rows {
+        for column in 0..columns {
+            let offset_x = jitter * rng.gen_range(-0.5, 0.5);
+            let offset_y = jitter * rng.gen_range(-0.5, 0.5);
+            points.push(cgmath::Point2::new(
+                min.x + (column as f64 + 0.5 + offset_x) * width,
+                min.y + (row as f64 + 0.5 + offset_y) * height,
+            ));
+        }
+    }
+    points
+}
+
+/// Generates the points of a hexagonal lattice over a rectangle, each moved randomly, so that
+/// their regions are close to regular hexagons.
+/// # Arguments
+/// * `rng` - the random number generator to use.
+/// * `min` - the corner of the rectangle with the lowest coordinates.
+/// * `max` - the corner of the rectangle with the highest coordinates.
+/// * `spacing` - the distance between neighbouring points of the lattice.
+/// * `jitter` - how far the points are moved, as a fraction of the spacing. Points that would be
+///   moved out of the rectangle are kept at its edge.
+///
+/// # Panics
+/// If the spacing is not positive or the jitter is negative.
+pub fn hexagonal_lattice<R: Rng + ?Sized>(
+    rng: &mut R,
+    min: cgmath::Point2<f64>,
+    max: cgmath::Point2<f64>,
+    spacing: f64,
+    jitter: f64,
+) -> Vec<cgmath::Point2<f64>> {
+    assert!(spacing > 0.0, "The spacing must be positive");
+    assert!(jitter >= 0.0, "The jitter must not be negative");
+    let row_height = spacing * 3.0f64.sqrt() * 0.5;
+    let mut points = vec![];
+    let mut row = 0;
+    while min.y + (row as f64 + 0.5) * row_height < max.y {
+        // Every other row is shifted by half of the spacing
+        let shift = if row % 2 == 0 { 0.25 } else { 0.75 };
+        let mut column = 0;
+        while min.x + (column as f64 + shift) * spacing < max.x {
+            let mut point = cgmath::Point2::new(
+                min.x + (column as f64 + shift) * spacing,
+                min.y + (row as f64 + 0.5) * row_height,
+            );
+            if jitter > 0.0 {
+                let angle = rng.gen_range(0.0, 2.0 * std::f64::consts::PI);
+                let distance = jitter * spacing * rng.gen::<f64>().sqrt();
+                point.x = (point.x + distance * angle.cos()).max(min.x).min(max.x);
+                point.y = (point.y + distance * angle.sin()).max(min.y).min(max.y);
+            }
+            points.push(point);
+            column += 1;
+        }
+        row += 1;
+    }
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::assert_consistent;
+    use rand::SeedableRng;
+
+    fn assert_in_rectangle(
+        points: &[cgmath::Point2<f64>],
+        min: cgmath::Point2<f64>,
+        max: cgmath::Point2<f64>,
+    ) {
+        for point in points {
+            assert!(point.x >= min.x && point.x <= max.x);
+            assert!(point.y >= min.y && point.y <= max.y);
+        }
+    }
+
+    #[test]
+    fn poisson_disk_sampling_test() {
+        let min = cgmath::Point2::new(0.2, 0.1);
+        let max = cgmath::Point2::new(0.9, 0.5);
+        let radius = 0.02;
+        let points =
+            poisson_disk_sampling(&mut rand::rngs::StdRng::seed_from_u64(0), min, max, radius);
+        assert_in_rectangle(&points, min, max);
+        for (i, a) in points.iter().enumerate() {
+            for b in &points[i + 1..] {
+                assert!(a.distance(*b) >= radius);
+            }
+        }
+        // Maximal sampling covers the rectangle, so there are close to as many points as disks
+        // of the radius packed at about 70% density
+        let expected = 0.7 * 0.28 / (std::f64::consts::PI * (radius * 0.5).powi(2));
+        assert!(points.len() as f64 > expected * 0.5);
+
+        let same =
+            poisson_disk_sampling(&mut rand::rngs::StdRng::seed_from_u64(0), min, max, radius);
+        assert_eq!(points, same);
+
+        assert_consistent(&build_voronoi(&poisson_disk_sampling(
+            &mut rand::rngs::StdRng::seed_from_u64(1),
+            cgmath::Point2::new(0.0, 0.0),
+            cgmath::Point2::new(1.0, 1.0),
+            0.05,
+        )));
+    }
+
+    #[test]
+    fn poisson_disk_sampling_with_radius_test() {
+        let min = cgmath::Point2::new(0.0, 0.0);
+        let max = cgmath::Point2::new(1.0, 1.0);
+        let radius = |point: cgmath::Point2<f64>| 0.01 + 0.04 * point.x;
+        let points = poisson_disk_sampling_with_radius(
+            &mut rand::rngs::StdRng::seed_from_u64(2),
+            min,
+            max,
+            0.05,
+            radius,
+        );
+        assert_in_rectangle(&points, min, max);
+        for (i, a) in points.iter().enumerate() {
+            for b in &points[i + 1..] {
+                assert!(a.distance(*b) >= radius(*a).max(radius(*b)));
+            }
+        }
+        // The points are denser where the radius is smaller
+        let left = points.iter().filter(|point| point.x < 0.5).count();
+        assert!(left > 2 * (points.len() - left));
+    }
+
+    #[test]
+    fn jittered_grid_test() {
+        let min = cgmath::Point2::new(0.0, 0.0);
+        let max = cgmath::Point2::new(1.0, 0.5);
+        let mut rng = rand::rngs::StdRng::seed_from_u64(3);
+        let points = jittered_grid(&mut rng, min, max, 4, 2, 0.0);
+        assert_eq!(points.len(), 8);
+        assert_eq!(points[0], cgmath::Point2::new(0.125, 0.125));
+        assert_eq!(points[5], cgmath::Point2::new(0.375, 0.375));
+
+        let points = jittered_grid(&mut rng, min, max, 10, 5, 1.0);
+        assert_eq!(points.len(), 50);
+        assert_in_rectangle(&points, min, max);
+        // Each point stays in its own cell
+        for (i, point) in points.iter().enumerate() {
+            assert_eq!((point.x * 10.0) as usize, i % 10);
+            assert_eq!((point.y * 10.0) as usize, i / 10);
+        }
+    }
+
+    #[test]
+    fn hexagonal_lattice_test() {
+        let min = cgmath::Point2::new(0.0, 0.0);
+        let max = cgmath::Point2::new(1.0, 1.0);
+        let mut rng = rand::rngs::StdRng::seed_from_u64(4);
+        let spacing = 0.1;
+        let points = hexagonal_lattice(&mut rng, min, max, spacing, 0.0);
+        assert_in_rectangle(&points, min, max);
+        for (i, a) in points.iter().enumerate() {
+            for b in &points[i + 1..] {
+                assert!(a.distance(*b) > spacing - 1e-9);
+            }
+        }
+
+        // The regions away from the border are regular hexagons
+        let voronoi = build_voronoi(&points);
+        assert_consistent(&voronoi);
+        let hexagon_area = spacing * spacing * 3.0f64.sqrt() * 0.5;
+        for face in voronoi.get_face_indices() {
+            let point = voronoi.get_face_point(face);
+            if point.x > 0.2 && point.x < 0.8 && point.y > 0.2 && point.y < 0.8 {
+                assert_eq!(voronoi.outer_edge_iter(face).count(), 6);
+                assert!((voronoi.get_face_area(face) - hexagon_area).abs() < 1e-9);
+            }
+        }
+
+        let jittered = hexagonal_lattice(&mut rng, min, max, spacing, 0.2);
+        assert_eq!(jittered.len(), points.len());
+        assert_in_rectangle(&jittered, min, max);
+        for (point, jittered_point) in points.iter().zip(&jittered) {
+            assert!(point.distance(*jittered_point) <= 0.2 * spacing + 1e-12);
+        }
+    }
+}
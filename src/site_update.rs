@@ -0,0 +1,206 @@
+use super::*;
+use cell::{compute_cell, find_nearest_face, get_neighbours, replace_cells, Cell};
+use cgmath::MetricSpace;
+use std::collections::HashSet;
+
+impl Diagram {
+    /// Removes a site from the diagram, handing its region over to the neighbouring faces.
+    ///
+    /// Only the faces that bordered the removed face are recomputed, so this is much cheaper than
+    /// rebuilding the diagram. Returns the faces whose regions have changed.
+    /// # Arguments
+    /// * `face` - the index of the face to remove.
+    ///
+    /// # Panics
+    /// If the face index is invalid.
+    pub fn remove_site(&mut self, face: FaceKey) -> Vec<FaceKey> {
+        let changed = self.detach_face(face);
+        self.remove_face(face);
+        match changed {
+            Some(changed) => changed,
+            None => {
+                voronoi_builder::compute_voronoi(self);
+                self.get_face_indices()
+            }
+        }
+    }
+
+    /// Moves a site to a new point, updating the regions of the faces around its old and new
+    /// positions.
+    ///
+    /// The face keeps its index. Returns the faces whose regions have changed, including the
+    /// moved face.
+    /// # Arguments
+    /// * `face` - the index of the face to move.
+    /// * `point` - the new point of the face, this should be in the range [0,1] X [0,1].
+    ///
+    /// # Panics
+    /// If the face index is invalid or another site is already located at the point.
+    pub fn move_site(&mut self, face: FaceKey, point: cgmath::Point2<f64>) -> Vec<FaceKey> {
+        let old_neighbours = self.detach_face(face);
+        self.set_face_point(face, point);
+        let new_neighbours = old_neighbours.as_ref().and_then(|old_neighbours| {
+            let start = old_neighbours
+                .first()
+                .cloned()
+                .or_else(|| self.get_face_indices().into_iter().find(|&f| f != face));
+            self.attach_face(face, start)
+        });
+        match (old_neighbours, new_neighbours) {
+            (Some(old_neighbours), Some(new_neighbours)) => {
+                let mut changed = vec![face];
+                for neighbour in old_neighbours.into_iter().chain(new_neighbours) {
+                    if !changed.contains(&neighbour) {
+                        changed.push(neighbour);
+                    }
+                }
+                changed
+            }
+            _ => {
+                voronoi_builder::compute_voronoi(self);
+                self.get_face_indices()
+            }
+        }
+    }
+
+    /// Removes the region of a face, handing it over to the neighbouring faces.
+    ///
+    /// Returns the neighbouring faces, or `None` if the neighbouring regions could not be updated
+    /// locally.
+    fn detach_face(&mut self, face: FaceKey) -> Option<Vec<FaceKey>> {
+        let neighbours = get_neighbours(self, face);
+        let cells: Vec<(FaceKey, Cell)> = neighbours
+            .iter()
+            .map(|&neighbour| {
+                // Removing a site only creates edges between the sites that bordered it
+                let candidates = get_neighbours(self, neighbour)
+                    .into_iter()
+                    .chain(neighbours.iter().cloned())
+                    .filter(|&candidate| candidate != face);
+                let cell =
+                    compute_cell(self, neighbour, self.get_face_point(neighbour), candidates);
+                (neighbour, cell)
+            })
+            .collect();
+        if replace_cells(self, &cells, &[face]) {
+            Some(neighbours)
+        } else {
+            None
+        }
+    }
+
+    /// Gives a face without a region the part of the diagram closest to its point.
+    ///
+    /// Returns the faces that now border the face, or `None` if the regions could not be updated
+    /// locally.
+    /// # Arguments
+    /// * `face` - the face to add a region for.
+    /// * `start` - any other face with a region, the search for the new neighbours starts here.
+    fn attach_face(&mut self, face: FaceKey, start: Option<FaceKey>) -> Option<Vec<FaceKey>> {
+        let point = self.get_face_point(face);
+        let start = match start {
+            Some(start) => start,
+            None => {
+                // This is the only face so it covers the whole diagram
+                let cell = compute_cell(self, face, point, vec![]);
+                return if replace_cells(self, &[(face, cell)], &[]) {
+                    Some(vec![])
+                } else {
+                    None
+                };
+            }
+        };
+
+        let nearest = find_nearest_face(self, start, point);
+        if self.get_face_point(nearest) == point {
+            panic!("Two sites located at the same point");
+        }
+
+        // Grow the set of candidates until no site outside of it is closer to a vertex of the
+        // cell than the new site, at which point no other site can cut the cell.
+        let mut candidates: HashSet<FaceKey> = get_neighbours(self, nearest).into_iter().collect();
+        candidates.insert(nearest);
+        let cell = loop {
+            let cell = compute_cell(self, face, point, candidates.iter().cloned());
+            let mut missing = vec![];
+            for &(vertex, _) in cell.vertices() {
+                let closest = find_nearest_face(self, nearest, vertex);
+                if !candidates.contains(&closest)
+                    && vertex.distance2(self.get_face_point(closest)) < vertex.distance2(point)
+                {
+                    missing.push(closest);
+                }
+            }
+            if missing.is_empty() {
+                break cell;
+            }
+            candidates.extend(missing);
+        };
+
+        let neighbours: Vec<FaceKey> = cell.neighbours().collect();
+        let mut cells: Vec<(FaceKey, Cell)> = neighbours
+            .iter()
+            .map(|&neighbour| {
+                // Adding a site only cuts the regions of the sites it borders
+                let candidates = get_neighbours(self, neighbour)
+                    .into_iter()
+                    .chain(Some(face));
+                let cell =
+                    compute_cell(self, neighbour, self.get_face_point(neighbour), candidates);
+                (neighbour, cell)
+            })
+            .collect();
+        cells.push((face, cell));
+        if replace_cells(self, &cells, &[]) {
+            Some(neighbours)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{assert_consistent, assert_matches_rebuild, random_points};
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn remove_site_test() {
+        let mut voronoi = build_voronoi(&random_points(1, 200));
+        for face in voronoi.get_face_indices().into_iter().step_by(7) {
+            let neighbours = get_neighbours(&voronoi, face);
+            let changed = voronoi.remove_site(face);
+            assert_eq!(changed, neighbours);
+            assert_consistent(&voronoi);
+        }
+        assert_matches_rebuild(&voronoi);
+    }
+
+    #[test]
+    fn move_site_test() {
+        let mut voronoi = build_voronoi(&random_points(2, 200));
+        let mut rng = rand::rngs::StdRng::seed_from_u64(3);
+        for face in voronoi.get_face_indices().into_iter().step_by(5) {
+            let point = voronoi.get_face_point(face);
+            let new_point = cgmath::Point2::new(
+                (point.x + rng.gen_range(-0.05, 0.05)).clamp(0.0, 1.0),
+                (point.y + rng.gen_range(-0.05, 0.05)).clamp(0.0, 1.0),
+            );
+            let changed = voronoi.move_site(face, new_point);
+            assert_eq!(changed[0], face);
+            assert_eq!(voronoi.get_face_point(face), new_point);
+            assert_consistent(&voronoi);
+        }
+        assert_matches_rebuild(&voronoi);
+    }
+
+    #[test]
+    fn move_site_far_test() {
+        let mut voronoi = build_voronoi(&random_points(4, 50));
+        let face = voronoi.get_face_indices()[0];
+        voronoi.move_site(face, cgmath::Point2::new(0.99, 0.01));
+        assert_consistent(&voronoi);
+        assert_matches_rebuild(&voronoi);
+    }
+}
@@ -0,0 +1,297 @@
+use super::*;
+use cell::get_neighbours;
+use std::io::{self, Write};
+
+/// The appearance of a diagram written as SVG. Colors are any SVG paint, such as `"black"` or
+/// `"#ff8800"`, and sizes are in pixels of the image.
+#[derive(Clone, Debug)]
+pub struct SvgStyle {
+    /// The width of the image, the height follows from the bounds of the diagram.
+    pub width: f64,
+    /// The space around the diagram.
+    pub margin: f64,
+    /// The color of the edges, or `None` to leave them out.
+    pub edge_color: Option<String>,
+    /// The width of the edges.
+    pub edge_width: f64,
+    /// The color of the vertices, or `None` to leave them out.
+    pub vertex_color: Option<String>,
+    /// The radius of the vertices.
+    pub vertex_radius: f64,
+    /// The color of the sites, or `None` to leave them out.
+    pub site_color: Option<String>,
+    /// The radius of the sites.
+    pub site_radius: f64,
+    /// The color of the edges of the Delaunay graph joining neighbouring sites, or `None` to
+    /// leave them out.
+    pub delaunay_color: Option<String>,
+    /// The width of the edges of the Delaunay graph.
+    pub delaunay_width: f64,
+}
+
+impl Default for SvgStyle {
+    fn default() -> Self {
+        SvgStyle {
+            width: 800.0,
+            margin: 10.0,
+            edge_color: Some("black".to_string()),
+            edge_width: 1.0,
+            vertex_color: None,
+            vertex_radius: 2.0,
+            site_color: Some("red".to_string()),
+            site_radius: 2.0,
+            delaunay_color: None,
+            delaunay_width: 0.5,
+        }
+    }
+}
+
+/// Writes a diagram as an SVG image.
+///
+/// The regions are drawn first, filled with the color given for each face, then the Delaunay
+/// graph, the edges, the vertices and the sites on top. The view box covers the vertices and
+/// sites of the diagram and keeps its coordinates, so y increases down the image as it does in the
+/// diagram.
+/// # Arguments
+/// * `voronoi` - the diagram to draw.
+/// * `style` - how to draw the diagram.
+/// * `fill` - the color to fill the region of each face with, or `None` to leave it empty.
+/// * `writer` - where to write the image.
+pub fn write_svg<W, F>(
+    voronoi: &Diagram,
+    style: &SvgStyle,
+    fill: F,
+    mut writer: W,
+) -> io::Result<()>
+where
+    W: Write,
+    F: Fn(FaceKey) -> Option<String>,
+{
+    let faces = voronoi.get_face_indices();
+    let points = voronoi
+        .get_vertex_points()
+        .into_iter()
+        .chain(faces.iter().map(|&face| voronoi.get_face_point(face)));
+    let (mut min, mut max) = points.fold(
+        (
+            cgmath::Point2::new(f64::INFINITY, f64::INFINITY),
+            cgmath::Point2::new(f64::NEG_INFINITY, f64::NEG_INFINITY),
+        ),
+        |(min, max), point| {
+            (
+                cgmath::Point2::new(min.x.min(point.x), min.y.min(point.y)),
+                cgmath::Point2::new(max.x.max(point.x), max.y.max(point.y)),
+            )
+        },
+    );
+    if !(min.x < max.x && min.y < max.y) {
+        // Too few points to give an area, such as a single site
+        min = cgmath::Point2::new(0.0, 0.0);
+        max = cgmath::Point2::new(1.0, 1.0);
+    }
+
+    // Sizes in pixels are divided by the scale to get sizes in the coordinates of the diagram
+    let scale = (style.width - 2.0 * style.margin).max(1.0) / (max.x - min.x);
+    let margin = style.margin / scale;
+    let height = (max.y - min.y) * scale + 2.0 * style.margin;
+    writeln!(
+        writer,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="{} {} {} {}">"#,
+        style.width,
+        height,
+        min.x - margin,
+        min.y - margin,
+        max.x - min.x + 2.0 * margin,
+        max.y - min.y + 2.0 * margin
+    )?;
+
+    let regions: Vec<FaceKey> = faces
+        .iter()
+        .cloned()
+        .filter(|&face| voronoi.get_face_outer_component(face).is_some())
+        .collect();
+    writeln!(writer, r#"<g stroke="none">"#)?;
+    for &face in &regions {
+        if let Some(color) = fill(face) {
+            let points: Vec<String> = voronoi
+                .outer_edge_iter(face)
+                .map(|edge| {
+                    let point = voronoi.get_half_edge_origin_point(edge);
+                    format!("{},{}", point.x, point.y)
+                })
+                .collect();
+            writeln!(
+                writer,
+                r#"<polygon points="{}" fill="{}"/>"#,
+                points.join(" "),
+                color
+            )?;
+        }
+    }
+    writeln!(writer, "</g>")?;
+
+    if let Some(color) = &style.delaunay_color {
+        let mut lines = vec![];
+        for &face in &regions {
+            for neighbour in get_neighbours(voronoi, face) {
+                // Each pair of neighbours is joined once
+                if face < neighbour {
+                    lines.push((
+                        voronoi.get_face_point(face),
+                        voronoi.get_face_point(neighbour),
+                    ));
+                }
+            }
+        }
+        write_lines(&mut writer, &lines, color, style.delaunay_width / scale)?;
+    }
+
+    if let Some(color) = &style.edge_color {
+        let mut lines = vec![];
+        for &face in &regions {
+            for edge in voronoi.outer_edge_iter(face) {
+                // Edges between two faces are drawn once, from the half edge with the smaller key
+                if voronoi
+                    .get_half_edge_twin(edge)
+                    .filter(|&twin| twin < edge)
+                    .is_none()
+                {
+                    let destination = voronoi.get_half_edge_destination(edge).unwrap();
+                    lines.push((
+                        voronoi.get_half_edge_origin_point(edge),
+                        voronoi.get_vertex_point(destination),
+                    ));
+                }
+            }
+        }
+        write_lines(&mut writer, &lines, color, style.edge_width / scale)?;
+    }
+
+    if let Some(color) = &style.vertex_color {
+        write_circles(
+            &mut writer,
+            &voronoi.get_vertex_points(),
+            color,
+            style.vertex_radius / scale,
+        )?;
+    }
+
+    if let Some(color) = &style.site_color {
+        let sites: Vec<cgmath::Point2<f64>> = faces
+            .iter()
+            .map(|&face| voronoi.get_face_point(face))
+            .collect();
+        write_circles(&mut writer, &sites, color, style.site_radius / scale)?;
+    }
+
+    writeln!(writer, "</svg>")
+}
+
+/// Returns a diagram as an SVG image, see `write_svg`.
+/// # Arguments
+/// * `voronoi` - the diagram to draw.
+/// * `style` - how to draw the diagram.
+/// * `fill` - the color to fill the region of each face with, or `None` to leave it empty.
+pub fn to_svg<F: Fn(FaceKey) -> Option<String>>(
+    voronoi: &Diagram,
+    style: &SvgStyle,
+    fill: F,
+) -> String {
+    let mut svg = vec![];
+    write_svg(voronoi, style, fill, &mut svg).unwrap();
+    String::from_utf8(svg).unwrap()
+}
+
+fn write_lines<W: Write>(
+    writer: &mut W,
+    lines: &[(cgmath::Point2<f64>, cgmath::Point2<f64>)],
+    color: &str,
+    width: f64,
+) -> io::Result<()> {
+    writeln!(
+        writer,
+        r#"<g stroke="{}" stroke-width="{}" stroke-linecap="round">"#,
+        color, width
+    )?;
+    for (start, end) in lines {
+        writeln!(
+            writer,
+            r#"<line x1="{}" y1="{}" x2="{}" y2="{}"/>"#,
+            start.x, start.y, end.x, end.y
+        )?;
+    }
+    writeln!(writer, "</g>")
+}
+
+fn write_circles<W: Write>(
+    writer: &mut W,
+    centers: &[cgmath::Point2<f64>],
+    color: &str,
+    radius: f64,
+) -> io::Result<()> {
+    writeln!(writer, r#"<g fill="{}">"#, color)?;
+    for center in centers {
+        writeln!(
+            writer,
+            r#"<circle cx="{}" cy="{}" r="{}"/>"#,
+            center.x, center.y, radius
+        )?;
+    }
+    writeln!(writer, "</g>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::random_points;
+
+    #[test]
+    fn to_svg_test() {
+        let voronoi = build_voronoi(&random_points(60, 20));
+        let faces = voronoi.get_face_indices();
+        let style = SvgStyle {
+            width: 820.0,
+            vertex_color: Some("blue".to_string()),
+            delaunay_color: Some("gray".to_string()),
+            ..SvgStyle::default()
+        };
+        let svg = to_svg(&voronoi, &style, |face| {
+            if face == faces[0] {
+                Some("#ff8800".to_string())
+            } else {
+                None
+            }
+        });
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        assert!(svg.contains(r#"width="820" height="820" viewBox="-0.0125 -0.0125 "#));
+        assert_eq!(svg.matches("<polygon ").count(), 1);
+        assert_eq!(svg.matches(r##"fill="#ff8800""##).count(), 1);
+        assert_eq!(
+            svg.matches("<circle ").count(),
+            faces.len() + voronoi.get_vertex_points().len()
+        );
+
+        // Each edge is drawn once, along with each edge of the Delaunay graph
+        let half_edges = voronoi.get_edge_endpoints();
+        let border_edges = faces
+            .iter()
+            .flat_map(|&face| voronoi.outer_edge_iter(face))
+            .filter(|&edge| voronoi.get_half_edge_twin(edge).is_none())
+            .count();
+        let inner_edges = (half_edges.len() - border_edges) / 2;
+        assert_eq!(
+            svg.matches("<line ").count(),
+            inner_edges + border_edges + inner_edges
+        );
+
+        let bare = SvgStyle {
+            edge_color: None,
+            site_color: None,
+            ..SvgStyle::default()
+        };
+        let svg = to_svg(&voronoi, &bare, |_| None);
+        assert_eq!(svg.matches("<line ").count(), 0);
+        assert_eq!(svg.matches("<circle ").count(), 0);
+    }
+}
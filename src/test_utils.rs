@@ -0,0 +1,47 @@
+use super::*;
+use rand::{Rng, SeedableRng};
+
+pub fn random_points(seed: u64, count: usize) -> Vec<cgmath::Point2<f64>> {
+    let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+    (0..count)
+        .map(|_| cgmath::Point2::new(rng.gen(), rng.gen()))
+        .collect()
+}
+
+/// Checks the half edges of every face form a closed loop with consistent twins and vertices.
+pub fn assert_consistent(voronoi: &Diagram) {
+    for face in voronoi.get_face_indices() {
+        for edge in voronoi.outer_edge_iter(face) {
+            let next = voronoi.get_half_edge_next(edge).unwrap();
+            assert_eq!(voronoi.get_half_edge_prev(next), Some(edge));
+            assert_eq!(
+                voronoi.get_half_edge_destination(edge),
+                voronoi.get_half_edge_origin(next)
+            );
+            assert_eq!(voronoi.get_half_edge_incident_face(edge), Some(face));
+            if let Some(twin) = voronoi.get_half_edge_twin(edge) {
+                assert_eq!(voronoi.get_half_edge_twin(twin), Some(edge));
+                assert_eq!(
+                    voronoi.get_half_edge_origin(edge),
+                    voronoi.get_half_edge_destination(twin)
+                );
+            }
+        }
+    }
+}
+
+/// Checks every face has the same area as the face with the same point in a rebuilt diagram.
+pub fn assert_matches_rebuild(voronoi: &Diagram) {
+    let points: Vec<cgmath::Point2<f64>> = voronoi
+        .get_face_indices()
+        .into_iter()
+        .map(|face| voronoi.get_face_point(face))
+        .collect();
+    let rebuilt = build_voronoi(&points);
+    let rebuilt_faces = rebuilt.get_face_indices();
+    for (i, face) in voronoi.get_face_indices().into_iter().enumerate() {
+        assert!(
+            (voronoi.get_face_area(face) - rebuilt.get_face_area(rebuilt_faces[i])).abs() < 1e-9
+        );
+    }
+}
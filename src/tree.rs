@@ -0,0 +1,519 @@
+use std::ops::{Index, IndexMut};
+
+/// The index of a node in a `Tree`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NodeKey(usize);
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Color {
+    Red,
+    Black,
+}
+
+/// A node of the tree.
+///
+/// As well as the parent and child links used to keep the tree balanced each node stores the
+/// index of the previous and next node in the in order traversal of the tree so that neighbours
+/// can be found in constant time.
+#[derive(Debug)]
+struct Node<T> {
+    contents: T,
+    color: Color,
+    parent: Option<NodeKey>,
+    left: Option<NodeKey>,
+    right: Option<NodeKey>,
+    prev: Option<NodeKey>,
+    next: Option<NodeKey>,
+}
+
+impl<T> Node<T> {
+    fn new(contents: T) -> Self {
+        Node {
+            contents,
+            color: Color::Red,
+            parent: None,
+            left: None,
+            right: None,
+            prev: None,
+            next: None,
+        }
+    }
+}
+
+/// The storage for the nodes of a tree, slots freed by deleted nodes are reused by later insertions.
+struct Arena<T> {
+    slots: Vec<Option<Node<T>>>,
+    free: Vec<usize>,
+}
+
+impl<T> Arena<T> {
+    fn new() -> Self {
+        Arena {
+            slots: vec![],
+            free: vec![],
+        }
+    }
+
+    fn insert(&mut self, node: Node<T>) -> NodeKey {
+        match self.free.pop() {
+            Some(index) => {
+                self.slots[index] = Some(node);
+                NodeKey(index)
+            }
+            None => {
+                self.slots.push(Some(node));
+                NodeKey(self.slots.len() - 1)
+            }
+        }
+    }
+
+    fn remove(&mut self, key: NodeKey) -> Node<T> {
+        let node = self.slots[key.0].take().unwrap();
+        self.free.push(key.0);
+        node
+    }
+
+    fn clear(&mut self) {
+        self.slots.clear();
+        self.free.clear();
+    }
+}
+
+impl<T> Index<NodeKey> for Arena<T> {
+    type Output = Node<T>;
+
+    fn index(&self, key: NodeKey) -> &Node<T> {
+        self.slots[key.0].as_ref().unwrap()
+    }
+}
+
+impl<T> IndexMut<NodeKey> for Arena<T> {
+    fn index_mut(&mut self, key: NodeKey) -> &mut Node<T> {
+        self.slots[key.0].as_mut().unwrap()
+    }
+}
+
+/// A red black tree whose nodes are stored in an arena.
+///
+/// The tree is ordered purely by position, new nodes are inserted immediately before or after an
+/// existing node rather than by comparing their contents.
+pub struct Tree<T> {
+    pub root: Option<NodeKey>,
+    nodes: Arena<T>,
+}
+
+impl<T> Default for Tree<T> {
+    fn default() -> Self {
+        Tree::new()
+    }
+}
+
+impl<T> Tree<T> {
+    /// Constructs a new empty tree.
+    pub fn new() -> Self {
+        Tree {
+            root: None,
+            nodes: Arena::new(),
+        }
+    }
+
+    /// Removes every node from the tree, keeping the allocated storage.
+    pub fn clear(&mut self) {
+        self.root = None;
+        self.nodes.clear();
+    }
+
+    pub fn has_root(&self) -> bool {
+        self.root.is_some()
+    }
+
+    /// Creates the root node of an empty tree.
+    ///
+    /// # Panics
+    /// If the tree already has a root.
+    pub fn create_root(&mut self, contents: T) -> NodeKey {
+        assert!(self.root.is_none(), "The tree already has a root");
+        let mut node = Node::new(contents);
+        node.color = Color::Black;
+        let root = self.nodes.insert(node);
+        self.root = Some(root);
+        root
+    }
+
+    pub fn get_contents(&self, node: NodeKey) -> &T {
+        &self.nodes[node].contents
+    }
+
+    pub fn get_mut_contents(&mut self, node: NodeKey) -> &mut T {
+        &mut self.nodes[node].contents
+    }
+
+    pub fn set_contents(&mut self, node: NodeKey, contents: T) {
+        self.nodes[node].contents = contents;
+    }
+
+    pub fn get_left(&self, node: NodeKey) -> Option<NodeKey> {
+        self.nodes[node].left
+    }
+
+    pub fn get_right(&self, node: NodeKey) -> Option<NodeKey> {
+        self.nodes[node].right
+    }
+
+    pub fn get_prev(&self, node: NodeKey) -> Option<NodeKey> {
+        self.nodes[node].prev
+    }
+
+    pub fn get_next(&self, node: NodeKey) -> Option<NodeKey> {
+        self.nodes[node].next
+    }
+
+    /// Returns the first node in the in order traversal of the tree.
+    pub fn get_leftmost_node(&self) -> Option<NodeKey> {
+        let mut node = self.root?;
+        while let Some(left) = self.nodes[node].left {
+            node = left;
+        }
+        Some(node)
+    }
+
+    /// Inserts a new node immediately before an existing node.
+    pub fn insert_before(&mut self, node: NodeKey, contents: T) -> NodeKey {
+        let new_node = self.nodes.insert(Node::new(contents));
+        let prev = self.nodes[node].prev;
+        match self.nodes[node].left {
+            None => {
+                self.nodes[node].left = Some(new_node);
+                self.nodes[new_node].parent = Some(node);
+            }
+            Some(_) => {
+                // The previous node is the rightmost node of the left subtree so it has no right
+                // child.
+                let prev = prev.unwrap();
+                self.nodes[prev].right = Some(new_node);
+                self.nodes[new_node].parent = Some(prev);
+            }
+        }
+        self.nodes[new_node].prev = prev;
+        self.nodes[new_node].next = Some(node);
+        if let Some(prev) = prev {
+            self.nodes[prev].next = Some(new_node);
+        }
+        self.nodes[node].prev = Some(new_node);
+        self.insert_fixup(new_node);
+        new_node
+    }
+
+    /// Inserts a new node immediately after an existing node.
+    pub fn insert_after(&mut self, node: NodeKey, contents: T) -> NodeKey {
+        let new_node = self.nodes.insert(Node::new(contents));
+        let next = self.nodes[node].next;
+        match self.nodes[node].right {
+            None => {
+                self.nodes[node].right = Some(new_node);
+                self.nodes[new_node].parent = Some(node);
+            }
+            Some(_) => {
+                // The next node is the leftmost node of the right subtree so it has no left child.
+                let next = next.unwrap();
+                self.nodes[next].left = Some(new_node);
+                self.nodes[new_node].parent = Some(next);
+            }
+        }
+        self.nodes[new_node].prev = Some(node);
+        self.nodes[new_node].next = next;
+        if let Some(next) = next {
+            self.nodes[next].prev = Some(new_node);
+        }
+        self.nodes[node].next = Some(new_node);
+        self.insert_fixup(new_node);
+        new_node
+    }
+
+    /// Removes a node from the tree, returning its contents.
+    pub fn delete_node(&mut self, node: NodeKey) -> T {
+        let prev = self.nodes[node].prev;
+        let next = self.nodes[node].next;
+        if let Some(prev) = prev {
+            self.nodes[prev].next = next;
+        }
+        if let Some(next) = next {
+            self.nodes[next].prev = prev;
+        }
+
+        let mut removed_color = self.nodes[node].color;
+        let child;
+        let child_parent;
+        if self.nodes[node].left.is_none() {
+            child = self.nodes[node].right;
+            child_parent = self.nodes[node].parent;
+            self.transplant(node, child);
+        } else if self.nodes[node].right.is_none() {
+            child = self.nodes[node].left;
+            child_parent = self.nodes[node].parent;
+            self.transplant(node, child);
+        } else {
+            // The successor is the leftmost node of the right subtree and takes the place of the
+            // deleted node.
+            let successor = next.unwrap();
+            removed_color = self.nodes[successor].color;
+            child = self.nodes[successor].right;
+            if self.nodes[successor].parent == Some(node) {
+                child_parent = Some(successor);
+            } else {
+                child_parent = self.nodes[successor].parent;
+                self.transplant(successor, child);
+                let right = self.nodes[node].right;
+                self.nodes[successor].right = right;
+                self.nodes[right.unwrap()].parent = Some(successor);
+            }
+            self.transplant(node, Some(successor));
+            let left = self.nodes[node].left;
+            self.nodes[successor].left = left;
+            self.nodes[left.unwrap()].parent = Some(successor);
+            self.nodes[successor].color = self.nodes[node].color;
+        }
+
+        if removed_color == Color::Black {
+            self.delete_fixup(child, child_parent);
+        }
+
+        self.nodes.remove(node).contents
+    }
+
+    fn color(&self, node: Option<NodeKey>) -> Color {
+        node.map_or(Color::Black, |node| self.nodes[node].color)
+    }
+
+    fn set_color(&mut self, node: Option<NodeKey>, color: Color) {
+        if let Some(node) = node {
+            self.nodes[node].color = color;
+        }
+    }
+
+    fn transplant(&mut self, old: NodeKey, new: Option<NodeKey>) {
+        let parent = self.nodes[old].parent;
+        match parent {
+            None => self.root = new,
+            Some(parent) => {
+                if self.nodes[parent].left == Some(old) {
+                    self.nodes[parent].left = new;
+                } else {
+                    self.nodes[parent].right = new;
+                }
+            }
+        }
+        if let Some(new) = new {
+            self.nodes[new].parent = parent;
+        }
+    }
+
+    fn rotate_left(&mut self, node: NodeKey) {
+        let pivot = self.nodes[node].right.unwrap();
+        let inner = self.nodes[pivot].left;
+        self.nodes[node].right = inner;
+        if let Some(inner) = inner {
+            self.nodes[inner].parent = Some(node);
+        }
+        self.transplant(node, Some(pivot));
+        self.nodes[pivot].left = Some(node);
+        self.nodes[node].parent = Some(pivot);
+    }
+
+    fn rotate_right(&mut self, node: NodeKey) {
+        let pivot = self.nodes[node].left.unwrap();
+        let inner = self.nodes[pivot].right;
+        self.nodes[node].left = inner;
+        if let Some(inner) = inner {
+            self.nodes[inner].parent = Some(node);
+        }
+        self.transplant(node, Some(pivot));
+        self.nodes[pivot].right = Some(node);
+        self.nodes[node].parent = Some(pivot);
+    }
+
+    fn insert_fixup(&mut self, mut node: NodeKey) {
+        while let Some(parent) = self.nodes[node].parent {
+            if self.nodes[parent].color == Color::Black {
+                break;
+            }
+            // A red node is never the root so the grandparent must exist
+            let grandparent = self.nodes[parent].parent.unwrap();
+            if self.nodes[grandparent].left == Some(parent) {
+                let uncle = self.nodes[grandparent].right;
+                if self.color(uncle) == Color::Red {
+                    self.set_color(Some(parent), Color::Black);
+                    self.set_color(uncle, Color::Black);
+                    self.set_color(Some(grandparent), Color::Red);
+                    node = grandparent;
+                } else {
+                    let mut parent = parent;
+                    if self.nodes[parent].right == Some(node) {
+                        node = parent;
+                        self.rotate_left(node);
+                        parent = self.nodes[node].parent.unwrap();
+                    }
+                    self.set_color(Some(parent), Color::Black);
+                    self.set_color(Some(grandparent), Color::Red);
+                    self.rotate_right(grandparent);
+                }
+            } else {
+                let uncle = self.nodes[grandparent].left;
+                if self.color(uncle) == Color::Red {
+                    self.set_color(Some(parent), Color::Black);
+                    self.set_color(uncle, Color::Black);
+                    self.set_color(Some(grandparent), Color::Red);
+                    node = grandparent;
+                } else {
+                    let mut parent = parent;
+                    if self.nodes[parent].left == Some(node) {
+                        node = parent;
+                        self.rotate_right(node);
+                        parent = self.nodes[node].parent.unwrap();
+                    }
+                    self.set_color(Some(parent), Color::Black);
+                    self.set_color(Some(grandparent), Color::Red);
+                    self.rotate_left(grandparent);
+                }
+            }
+        }
+        self.set_color(self.root, Color::Black);
+    }
+
+    fn delete_fixup(&mut self, mut node: Option<NodeKey>, mut parent: Option<NodeKey>) {
+        while node != self.root && self.color(node) == Color::Black {
+            let current_parent = parent.unwrap();
+            if self.nodes[current_parent].left == node {
+                // The removed node was black so the sibling must exist
+                let mut sibling = self.nodes[current_parent].right.unwrap();
+                if self.nodes[sibling].color == Color::Red {
+                    self.set_color(Some(sibling), Color::Black);
+                    self.set_color(Some(current_parent), Color::Red);
+                    self.rotate_left(current_parent);
+                    sibling = self.nodes[current_parent].right.unwrap();
+                }
+                if self.color(self.nodes[sibling].left) == Color::Black
+                    && self.color(self.nodes[sibling].right) == Color::Black
+                {
+                    self.set_color(Some(sibling), Color::Red);
+                    node = Some(current_parent);
+                    parent = self.nodes[current_parent].parent;
+                } else {
+                    if self.color(self.nodes[sibling].right) == Color::Black {
+                        self.set_color(self.nodes[sibling].left, Color::Black);
+                        self.set_color(Some(sibling), Color::Red);
+                        self.rotate_right(sibling);
+                        sibling = self.nodes[current_parent].right.unwrap();
+                    }
+                    self.nodes[sibling].color = self.nodes[current_parent].color;
+                    self.set_color(Some(current_parent), Color::Black);
+                    self.set_color(self.nodes[sibling].right, Color::Black);
+                    self.rotate_left(current_parent);
+                    node = self.root;
+                    parent = None;
+                }
+            } else {
+                let mut sibling = self.nodes[current_parent].left.unwrap();
+                if self.nodes[sibling].color == Color::Red {
+                    self.set_color(Some(sibling), Color::Black);
+                    self.set_color(Some(current_parent), Color::Red);
+                    self.rotate_right(current_parent);
+                    sibling = self.nodes[current_parent].left.unwrap();
+                }
+                if self.color(self.nodes[sibling].left) == Color::Black
+                    && self.color(self.nodes[sibling].right) == Color::Black
+                {
+                    self.set_color(Some(sibling), Color::Red);
+                    node = Some(current_parent);
+                    parent = self.nodes[current_parent].parent;
+                } else {
+                    if self.color(self.nodes[sibling].left) == Color::Black {
+                        self.set_color(self.nodes[sibling].right, Color::Black);
+                        self.set_color(Some(sibling), Color::Red);
+                        self.rotate_left(sibling);
+                        sibling = self.nodes[current_parent].left.unwrap();
+                    }
+                    self.nodes[sibling].color = self.nodes[current_parent].color;
+                    self.set_color(Some(current_parent), Color::Black);
+                    self.set_color(self.nodes[sibling].left, Color::Black);
+                    self.rotate_right(current_parent);
+                    node = self.root;
+                    parent = None;
+                }
+            }
+        }
+        self.set_color(node, Color::Black);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+
+    /// Checks the in order links match the model and the red black properties hold, returning the
+    /// black height of the subtree.
+    fn check_subtree(tree: &Tree<usize>, node: Option<NodeKey>) -> usize {
+        match node {
+            None => 1,
+            Some(node) => {
+                let left = tree.get_left(node);
+                let right = tree.get_right(node);
+                if tree.nodes[node].color == Color::Red {
+                    assert_eq!(tree.color(left), Color::Black);
+                    assert_eq!(tree.color(right), Color::Black);
+                }
+                for child in left.iter().chain(right.iter()) {
+                    assert_eq!(tree.nodes[*child].parent, Some(node));
+                }
+                let left_height = check_subtree(tree, left);
+                assert_eq!(left_height, check_subtree(tree, right));
+                left_height + (tree.nodes[node].color == Color::Black) as usize
+            }
+        }
+    }
+
+    fn check_tree(tree: &Tree<usize>, model: &[(usize, NodeKey)]) {
+        let mut contents = vec![];
+        let mut node = tree.get_leftmost_node();
+        while let Some(current) = node {
+            contents.push((*tree.get_contents(current), current));
+            node = tree.get_next(current);
+        }
+        assert_eq!(contents, model);
+        for pair in model.windows(2) {
+            assert_eq!(tree.get_prev(pair[1].1), Some(pair[0].1));
+        }
+        assert_eq!(tree.color(tree.root), Color::Black);
+        check_subtree(tree, tree.root);
+    }
+
+    #[test]
+    fn tree_test() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(2);
+        let mut tree = Tree::new();
+        let mut model = vec![(0, tree.create_root(0))];
+        for i in 1..2000 {
+            if rng.gen_bool(0.3) && model.len() > 1 {
+                let (contents, node) = model.remove(rng.gen_range(0, model.len()));
+                assert_eq!(tree.delete_node(node), contents);
+            } else {
+                let position = rng.gen_range(0, model.len());
+                let node = model[position].1;
+                if rng.gen() {
+                    model.insert(position, (i, tree.insert_before(node, i)));
+                } else {
+                    model.insert(position + 1, (i, tree.insert_after(node, i)));
+                }
+            }
+            if i % 100 == 0 {
+                check_tree(&tree, &model);
+            }
+        }
+
+        while let Some((contents, node)) = model.pop() {
+            assert_eq!(tree.delete_node(node), contents);
+        }
+        assert!(!tree.has_root());
+    }
+}
@@ -1,4 +1,4 @@
-use cgmath::EuclideanSpace;
+use cgmath::{EuclideanSpace, InnerSpace};
 
 pub fn get_orthogonal(a: cgmath::Vector2<f64>) -> cgmath::Vector2<f64> {
     cgmath::Vector2::new(-a.y, a.x)
@@ -19,3 +19,55 @@ pub fn compute_circumcircle_center(
     let t = get_det(delta, v2) / get_det(v1, v2);
     (point_1 + point_2.to_vec()) * 0.5 + v1 * t
 }
+
+/// Returns the area of a polygon, positive if it winds counter-clockwise when y increases up and
+/// negative otherwise.
+pub fn get_polygon_signed_area(polygon: &[cgmath::Point2<f64>]) -> f64 {
+    let n = polygon.len();
+    (0..n)
+        .map(|i| get_det(polygon[i].to_vec(), polygon[(i + 1) % n].to_vec()))
+        .sum::<f64>()
+        * 0.5
+}
+
+/// Returns the centroid of a polygon, or the mean of its corners if it has no area.
+///
+/// # Panics
+/// If the polygon has no corners.
+pub fn get_polygon_centroid(polygon: &[cgmath::Point2<f64>]) -> cgmath::Point2<f64> {
+    // The corners are taken relative to the first one to limit rounding errors
+    let start = polygon[0];
+    let mut area = 0.0;
+    let mut acc = cgmath::Vector2::new(0.0, 0.0);
+    for i in 0..polygon.len() {
+        let (corner, next_corner) = (polygon[i] - start, polygon[(i + 1) % polygon.len()] - start);
+        let cross = get_det(corner, next_corner);
+        area += cross;
+        acc += (corner + next_corner) * cross;
+    }
+    if area == 0.0 {
+        let sum = polygon
+            .iter()
+            .fold(cgmath::Vector2::new(0.0, 0.0), |sum, corner| {
+                sum + corner.to_vec()
+            });
+        return cgmath::Point2::from_vec(sum / polygon.len() as f64);
+    }
+    start + acc / (3.0 * area)
+}
+
+/// Returns the distance from a point to the closest point on a line segment.
+pub fn get_segment_distance(
+    point: cgmath::Point2<f64>,
+    start: cgmath::Point2<f64>,
+    end: cgmath::Point2<f64>,
+) -> f64 {
+    let direction = end - start;
+    let length2 = direction.magnitude2();
+    let t = if length2 > 0.0 {
+        ((point - start).dot(direction) / length2).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    (point - (start + direction * t)).magnitude()
+}
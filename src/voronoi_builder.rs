@@ -1,260 +1,625 @@
 use super::*;
-use cgmath::MetricSpace;
-use priority_queue::PriorityQueue;
-use log::info;
+use crate::priority_queue::PriorityQueue;
+use beachline::compute_breakpoint;
+use cgmath::{InnerSpace, MetricSpace};
+use log::{debug, info, trace};
+
+/// How close a site must be to the x coordinate of a breakpoint to be treated as directly below it.
+const BREAKPOINT_TOLERANCE: f64 = 1e-12;
+
+/// The sine of the angle between the offsets of three sites below which they are treated as being
+/// on a line.
+const COLLINEAR_TOLERANCE: f64 = 1e-12;
 
 /// Generate a voronoi diagram using fortunes's algorithm from the supplied points.
 /// # Arguments
 /// * `points` - The points to construct the diagram from, these should be in the range [0,1] X [0,1].
 pub fn build_voronoi(points: &[cgmath::Point2<f64>]) -> Diagram {
-    let mut event_queue = PriorityQueue::new();
+    VoronoiSweep::new(points).finish()
+}
 
-    let mut voronoi = Diagram::default();
+/// Generate a voronoi diagram from the supplied points, reporting every event of the sweep to an
+/// observer.
+/// # Arguments
+/// * `points` - The points to construct the diagram from, these should be in the range [0,1] X [0,1].
+/// * `observer` - The observer to notify, pass `&mut observer` to keep hold of it.
+pub fn build_voronoi_with_observer<O: SweepObserver>(
+    points: &[cgmath::Point2<f64>],
+    observer: O,
+) -> Diagram {
+    VoronoiSweep::with_observer(points, observer).finish()
+}
 
-    for &point in points {
-        voronoi.add_face(point);
-    }
+/// Run fortune's algorithm over the faces already in a diagram.
+///
+/// Any existing vertices and half edges are discarded, the faces and their indices are kept.
+pub(crate) fn compute_voronoi(voronoi: &mut Diagram) {
+    let sweep = VoronoiSweep::from_diagram(std::mem::take(voronoi));
+    *voronoi = sweep.finish();
+}
 
-    let mut beachline = Beachline::new();
+/// Generates voronoi diagrams repeatedly, keeping the storage of the beachline and event queue
+/// between builds rather than allocating it again each time.
+#[derive(Default)]
+pub struct VoronoiBuilder {
+    beachline: Beachline,
+    event_queue: PriorityQueue<Event>,
+}
 
-    for &face in voronoi.get_face_indices().iter() {
-        event_queue.push(Event::site_event(voronoi.get_face_point(face).y, face));
+impl VoronoiBuilder {
+    pub fn new() -> Self {
+        VoronoiBuilder::default()
     }
 
-    loop {
-        let event = event_queue.pop();
-        match event {
-            Some(event) => handle_event(
-                event.event_type(),
-                &mut voronoi,
-                &mut beachline,
-                event.y(),
-                &mut event_queue,
-            ),
-            None => break,
-        }
+    /// Generate the voronoi diagram of the supplied points into an existing diagram.
+    ///
+    /// The storage of the diagram is reused, if it already has one face per point the face
+    /// indices are kept and their points replaced in order.
+    /// # Arguments
+    /// * `points` - The points to construct the diagram from, these should be in the range [0,1] X [0,1].
+    /// * `voronoi` - The diagram to replace with the result.
+    pub fn build(&mut self, points: &[cgmath::Point2<f64>], voronoi: &mut Diagram) {
+        voronoi.set_face_points(points);
+        let mut sweep = VoronoiSweep::from_parts(
+            std::mem::take(voronoi),
+            std::mem::take(&mut self.beachline),
+            std::mem::take(&mut self.event_queue),
+            (),
+        );
+        sweep.complete();
+        *voronoi = sweep.voronoi;
+        self.beachline = sweep.beachline;
+        self.event_queue = sweep.event_queue;
     }
+}
 
-    bound_diagram(&mut voronoi, &beachline);
-    let bbox = BoundingBox::new(0.0, 1.0, 0.0, 1.0);
-    bbox.intersect_diagram(&mut voronoi);
-    voronoi
+/// An arc of the beachline of a sweep in progress.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BeachlineArc {
+    /// The face whose site is the focus of the arc.
+    pub face: FaceKey,
+    /// The site of the face.
+    pub site: cgmath::Point2<f64>,
+    /// The x coordinate of the left breakpoint at the current sweep y, negative infinity for the
+    /// leftmost arc.
+    pub left: f64,
+    /// The x coordinate of the right breakpoint at the current sweep y, infinity for the
+    /// rightmost arc.
+    pub right: f64,
 }
 
-fn handle_event(
-    event_type: &EventType,
-    voronoi: &mut Diagram,
-    beachline: &mut Beachline,
-    current_y: f64,
-    event_queue: &mut PriorityQueue<Event>,
-) {
-    match *event_type {
-        EventType::SiteEvent { face } => {
-            handle_site_event(face, voronoi, beachline, current_y, event_queue)
-        }
-        EventType::CircleEvent { point, arc } => {
-            handle_circle_event(point, arc, voronoi, beachline, current_y, event_queue)
-        }
-    }
+/// An event that is still waiting to be processed by a sweep.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SweepEvent {
+    /// The sweep line will reach the site of a face.
+    Site { face: FaceKey, y: f64 },
+    /// The arc of a face will disappear, adding a vertex at the center of the circle.
+    Circle {
+        face: FaceKey,
+        y: f64,
+        center: cgmath::Point2<f64>,
+    },
 }
 
-fn handle_site_event(
-    face: FaceKey,
-    voronoi: &mut Diagram,
-    beachline: &mut Beachline,
-    current_y: f64,
-    event_queue: &mut PriorityQueue<Event>,
-) {
-    // 1 Check if beachline is empty
-    if !beachline.tree.has_root() {
-        beachline.create_root(face);
-        return;
-    }
-
-    // 2 Look for the arc above the site
-    let point = voronoi.get_face_point(face);
-    let middle_arc = beachline.locate_arc_above(point, current_y, voronoi);
-    delete_event(middle_arc, beachline, event_queue);
-
-    // 3 Replace this arc by new arcs
-    beachline.break_arc(middle_arc, face);
-    let left_arc = beachline.tree.get_prev(middle_arc).unwrap();
-    let right_arc = beachline.tree.get_next(middle_arc).unwrap();
-
-    // 4 Add a new edge to the diagram
-    let (half_edge_1, half_edge_2) = voronoi.add_edge(
-        beachline.get_arc_face(left_arc).unwrap(),
-        beachline.get_arc_face(middle_arc).unwrap(),
-    );
-
-    beachline.set_right_half_edge(left_arc, Some(half_edge_1));
-    beachline.set_left_half_edge(middle_arc, Some(half_edge_2));
-    beachline.set_right_half_edge(middle_arc, Some(half_edge_2));
-    beachline.set_left_half_edge(right_arc, Some(half_edge_1));
-
-    // 5 Check circle events
-    let prev_arc = beachline.tree.get_prev(left_arc);
-    if prev_arc.is_some() {
-        add_event(
-            prev_arc.unwrap(),
-            left_arc,
-            middle_arc,
-            voronoi,
-            beachline,
-            current_y,
-            event_queue,
-        );
-    }
-    let next_arc = beachline.tree.get_next(right_arc);
-    if next_arc.is_some() {
-        add_event(
-            middle_arc,
-            right_arc,
-            next_arc.unwrap(),
-            voronoi,
-            beachline,
-            current_y,
-            event_queue,
-        );
+impl SweepEvent {
+    /// Returns the sweep y at which the event will be processed.
+    pub fn y(&self) -> f64 {
+        match *self {
+            SweepEvent::Site { y, .. } | SweepEvent::Circle { y, .. } => y,
+        }
     }
 }
 
-fn is_moving_right(left: cgmath::Point2<f64>, right: cgmath::Point2<f64>) -> bool {
-    left.y > right.y
+/// A run of fortune's algorithm that can be advanced one event at a time.
+///
+/// This exposes the state of the sweep between events, the beachline, the pending events and the
+/// partially constructed diagram, which is useful for visualising how the diagram is built. Every
+/// change made by the sweep is reported to its observer, see `SweepObserver`.
+pub struct VoronoiSweep<O: SweepObserver = ()> {
+    voronoi: Diagram,
+    beachline: Beachline,
+    event_queue: PriorityQueue<Event>,
+    sweep_y: f64,
+    event_count: usize,
+    observer: O,
 }
 
-fn get_initial_x(left: cgmath::Point2<f64>, right: cgmath::Point2<f64>, moving_right: bool) -> f64 {
-    if moving_right {
-        left.x
-    } else {
-        right.x
+impl VoronoiSweep {
+    /// Start a sweep over the supplied points.
+    /// # Arguments
+    /// * `points` - The points to construct the diagram from, these should be in the range [0,1] X [0,1].
+    pub fn new(points: &[cgmath::Point2<f64>]) -> Self {
+        VoronoiSweep::with_observer(points, ())
+    }
+
+    /// Start a sweep over the faces already in a diagram, discarding any existing vertices and
+    /// half edges.
+    pub(crate) fn from_diagram(voronoi: Diagram) -> Self {
+        VoronoiSweep::from_diagram_with_observer(voronoi, ())
     }
 }
 
-fn add_event(
-    left_arc: NodeKey,
-    middle_arc: NodeKey,
-    right_arc: NodeKey,
-    voronoi: &Diagram,
-    beachline: &mut Beachline,
-    current_y: f64,
-    event_queue: &mut PriorityQueue<Event>,
-) {
-    let left_point = voronoi.get_face_point(beachline.get_arc_face(left_arc).unwrap());
-    let middle_point = voronoi.get_face_point(beachline.get_arc_face(middle_arc).unwrap());
-    let right_point = voronoi.get_face_point(beachline.get_arc_face(right_arc).unwrap());
-    let center = compute_circumcircle_center(left_point, middle_point, right_point);
-    let radius = center.distance(middle_point);
-    let event_y = center.y + radius;
-
-    if event_y > current_y - f64::EPSILON {
-        let left_breakpoint_moving_right = is_moving_right(left_point, middle_point);
-        let right_breakpoint_moving_right = is_moving_right(middle_point, right_point);
-        let left_initial_x = get_initial_x(left_point, middle_point, left_breakpoint_moving_right);
-        let right_initial_x =
-            get_initial_x(middle_point, right_point, right_breakpoint_moving_right);
-
-        let is_valid = ((left_breakpoint_moving_right && left_initial_x <= center.x)
-            || (!left_breakpoint_moving_right && left_initial_x >= center.x))
-            && (right_breakpoint_moving_right && right_initial_x <= center.x
-                || !right_breakpoint_moving_right && right_initial_x >= center.x);
-
-        if is_valid {
-            let event = event_queue.push(Event::circle_event(event_y, center, middle_arc));
-            beachline.set_arc_event(middle_arc, event);
+impl<O: SweepObserver> VoronoiSweep<O> {
+    /// Start a sweep over the supplied points which reports each change to an observer.
+    /// # Arguments
+    /// * `points` - The points to construct the diagram from, these should be in the range [0,1] X [0,1].
+    /// * `observer` - The observer to notify, pass `&mut observer` to keep hold of it.
+    pub fn with_observer(points: &[cgmath::Point2<f64>], observer: O) -> Self {
+        let mut voronoi = Diagram::default();
+        for &point in points {
+            voronoi.add_face(point);
         }
+        VoronoiSweep::from_diagram_with_observer(voronoi, observer)
     }
-}
 
-fn handle_circle_event(
-    point: cgmath::Point2<f64>,
-    arc: NodeKey,
-    voronoi: &mut Diagram,
-    beachline: &mut Beachline,
-    y: f64,
-    event_queue: &mut PriorityQueue<Event>,
-) {
-    // 1 Add vertex
-    let vertex = voronoi.add_vertex(point);
-
-    // 2 Delete all events with this arc
-    let left_arc = beachline.tree.get_prev(arc).unwrap();
-    let right_arc = beachline.tree.get_next(arc).unwrap();
-
-    delete_event(left_arc, beachline, event_queue);
-    delete_event(right_arc, beachline, event_queue);
-
-    // 3. Update the beachline and the diagram
-    remove_arc(arc, vertex, voronoi, beachline);
-
-    // 4. Add new circle events
-    let left_arc_prev = beachline.tree.get_prev(left_arc);
-    if left_arc_prev.is_some() {
-        add_event(
-            left_arc_prev.unwrap(),
-            left_arc,
-            right_arc,
+    fn from_diagram_with_observer(voronoi: Diagram, observer: O) -> Self {
+        VoronoiSweep::from_parts(voronoi, Beachline::new(), PriorityQueue::new(), observer)
+    }
+
+    /// Start a sweep using existing storage for the beachline and event queue, which are cleared.
+    fn from_parts(
+        mut voronoi: Diagram,
+        mut beachline: Beachline,
+        mut event_queue: PriorityQueue<Event>,
+        mut observer: O,
+    ) -> Self {
+        voronoi.clear_edges();
+        beachline.clear();
+        event_queue.clear();
+
+        for &face in voronoi.get_face_indices().iter() {
+            event_queue.push(Event::site_event(voronoi.get_face_point(face), face));
+        }
+
+        info!("Starting sweep over {} sites", event_queue.len());
+        observer.sweep_started(&voronoi);
+
+        VoronoiSweep {
             voronoi,
             beachline,
-            y,
             event_queue,
+            sweep_y: 0.0,
+            event_count: 0,
+            observer,
+        }
+    }
+
+    /// Process the next event, moving the sweep line down to it.
+    ///
+    /// Returns false once there are no events left to process.
+    pub fn step(&mut self) -> bool {
+        match self.event_queue.pop() {
+            Some(event) => {
+                self.sweep_y = event.y();
+                self.event_count += 1;
+                match *event.event_type() {
+                    EventType::SiteEvent { face } => self.handle_site_event(face),
+                    EventType::CircleEvent { point, arc } => self.handle_circle_event(point, arc),
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the y coordinate of the sweep line, this starts at the top of the unit square and
+    /// is the y of the last processed event after each step.
+    pub fn sweep_y(&self) -> f64 {
+        self.sweep_y
+    }
+
+    /// Returns the arcs of the beachline from left to right along with their breakpoints at the
+    /// current sweep y.
+    pub fn beachline(&self) -> Vec<BeachlineArc> {
+        let mut arcs: Vec<BeachlineArc> = vec![];
+        if !self.beachline.tree.has_root() {
+            return arcs;
+        }
+
+        let mut node = self.beachline.tree.get_leftmost_node();
+        while let Some(current) = node {
+            let face = self.beachline.get_arc_face(current).unwrap();
+            let site = self.voronoi.get_face_point(face);
+            let left = match arcs.last_mut() {
+                Some(prev) => {
+                    // An arc whose site is on the sweep line is a vertical ray from its site
+                    let breakpoint = if (site.y - self.sweep_y).abs() < f64::EPSILON {
+                        site.x
+                    } else {
+                        compute_breakpoint(prev.site, site, self.sweep_y)
+                    };
+                    prev.right = breakpoint;
+                    breakpoint
+                }
+                None => f64::NEG_INFINITY,
+            };
+            arcs.push(BeachlineArc {
+                face,
+                site,
+                left,
+                right: f64::INFINITY,
+            });
+            node = self.beachline.tree.get_next(current);
+        }
+        arcs
+    }
+
+    /// Returns the events still waiting to be processed, ordered by the y at which they occur.
+    pub fn pending_events(&self) -> Vec<SweepEvent> {
+        let mut events: Vec<&Event> = self.event_queue.iter().collect();
+        events.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        events
+            .into_iter()
+            .map(|event| match *event.event_type() {
+                EventType::SiteEvent { face } => SweepEvent::Site { face, y: event.y() },
+                EventType::CircleEvent { point, arc } => SweepEvent::Circle {
+                    face: self.beachline.get_arc_face(arc).unwrap(),
+                    y: event.y(),
+                    center: point,
+                },
+            })
+            .collect()
+    }
+
+    /// Returns the diagram constructed so far, edges which haven't been completed have no origin
+    /// or destination vertex.
+    pub fn diagram(&self) -> &Diagram {
+        &self.voronoi
+    }
+
+    /// Returns the observer notified by the sweep.
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    /// Process all the remaining events and bound the diagram to the unit square.
+    pub fn finish(mut self) -> Diagram {
+        self.complete();
+        self.voronoi
+    }
+
+    fn complete(&mut self) {
+        while self.step() {}
+
+        bound_diagram(&mut self.voronoi, &self.beachline);
+        let bbox = BoundingBox::new(0.0, 1.0, 0.0, 1.0);
+        bbox.intersect_diagram(&mut self.voronoi);
+        info!("Completed sweep after {} events", self.event_count);
+    }
+
+    fn handle_site_event(&mut self, face: FaceKey) {
+        debug!("Site event for face {:?} at y {}", face, self.sweep_y);
+        // 1 Check if beachline is empty
+        if !self.beachline.tree.has_root() {
+            trace!("Created the first arc for face {:?}", face);
+            self.observer.site_event(face, None, self.sweep_y);
+            self.beachline.create_root(face);
+            return;
+        }
+
+        // 2 Look for the arc above the site
+        let point = self.voronoi.get_face_point(face);
+        let middle_arc = self
+            .beachline
+            .locate_arc_above(point, self.sweep_y, &self.voronoi);
+        self.observer
+            .site_event(face, self.beachline.get_arc_face(middle_arc), self.sweep_y);
+        let middle_face = self.beachline.get_arc_face(middle_arc).unwrap();
+        if (self.voronoi.get_face_point(middle_face).y - self.sweep_y).abs() < f64::EPSILON {
+            // Sites on the same line as the first site do not split an arc
+            self.add_arc_beside(middle_arc, face);
+            return;
+        }
+        if let Some((left_arc, right_arc)) = self.find_breakpoint_above(point, middle_arc) {
+            self.add_arc_at_breakpoint(left_arc, right_arc, face);
+            return;
+        }
+        self.delete_event(middle_arc);
+
+        // 3 Replace this arc by new arcs
+        trace!(
+            "Split the arc of face {:?} for face {:?}",
+            middle_face,
+            face
+        );
+        self.beachline.break_arc(middle_arc, face);
+        let left_arc = self.beachline.tree.get_prev(middle_arc).unwrap();
+        let right_arc = self.beachline.tree.get_next(middle_arc).unwrap();
+
+        // 4 Add a new edge to the diagram
+        let (half_edge_1, half_edge_2) = self.voronoi.add_edge(
+            self.beachline.get_arc_face(left_arc).unwrap(),
+            self.beachline.get_arc_face(middle_arc).unwrap(),
         );
+
+        self.beachline
+            .set_right_half_edge(left_arc, Some(half_edge_1));
+        self.beachline
+            .set_left_half_edge(middle_arc, Some(half_edge_2));
+        self.beachline
+            .set_right_half_edge(middle_arc, Some(half_edge_2));
+        self.beachline
+            .set_left_half_edge(right_arc, Some(half_edge_1));
+
+        // 5 Check circle events
+        if let Some(prev_arc) = self.beachline.tree.get_prev(left_arc) {
+            self.add_event(prev_arc, left_arc, middle_arc);
+        }
+        if let Some(next_arc) = self.beachline.tree.get_next(right_arc) {
+            self.add_event(middle_arc, right_arc, next_arc);
+        }
     }
-    let right_arc_next = beachline.tree.get_next(right_arc);
-    if right_arc_next.is_some() {
-        add_event(
-            left_arc,
-            right_arc,
-            right_arc_next.unwrap(),
-            voronoi,
-            beachline,
-            y,
-            event_queue,
+
+    /// Returns the arcs either side of the breakpoint directly above a site, if it is at one of the
+    /// ends of the arc above it.
+    fn find_breakpoint_above(
+        &self,
+        point: cgmath::Point2<f64>,
+        arc: NodeKey,
+    ) -> Option<(NodeKey, NodeKey)> {
+        let arc_point = self
+            .voronoi
+            .get_face_point(self.beachline.get_arc_face(arc).unwrap());
+        let is_above = |left_point, right_point| {
+            (compute_breakpoint(left_point, right_point, self.sweep_y) - point.x).abs()
+                < BREAKPOINT_TOLERANCE
+        };
+        if let Some(prev_arc) = self.beachline.tree.get_prev(arc) {
+            let prev_point = self
+                .voronoi
+                .get_face_point(self.beachline.get_arc_face(prev_arc).unwrap());
+            if is_above(prev_point, arc_point) {
+                return Some((prev_arc, arc));
+            }
+        }
+        if let Some(next_arc) = self.beachline.tree.get_next(arc) {
+            let next_point = self
+                .voronoi
+                .get_face_point(self.beachline.get_arc_face(next_arc).unwrap());
+            if is_above(arc_point, next_point) {
+                return Some((arc, next_arc));
+            }
+        }
+        None
+    }
+
+    /// Adds the arc of a site directly below the breakpoint between two arcs. Breaking either arc
+    /// would leave a piece of it with no width, instead the edge between them ends at a vertex
+    /// where the edges of the new arc start.
+    fn add_arc_at_breakpoint(&mut self, left_arc: NodeKey, right_arc: NodeKey, face: FaceKey) {
+        self.delete_event(left_arc);
+        self.delete_event(right_arc);
+
+        let left_face = self.beachline.get_arc_face(left_arc).unwrap();
+        let right_face = self.beachline.get_arc_face(right_arc).unwrap();
+        let center = compute_circumcircle_center(
+            self.voronoi.get_face_point(left_face),
+            self.voronoi.get_face_point(face),
+            self.voronoi.get_face_point(right_face),
         );
+        let vertex = self.voronoi.add_vertex(center);
+
+        // End the edge between the arcs
+        let left_half_edge = self.beachline.get_right_half_edge(left_arc).unwrap();
+        let right_half_edge = self.beachline.get_left_half_edge(right_arc).unwrap();
+        self.voronoi
+            .set_half_edge_origin(left_half_edge, Some(vertex));
+        self.voronoi
+            .set_half_edge_destination(right_half_edge, Some(vertex));
+
+        // Start an edge either side of the new arc
+        trace!(
+            "Added the arc of face {:?} below the breakpoint between faces {:?} and {:?}",
+            face,
+            left_face,
+            right_face
+        );
+        let middle_arc = self.beachline.insert_arc_after(left_arc, face);
+        let (half_edge_1, half_edge_2) = self.voronoi.add_edge(left_face, face);
+        let (half_edge_3, half_edge_4) = self.voronoi.add_edge(face, right_face);
+        self.beachline
+            .set_right_half_edge(left_arc, Some(half_edge_1));
+        self.beachline
+            .set_left_half_edge(middle_arc, Some(half_edge_2));
+        self.beachline
+            .set_right_half_edge(middle_arc, Some(half_edge_3));
+        self.beachline
+            .set_left_half_edge(right_arc, Some(half_edge_4));
+        self.voronoi
+            .set_half_edge_destination(half_edge_1, Some(vertex));
+        self.voronoi.set_half_edge_origin(half_edge_2, Some(vertex));
+        self.voronoi
+            .set_half_edge_destination(half_edge_3, Some(vertex));
+        self.voronoi.set_half_edge_origin(half_edge_4, Some(vertex));
+
+        self.voronoi.link_half_edges(half_edge_1, left_half_edge);
+        self.voronoi.link_half_edges(half_edge_3, half_edge_2);
+        self.voronoi.link_half_edges(right_half_edge, half_edge_4);
+
+        if let Some(prev_arc) = self.beachline.tree.get_prev(left_arc) {
+            self.add_event(prev_arc, left_arc, middle_arc);
+        }
+        if let Some(next_arc) = self.beachline.tree.get_next(right_arc) {
+            self.add_event(middle_arc, right_arc, next_arc);
+        }
     }
-}
 
-fn delete_event(arc: NodeKey, beachline: &Beachline, event_queue: &mut PriorityQueue<Event>) {
-    event_queue.remove(beachline.get_arc_event(arc));
-}
+    /// Adds the arc of a site on the same line as every site swept so far beside the arc of the
+    /// nearest of them.
+    fn add_arc_beside(&mut self, arc: NodeKey, face: FaceKey) {
+        let point = self.voronoi.get_face_point(face);
+        let arc_point = self
+            .voronoi
+            .get_face_point(self.beachline.get_arc_face(arc).unwrap());
+        trace!(
+            "Added the arc of face {:?} beside the arc of face {:?}",
+            face,
+            self.beachline.get_arc_face(arc).unwrap()
+        );
+        self.beachline
+            .insert_arc_beside(arc, face, point.x < arc_point.x, &mut self.voronoi);
+    }
+
+    fn add_event(&mut self, left_arc: NodeKey, middle_arc: NodeKey, right_arc: NodeKey) {
+        let left_face = self.beachline.get_arc_face(left_arc).unwrap();
+        let middle_face = self.beachline.get_arc_face(middle_arc).unwrap();
+        let right_face = self.beachline.get_arc_face(right_arc).unwrap();
+        let left_point = self.voronoi.get_face_point(left_face);
+        let middle_point = self.voronoi.get_face_point(middle_face);
+        let right_point = self.voronoi.get_face_point(right_face);
+        let (left_offset, right_offset) = (middle_point - left_point, right_point - middle_point);
+        if vector2::get_det(left_offset, right_offset).abs()
+            <= COLLINEAR_TOLERANCE * left_offset.magnitude() * right_offset.magnitude()
+        {
+            // The breakpoints either side of an arc between collinear sites never meet, and for
+            // sites that are only just off a line they meet far beyond the diagram
+            return;
+        }
+        let center = compute_circumcircle_center(left_point, middle_point, right_point);
+        let radius = center.distance(middle_point);
+        let event_y = center.y + radius;
+
+        if event_y > self.sweep_y - f64::EPSILON {
+            let left_breakpoint_moving_right = is_moving_right(left_point, middle_point);
+            let right_breakpoint_moving_right = is_moving_right(middle_point, right_point);
+            let left_initial_x =
+                get_initial_x(left_point, middle_point, left_breakpoint_moving_right);
+            let right_initial_x =
+                get_initial_x(middle_point, right_point, right_breakpoint_moving_right);
+
+            let is_valid = ((left_breakpoint_moving_right && left_initial_x <= center.x)
+                || (!left_breakpoint_moving_right && left_initial_x >= center.x))
+                && (right_breakpoint_moving_right && right_initial_x <= center.x
+                    || !right_breakpoint_moving_right && right_initial_x >= center.x);
+
+            if is_valid {
+                let event = self
+                    .event_queue
+                    .push(Event::circle_event(event_y, center, middle_arc));
+                self.beachline.set_arc_event(middle_arc, event);
+                trace!(
+                    "Added a circle event for face {:?} at y {} with center {:?}",
+                    middle_face,
+                    event_y,
+                    center
+                );
+                self.observer
+                    .event_added(left_face, middle_face, right_face, event_y, center);
+            }
+        }
+    }
+
+    fn handle_circle_event(&mut self, point: cgmath::Point2<f64>, arc: NodeKey) {
+        debug!(
+            "Circle event for face {:?} at y {} with center {:?}",
+            self.beachline.get_arc_face(arc).unwrap(),
+            self.sweep_y,
+            point
+        );
+        // 1 Add vertex
+        let vertex = self.voronoi.add_vertex(point);
+        self.observer.circle_event(
+            self.beachline.get_arc_face(arc).unwrap(),
+            vertex,
+            self.sweep_y,
+        );
+
+        // 2 Delete all events with this arc
+        let left_arc = self.beachline.tree.get_prev(arc).unwrap();
+        let right_arc = self.beachline.tree.get_next(arc).unwrap();
+
+        self.delete_event(left_arc);
+        self.delete_event(right_arc);
+
+        // 3. Update the beachline and the diagram
+        self.remove_arc(arc, vertex);
+
+        // 4. Add new circle events
+        if let Some(left_arc_prev) = self.beachline.tree.get_prev(left_arc) {
+            self.add_event(left_arc_prev, left_arc, right_arc);
+        }
+        if let Some(right_arc_next) = self.beachline.tree.get_next(right_arc) {
+            self.add_event(left_arc, right_arc, right_arc_next);
+        }
+    }
 
-fn remove_arc(arc: NodeKey, vertex: VertexKey, voronoi: &mut Diagram, beachline: &mut Beachline) {
-    let prev = beachline.tree.get_prev(arc).unwrap();
-    let next = beachline.tree.get_next(arc).unwrap();
-    let left_half_edge = beachline.get_left_half_edge(arc).unwrap();
-    let right_half_edge = beachline.get_right_half_edge(arc).unwrap();
-    let prev_right_half_edge = beachline.get_right_half_edge(prev).unwrap();
-    let next_left_half_edge = beachline.get_left_half_edge(next).unwrap();
+    fn delete_event(&mut self, arc: NodeKey) {
+        if let Some(event) = self.event_queue.remove(self.beachline.get_arc_event(arc)) {
+            trace!(
+                "Deleted the circle event for face {:?} at y {}",
+                self.beachline.get_arc_face(arc).unwrap(),
+                event.y()
+            );
+            self.observer
+                .event_deleted(self.beachline.get_arc_face(arc).unwrap(), event.y());
+        }
+    }
 
-    // End existing edges
-    voronoi.set_half_edge_origin(prev_right_half_edge, Some(vertex));
-    voronoi.set_half_edge_destination(left_half_edge, Some(vertex));
-    voronoi.set_half_edge_origin(right_half_edge, Some(vertex));
-    voronoi.set_half_edge_destination(next_left_half_edge, Some(vertex));
+    fn remove_arc(&mut self, arc: NodeKey, vertex: VertexKey) {
+        let voronoi = &mut self.voronoi;
+        let beachline = &mut self.beachline;
+        let prev = beachline.tree.get_prev(arc).unwrap();
+        let next = beachline.tree.get_next(arc).unwrap();
+        let left_half_edge = beachline.get_left_half_edge(arc).unwrap();
+        let right_half_edge = beachline.get_right_half_edge(arc).unwrap();
+        let prev_right_half_edge = beachline.get_right_half_edge(prev).unwrap();
+        let next_left_half_edge = beachline.get_left_half_edge(next).unwrap();
+
+        trace!(
+            "Removed the arc of face {:?} between faces {:?} and {:?}",
+            beachline.get_arc_face(arc).unwrap(),
+            beachline.get_arc_face(prev).unwrap(),
+            beachline.get_arc_face(next).unwrap()
+        );
+        self.observer.arc_removed(
+            beachline.get_arc_face(prev).unwrap(),
+            beachline.get_arc_face(arc).unwrap(),
+            beachline.get_arc_face(next).unwrap(),
+            vertex,
+        );
 
-    // Join the edges of the middle arc
-    voronoi.link_half_edges(left_half_edge, right_half_edge);
+        // End existing edges
+        voronoi.set_half_edge_origin(prev_right_half_edge, Some(vertex));
+        voronoi.set_half_edge_destination(left_half_edge, Some(vertex));
+        voronoi.set_half_edge_origin(right_half_edge, Some(vertex));
+        voronoi.set_half_edge_destination(next_left_half_edge, Some(vertex));
 
-    // Create a new edge
-    let prev_half_edge = beachline.get_right_half_edge(prev).unwrap();
-    let next_half_edge = beachline.get_left_half_edge(next).unwrap();
+        // Join the edges of the middle arc
+        voronoi.link_half_edges(left_half_edge, right_half_edge);
 
-    let (half_edge_1, half_edge_2) = voronoi.add_edge(
-        beachline.get_arc_face(prev).unwrap(),
-        beachline.get_arc_face(next).unwrap(),
-    );
+        // Create a new edge
+        let prev_half_edge = beachline.get_right_half_edge(prev).unwrap();
+        let next_half_edge = beachline.get_left_half_edge(next).unwrap();
+
+        let (half_edge_1, half_edge_2) = voronoi.add_edge(
+            beachline.get_arc_face(prev).unwrap(),
+            beachline.get_arc_face(next).unwrap(),
+        );
 
-    beachline.set_right_half_edge(prev, Some(half_edge_1));
-    beachline.set_left_half_edge(next, Some(half_edge_2));
+        beachline.set_right_half_edge(prev, Some(half_edge_1));
+        beachline.set_left_half_edge(next, Some(half_edge_2));
 
-    voronoi.set_half_edge_destination(half_edge_1, Some(vertex));
-    voronoi.set_half_edge_origin(half_edge_2, Some(vertex));
+        voronoi.set_half_edge_destination(half_edge_1, Some(vertex));
+        voronoi.set_half_edge_origin(half_edge_2, Some(vertex));
 
-    voronoi.link_half_edges(half_edge_1, prev_half_edge);
-    voronoi.link_half_edges(next_half_edge, half_edge_2);
+        voronoi.link_half_edges(half_edge_1, prev_half_edge);
+        voronoi.link_half_edges(next_half_edge, half_edge_2);
 
-    // Remove the arc from the beachline
-    beachline.tree.delete_node(arc);
+        // Remove the arc from the beachline
+        beachline.tree.delete_node(arc);
+    }
+}
+
+fn is_moving_right(left: cgmath::Point2<f64>, right: cgmath::Point2<f64>) -> bool {
+    left.y > right.y
+}
+
+fn get_initial_x(left: cgmath::Point2<f64>, right: cgmath::Point2<f64>, moving_right: bool) -> f64 {
+    if moving_right {
+        left.x
+    } else {
+        right.x
+    }
 }
 
 fn bound_diagram(voronoi: &mut Diagram, beachline: &Beachline) {
@@ -274,3 +639,214 @@ fn bound_diagram(voronoi: &mut Diagram, beachline: &Beachline) {
 
     beachline.complete_edges(&bbox, voronoi);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{assert_consistent, assert_matches_rebuild, random_points};
+    use rand::{Rng, SeedableRng};
+    use std::time::Instant;
+
+    #[test]
+    fn voronoi_sweep_test() {
+        let points = random_points(11, 200);
+        let mut sweep = VoronoiSweep::new(&points);
+        assert_eq!(sweep.pending_events().len(), points.len());
+
+        let mut previous_y = sweep.sweep_y();
+        while sweep.step() {
+            assert!(sweep.sweep_y() >= previous_y);
+            previous_y = sweep.sweep_y();
+
+            let arcs = sweep.beachline();
+            assert!(!arcs.is_empty());
+            assert_eq!(arcs[0].left, f64::NEG_INFINITY);
+            assert_eq!(arcs[arcs.len() - 1].right, f64::INFINITY);
+            for pair in arcs.windows(2) {
+                assert_eq!(pair[0].right, pair[1].left);
+            }
+
+            for event in sweep.pending_events() {
+                assert!(event.y() >= sweep.sweep_y() - f64::EPSILON);
+            }
+        }
+        assert!(sweep.pending_events().is_empty());
+
+        let voronoi = sweep.finish();
+        assert_consistent(&voronoi);
+        assert_matches_rebuild(&voronoi);
+    }
+
+    #[test]
+    fn voronoi_sweep_partial_diagram_test() {
+        let points = random_points(12, 50);
+        let mut sweep = VoronoiSweep::new(&points);
+        for _ in 0..5 {
+            assert!(sweep.step());
+        }
+
+        // Some faces have no edges yet, the edges read so far are between vertices in the diagram
+        let voronoi = sweep.diagram();
+        assert!(voronoi
+            .get_face_indices()
+            .into_iter()
+            .any(|face| voronoi.get_face_outer_component(face).is_none()));
+        let endpoints: Vec<_> = voronoi
+            .get_edge_vertices()
+            .into_iter()
+            .map(|(origin, destination)| {
+                (
+                    voronoi.get_vertex_point(origin),
+                    voronoi.get_vertex_point(destination),
+                )
+            })
+            .collect();
+        assert_eq!(voronoi.get_edge_endpoints(), endpoints);
+    }
+
+    #[test]
+    fn voronoi_builder_test() {
+        let mut builder = VoronoiBuilder::new();
+        let mut voronoi = Diagram::new();
+        for (seed, count) in &[(21, 200), (22, 200), (23, 50), (24, 300)] {
+            let points = random_points(*seed, *count);
+            let previous_faces = voronoi.get_face_indices();
+            builder.build(&points, &mut voronoi);
+
+            let faces = voronoi.get_face_indices();
+            if previous_faces.len() == points.len() {
+                assert_eq!(faces, previous_faces);
+            }
+            let rebuilt = build_voronoi(&points);
+            for (i, (&face, rebuilt_face)) in
+                faces.iter().zip(rebuilt.get_face_indices()).enumerate()
+            {
+                assert_eq!(voronoi.get_face_point(face), points[i]);
+                assert!(
+                    (voronoi.get_face_area(face) - rebuilt.get_face_area(rebuilt_face)).abs()
+                        < 1e-12
+                );
+            }
+            assert_consistent(&voronoi);
+        }
+    }
+
+    #[test]
+    fn build_voronoi_clipping_test() {
+        // Few points give long edges with vertices far outside the box
+        for &count in &[2, 3, 4, 5, 10, 50] {
+            for seed in 0..200 {
+                let voronoi = build_voronoi(&random_points(seed, count));
+                assert_consistent(&voronoi);
+                let area: f64 = voronoi
+                    .get_face_indices()
+                    .into_iter()
+                    .map(|face| voronoi.get_face_area(face))
+                    .sum();
+                assert!((area - 1.0).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "compares timings, run with cargo test --release -- --ignored"]
+    fn clipping_scaling_test() {
+        // Points in thin bands along each side of the box, so most regions cross the border
+        let border_points = |count| {
+            let mut rng = rand::rngs::StdRng::seed_from_u64(count as u64);
+            (0..count)
+                .map(|i| {
+                    let t: f64 = rng.gen();
+                    let depth = 0.01 + rng.gen::<f64>() * 1e-5;
+                    match i % 4 {
+                        0 => cgmath::Point2::new(t, depth),
+                        1 => cgmath::Point2::new(1.0 - depth, t),
+                        2 => cgmath::Point2::new(t, 1.0 - depth),
+                        _ => cgmath::Point2::new(depth, t),
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+        let time_clipping = |points: &[cgmath::Point2<f64>]| {
+            let mut sweep = VoronoiSweep::new(points);
+            while sweep.step() {}
+            let start = Instant::now();
+            bound_diagram(&mut sweep.voronoi, &sweep.beachline);
+            let bbox = BoundingBox::new(0.0, 1.0, 0.0, 1.0);
+            bbox.intersect_diagram(&mut sweep.voronoi);
+            let elapsed = start.elapsed();
+            assert_consistent(&sweep.voronoi);
+            elapsed
+        };
+
+        let small = time_clipping(&border_points(2_000));
+        let large = time_clipping(&border_points(32_000));
+        // Sixteen times the points should take roughly sixteen times as long, the quadratic
+        // border passes took more than fifty times as long
+        assert!(large < small * 40);
+    }
+
+    #[test]
+    fn build_voronoi_degenerate_test() {
+        // Rows of sites on the same line, sites directly below breakpoints and collinear sites
+        let mut inputs = vec![];
+        for &n in &[2, 3, 7, 10] {
+            let coordinate = |i: usize, shift: f64| (i as f64 + shift) / n as f64;
+            inputs.push(
+                (0..n)
+                    .map(|i| cgmath::Point2::new(coordinate(i, 0.5), 0.5))
+                    .collect(),
+            );
+            inputs.push(
+                (0..n)
+                    .map(|i| cgmath::Point2::new(0.3, coordinate(i, 0.5)))
+                    .collect(),
+            );
+            inputs.push(
+                (0..n)
+                    .map(|i| cgmath::Point2::new(coordinate(i, 0.5), coordinate(i, 0.3) * 0.7))
+                    .collect(),
+            );
+            let mut grid = vec![];
+            let mut hexagons = vec![];
+            for j in 0..n {
+                for i in 0..n {
+                    grid.push(cgmath::Point2::new(coordinate(i, 0.5), coordinate(j, 0.5)));
+                    let shift = 0.25 + 0.5 * (j % 2) as f64;
+                    hexagons.push(cgmath::Point2::new(
+                        coordinate(i, shift),
+                        coordinate(j, 0.5),
+                    ));
+                }
+            }
+            inputs.push(grid);
+            inputs.push(hexagons);
+        }
+
+        for points in inputs {
+            let voronoi = build_voronoi(&points);
+            assert_consistent(&voronoi);
+            let faces = voronoi.get_face_indices();
+            for &face in &faces {
+                // Compare with the region clipped by every other site
+                let cell = cell::compute_cell(
+                    &voronoi,
+                    face,
+                    voronoi.get_face_point(face),
+                    faces.iter().cloned(),
+                );
+                let vertices = cell.vertices();
+                let area = (0..vertices.len())
+                    .map(|i| {
+                        let a = vertices[i].0;
+                        let b = vertices[(i + 1) % vertices.len()].0;
+                        a.x * b.y - b.x * a.y
+                    })
+                    .sum::<f64>()
+                    .abs()
+                    * 0.5;
+                assert!((voronoi.get_face_area(face) - area).abs() < 1e-9);
+            }
+        }
+    }
+}
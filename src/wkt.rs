@@ -0,0 +1,400 @@
+use super::*;
+use cell::get_counter_clockwise_ring;
+use std::collections::HashSet;
+use std::fmt::Write;
+
+/// The geometry type codes of
+/// [well-known binary](https://en.wikipedia.org/wiki/Well-known_text_representation_of_geometry).
+const WKB_LINE_STRING: u32 = 2;
+const WKB_POLYGON: u32 = 3;
+const WKB_MULTI_LINE_STRING: u32 = 5;
+const WKB_MULTI_POLYGON: u32 = 6;
+const WKB_GEOMETRY_COLLECTION: u32 = 7;
+
+/// Returns the region of a face as a
+/// [well-known text](https://en.wikipedia.org/wiki/Well-known_text_representation_of_geometry)
+/// POLYGON, or `POLYGON EMPTY` if the face has no edges.
+///
+/// The ring is closed and winds as [`get_counter_clockwise_ring`] describes.
+/// # Arguments
+/// * `voronoi` - the diagram containing the face.
+/// * `face` - the face whose region to return.
+///
+/// # Panics
+/// If the face index is invalid.
+pub fn face_to_wkt(voronoi: &Diagram, face: FaceKey) -> String {
+    let mut wkt = String::from("POLYGON ");
+    write_wkt_polygon(&mut wkt, &get_counter_clockwise_ring(voronoi, face));
+    wkt
+}
+
+/// Returns the regions of the faces of a diagram as a well-known text MULTIPOLYGON, leaving out
+/// faces without edges.
+/// # Arguments
+/// * `voronoi` - the diagram to return.
+pub fn to_wkt_multipolygon(voronoi: &Diagram) -> String {
+    let rings = get_rings(voronoi);
+    let mut wkt = String::from("MULTIPOLYGON ");
+    write_wkt_list(
+        &mut wkt,
+        rings.iter().filter(|ring| !ring.is_empty()),
+        |wkt, ring| write_wkt_polygon(wkt, ring),
+    );
+    wkt
+}
+
+/// Returns the regions of the faces of a diagram as a well-known text GEOMETRYCOLLECTION of
+/// POLYGONs, in the order of `get_face_indices`. Faces without edges are given as
+/// `POLYGON EMPTY`, so the position of each polygon matches its face.
+/// # Arguments
+/// * `voronoi` - the diagram to return.
+pub fn to_wkt_geometry_collection(voronoi: &Diagram) -> String {
+    let rings = get_rings(voronoi);
+    let mut wkt = String::from("GEOMETRYCOLLECTION ");
+    write_wkt_list(&mut wkt, rings.iter(), |wkt, ring| {
+        wkt.push_str("POLYGON ");
+        write_wkt_polygon(wkt, ring);
+    });
+    wkt
+}
+
+/// Returns the edges of a diagram as a well-known text MULTILINESTRING, with each edge between
+/// two faces given once.
+/// # Arguments
+/// * `voronoi` - the diagram to return the edges of.
+pub fn edges_to_wkt(voronoi: &Diagram) -> String {
+    let mut wkt = String::from("MULTILINESTRING ");
+    write_wkt_list(&mut wkt, get_edges(voronoi).iter(), |wkt, &(start, end)| {
+        write_wkt_ring(wkt, &[start, end])
+    });
+    wkt
+}
+
+/// Returns the region of a face as little endian
+/// [well-known binary](https://en.wikipedia.org/wiki/Well-known_text_representation_of_geometry)
+/// Polygon, as given by `face_to_wkt`.
+/// # Arguments
+/// * `voronoi` - the diagram containing the face.
+/// * `face` - the face whose region to return.
+///
+/// # Panics
+/// If the face index is invalid.
+pub fn face_to_wkb(voronoi: &Diagram, face: FaceKey) -> Vec<u8> {
+    let mut wkb = vec![];
+    write_wkb_polygon(&mut wkb, &get_counter_clockwise_ring(voronoi, face));
+    wkb
+}
+
+/// Returns the regions of the faces of a diagram as a little endian well-known binary
+/// MultiPolygon, as given by `to_wkt_multipolygon`.
+/// # Arguments
+/// * `voronoi` - the diagram to return.
+pub fn to_wkb_multipolygon(voronoi: &Diagram) -> Vec<u8> {
+    let rings: Vec<Vec<cgmath::Point2<f64>>> = get_rings(voronoi)
+        .into_iter()
+        .filter(|ring| !ring.is_empty())
+        .collect();
+    let mut wkb = vec![];
+    write_wkb_header(&mut wkb, WKB_MULTI_POLYGON, rings.len());
+    for ring in &rings {
+        write_wkb_polygon(&mut wkb, ring);
+    }
+    wkb
+}
+
+/// Returns the regions of the faces of a diagram as a little endian well-known binary
+/// GeometryCollection of Polygons, as given by `to_wkt_geometry_collection`.
+/// # Arguments
+/// * `voronoi` - the diagram to return.
+pub fn to_wkb_geometry_collection(voronoi: &Diagram) -> Vec<u8> {
+    let rings = get_rings(voronoi);
+    let mut wkb = vec![];
+    write_wkb_header(&mut wkb, WKB_GEOMETRY_COLLECTION, rings.len());
+    for ring in &rings {
+        write_wkb_polygon(&mut wkb, ring);
+    }
+    wkb
+}
+
+/// Returns the edges of a diagram as a little endian well-known binary MultiLineString, as given
+/// by `edges_to_wkt`.
+/// # Arguments
+/// * `voronoi` - the diagram to return the edges of.
+pub fn edges_to_wkb(voronoi: &Diagram) -> Vec<u8> {
+    let edges = get_edges(voronoi);
+    let mut wkb = vec![];
+    write_wkb_header(&mut wkb, WKB_MULTI_LINE_STRING, edges.len());
+    for &(start, end) in &edges {
+        write_wkb_header(&mut wkb, WKB_LINE_STRING, 2);
+        write_wkb_points(&mut wkb, &[start, end]);
+    }
+    wkb
+}
+
+fn get_rings(voronoi: &Diagram) -> Vec<Vec<cgmath::Point2<f64>>> {
+    voronoi
+        .get_face_indices()
+        .into_iter()
+        .map(|face| get_counter_clockwise_ring(voronoi, face))
+        .collect()
+}
+
+/// Returns the edges of a diagram, leaving out the second half edge of each twin pair.
+fn get_edges(voronoi: &Diagram) -> Vec<(cgmath::Point2<f64>, cgmath::Point2<f64>)> {
+    // Twins share their vertices, so the points of one are exactly those of the other reversed
+    let key = |start: cgmath::Point2<f64>, end: cgmath::Point2<f64>| {
+        [
+            start.x.to_bits(),
+            start.y.to_bits(),
+            end.x.to_bits(),
+            end.y.to_bits(),
+        ]
+    };
+    let mut seen = HashSet::new();
+    voronoi
+        .get_edge_endpoints()
+        .into_iter()
+        .filter(|&(start, end)| {
+            let is_new = !seen.contains(&key(end, start));
+            seen.insert(key(start, end));
+            is_new
+        })
+        .collect()
+}
+
+fn write_wkt_list<'a, T: 'a, I, F>(wkt: &mut String, items: I, mut write_item: F)
+where
+    I: Iterator<Item = &'a T>,
+    F: FnMut(&mut String, &T),
+{
+    let mut is_empty = true;
+    for item in items {
+        wkt.push_str(if is_empty { "(" } else { ", " });
+        write_item(wkt, item);
+        is_empty = false;
+    }
+    wkt.push_str(if is_empty { "EMPTY" } else { ")" });
+}
+
+fn write_wkt_polygon(wkt: &mut String, ring: &[cgmath::Point2<f64>]) {
+    if ring.is_empty() {
+        wkt.push_str("EMPTY");
+    } else {
+        wkt.push('(');
+        write_wkt_ring(wkt, ring);
+        wkt.push(')');
+    }
+}
+
+fn write_wkt_ring(wkt: &mut String, points: &[cgmath::Point2<f64>]) {
+    wkt.push('(');
+    for (i, point) in points.iter().enumerate() {
+        if i > 0 {
+            wkt.push_str(", ");
+        }
+        write!(wkt, "{} {}", point.x, point.y).unwrap();
+    }
+    wkt.push(')');
+}
+
+fn write_wkb_header(wkb: &mut Vec<u8>, geometry_type: u32, count: usize) {
+    // The byte order, 1 being little endian
+    wkb.push(1);
+    wkb.extend_from_slice(&geometry_type.to_le_bytes());
+    wkb.extend_from_slice(&(count as u32).to_le_bytes());
+}
+
+fn write_wkb_polygon(wkb: &mut Vec<u8>, ring: &[cgmath::Point2<f64>]) {
+    let ring_count = if ring.is_empty() { 0 } else { 1 };
+    write_wkb_header(wkb, WKB_POLYGON, ring_count);
+    if !ring.is_empty() {
+        wkb.extend_from_slice(&(ring.len() as u32).to_le_bytes());
+        write_wkb_points(wkb, ring);
+    }
+}
+
+fn write_wkb_points(wkb: &mut Vec<u8>, points: &[cgmath::Point2<f64>]) {
+    for point in points {
+        wkb.extend_from_slice(&point.x.to_le_bytes());
+        wkb.extend_from_slice(&point.y.to_le_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::random_points;
+    use std::convert::TryInto;
+
+    /// Reads well-known binary back into nested lists of points, checking the byte order and
+    /// geometry types on the way.
+    struct WkbReader<'a> {
+        wkb: &'a [u8],
+    }
+
+    impl<'a> WkbReader<'a> {
+        fn read_u32(&mut self) -> u32 {
+            let (bytes, rest) = self.wkb.split_at(4);
+            self.wkb = rest;
+            u32::from_le_bytes(bytes.try_into().unwrap())
+        }
+
+        fn read_f64(&mut self) -> f64 {
+            let (bytes, rest) = self.wkb.split_at(8);
+            self.wkb = rest;
+            f64::from_le_bytes(bytes.try_into().unwrap())
+        }
+
+        fn read_header(&mut self, geometry_type: u32) -> usize {
+            assert_eq!(self.wkb[0], 1);
+            self.wkb = &self.wkb[1..];
+            assert_eq!(self.read_u32(), geometry_type);
+            self.read_u32() as usize
+        }
+
+        fn read_points(&mut self, count: usize) -> Vec<cgmath::Point2<f64>> {
+            (0..count)
+                .map(|_| cgmath::Point2::new(self.read_f64(), self.read_f64()))
+                .collect()
+        }
+
+        fn read_polygon(&mut self) -> Vec<cgmath::Point2<f64>> {
+            match self.read_header(WKB_POLYGON) {
+                0 => vec![],
+                1 => {
+                    let count = self.read_u32() as usize;
+                    self.read_points(count)
+                }
+                count => panic!("Unexpected {} rings", count),
+            }
+        }
+    }
+
+    #[test]
+    fn face_to_wkt_test() {
+        let voronoi = build_voronoi(&[
+            cgmath::Point2::new(0.25, 0.5),
+            cgmath::Point2::new(0.75, 0.5),
+        ]);
+        let faces = voronoi.get_face_indices();
+        let wkt = face_to_wkt(&voronoi, faces[0]);
+        assert!(wkt.starts_with("POLYGON (("));
+        assert!(wkt.ends_with("))"));
+        // The four corners of the left half of the square, with the first repeated, turning
+        // counter-clockwise with y up
+        let ring = get_counter_clockwise_ring(&voronoi, faces[0]);
+        assert_eq!(ring.len(), 5);
+        let expected = [
+            cgmath::Point2::new(0.0, 0.0),
+            cgmath::Point2::new(0.5, 0.0),
+            cgmath::Point2::new(0.5, 1.0),
+            cgmath::Point2::new(0.0, 1.0),
+        ];
+        let start = expected.iter().position(|&point| point == ring[0]).unwrap();
+        for (i, &point) in ring.iter().enumerate() {
+            assert_eq!(point, expected[(start + i) % 4]);
+        }
+        let coordinates: Vec<String> = ring
+            .iter()
+            .map(|point| format!("{} {}", point.x, point.y))
+            .collect();
+        assert_eq!(wkt, format!("POLYGON (({}))", coordinates.join(", ")));
+
+        assert_eq!(
+            edges_to_wkt(&voronoi).matches(['(', ')']).count(),
+            2 * (1 + 7)
+        );
+
+        let single = build_voronoi(&[cgmath::Point2::new(0.5, 0.5)]);
+        let face = single.get_face_indices()[0];
+        assert_eq!(face_to_wkt(&single, face), "POLYGON EMPTY");
+        assert_eq!(to_wkt_multipolygon(&single), "MULTIPOLYGON EMPTY");
+        assert_eq!(
+            to_wkt_geometry_collection(&single),
+            "GEOMETRYCOLLECTION (POLYGON EMPTY)"
+        );
+        assert_eq!(edges_to_wkt(&single), "MULTILINESTRING EMPTY");
+        assert_eq!(to_wkb_multipolygon(&single), [1, 6, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn to_wkt_test() {
+        let voronoi = build_voronoi(&random_points(80, 30));
+        let faces = voronoi.get_face_indices();
+        let polygons: Vec<String> = faces
+            .iter()
+            .map(|&face| face_to_wkt(&voronoi, face))
+            .collect();
+        assert_eq!(
+            to_wkt_geometry_collection(&voronoi),
+            format!("GEOMETRYCOLLECTION ({})", polygons.join(", "))
+        );
+        let polygons: Vec<&str> = polygons
+            .iter()
+            .map(|polygon| &polygon["POLYGON ".len()..])
+            .collect();
+        assert_eq!(
+            to_wkt_multipolygon(&voronoi),
+            format!("MULTIPOLYGON ({})", polygons.join(", "))
+        );
+
+        // Each edge between two faces is given once, in the direction of the first half edge
+        let edges = get_edges(&voronoi);
+        let half_edges = voronoi.get_edge_endpoints();
+        for &(start, end) in &half_edges {
+            let count = edges
+                .iter()
+                .filter(|&&edge| edge == (start, end) || edge == (end, start))
+                .count();
+            assert_eq!(count, 1);
+        }
+        let border_edges = half_edges
+            .iter()
+            .filter(|&&(start, end)| !half_edges.contains(&(end, start)))
+            .count();
+        assert_eq!(edges.len(), (half_edges.len() + border_edges) / 2);
+        let wkt = edges_to_wkt(&voronoi);
+        assert!(wkt.starts_with("MULTILINESTRING (("));
+        assert_eq!(wkt.matches("), (").count(), edges.len() - 1);
+    }
+
+    #[test]
+    fn to_wkb_test() {
+        let voronoi = build_voronoi(&random_points(81, 30));
+        let faces = voronoi.get_face_indices();
+        let rings: Vec<Vec<cgmath::Point2<f64>>> = faces
+            .iter()
+            .map(|&face| get_counter_clockwise_ring(&voronoi, face))
+            .collect();
+
+        let wkb = face_to_wkb(&voronoi, faces[3]);
+        let mut reader = WkbReader { wkb: &wkb };
+        assert_eq!(reader.read_polygon(), rings[3]);
+        assert!(reader.wkb.is_empty());
+
+        for (wkb, geometry_type) in &[
+            (to_wkb_multipolygon(&voronoi), WKB_MULTI_POLYGON),
+            (
+                to_wkb_geometry_collection(&voronoi),
+                WKB_GEOMETRY_COLLECTION,
+            ),
+        ] {
+            let mut reader = WkbReader { wkb };
+            assert_eq!(reader.read_header(*geometry_type), faces.len());
+            for ring in &rings {
+                assert_eq!(&reader.read_polygon(), ring);
+            }
+            assert!(reader.wkb.is_empty());
+        }
+
+        let wkb = edges_to_wkb(&voronoi);
+        let mut reader = WkbReader { wkb: &wkb };
+        let edges = get_edges(&voronoi);
+        assert_eq!(reader.read_header(WKB_MULTI_LINE_STRING), edges.len());
+        for &(start, end) in &edges {
+            assert_eq!(reader.read_header(WKB_LINE_STRING), 2);
+            assert_eq!(reader.read_points(2), vec![start, end]);
+        }
+        assert!(reader.wkb.is_empty());
+    }
+}